@@ -0,0 +1,136 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::spanned::Spanned;
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Error, Fields, Index, Type};
+
+/// Derives `clockwork::Configurable` for a struct whose fields mirror its `Config` type.
+/// The `Config` type is named with `#[configurable(config = ...)]`. `from` then builds each field
+/// from the config field of the same name, converting it with `Into` (so e.g. a `String` in the
+/// config may become an `Arc<String>`). Fields marked `#[configurable(skip)]` are not read from the
+/// config, they are set to their `Default` instead.
+///
+/// ```
+/// use clockwork::Configurable;
+/// use serde::Deserialize;
+/// use std::sync::Arc;
+///
+/// #[derive(Deserialize)]
+/// struct EchoerConfig {
+///     str: String,
+///     repeat_period_millis: u64,
+/// }
+///
+/// #[derive(Configurable)]
+/// #[configurable(config = EchoerConfig)]
+/// struct EchoerApp {
+///     str: Arc<String>,
+///     repeat_period_millis: u64,
+///     #[configurable(skip)]
+///     echoed: Vec<String>,
+/// }
+///
+/// let conf = EchoerConfig { str: "Hello".to_string(), repeat_period_millis: 500 };
+/// let app = <EchoerApp as Configurable>::from(conf);
+/// assert_eq!(*app.str, "Hello");
+/// assert_eq!(app.repeat_period_millis, 500);
+/// assert!(app.echoed.is_empty());
+/// ```
+#[proc_macro_derive(Configurable, attributes(configurable))]
+pub fn derive_configurable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let config = config_type(&input)?;
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => {
+            return Err(Error::new(
+                input.ident.span(),
+                "Configurable can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut inits = Vec::new();
+    for (i, field) in fields.iter().enumerate() {
+        let skip = is_skipped(&field.attrs)?;
+        let value = match (&field.ident, skip) {
+            (_, true) => quote!(::core::default::Default::default()),
+            (Some(ident), false) => quote!(::core::convert::Into::into(config.#ident)),
+            (None, false) => {
+                let index = Index::from(i);
+                quote!(::core::convert::Into::into(config.#index))
+            }
+        };
+        inits.push(value);
+    }
+
+    let body = match fields {
+        Fields::Named(_) => {
+            let idents = fields.iter().map(|field| &field.ident);
+            quote!(Self { #(#idents: #inits),* })
+        }
+        Fields::Unnamed(_) => quote!(Self(#(#inits),*)),
+        Fields::Unit => quote!(Self),
+    };
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::clockwork::Configurable for #ident #ty_generics #where_clause {
+            type Config = #config;
+
+            #[allow(unused_variables)]
+            fn from(config: Self::Config) -> Self {
+                #body
+            }
+        }
+    })
+}
+
+/// The type named by the struct's `#[configurable(config = ...)]` attribute
+fn config_type(input: &DeriveInput) -> syn::Result<Type> {
+    let mut config = None;
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|a| a.path().is_ident("configurable"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("config") {
+                config = Some(meta.value()?.parse::<Type>()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `config = <type>`"))
+            }
+        })?;
+    }
+
+    config.ok_or_else(|| {
+        Error::new(
+            input.span(),
+            "missing `#[configurable(config = <type>)]` attribute",
+        )
+    })
+}
+
+/// Whether a field is marked `#[configurable(skip)]`
+fn is_skipped(attrs: &[Attribute]) -> syn::Result<bool> {
+    let mut skip = false;
+    for attr in attrs.iter().filter(|a| a.path().is_ident("configurable")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                skip = true;
+                Ok(())
+            } else {
+                Err(meta.error("expected `skip`"))
+            }
+        })?;
+    }
+    Ok(skip)
+}
@@ -2,17 +2,16 @@ use clockwork::ClockworkApp;
 use clockwork::ClockworkHandle;
 use clockwork::{Configurable, Runnable};
 use serde::Deserialize;
+use std::error::Error;
 use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
-use tokio::macros::support::{Future, Pin};
-use tokio::time::{sleep, Duration, Instant};
+use tokio::time::Duration;
 use tracing::info;
 
 #[derive(Deserialize, Clone)]
 struct MultiEchoConfig {
     every_half_second: String,
     every_second: String,
-    run_duration_secs: usize,
 }
 
 struct MultiEchoApp {
@@ -40,7 +39,7 @@ impl Runnable for MultiEchoApp {
             let count = count.clone();
             let conf = self.conf.clone();
 
-            handle.schedule_repeating_task_at(
+            handle.schedule_repeating_task_from_start(
                 move || {
                     info!(
                         "{}: {}",
@@ -48,7 +47,7 @@ impl Runnable for MultiEchoApp {
                         count.fetch_add(1, Ordering::SeqCst)
                     )
                 },
-                Instant::now().checked_add(half_second).unwrap(),
+                half_second,
                 half_second,
             );
         }
@@ -57,7 +56,7 @@ impl Runnable for MultiEchoApp {
             let count = count.clone();
             let conf = Arc::clone(&self.conf);
 
-            handle.schedule_repeating_task_at(
+            handle.schedule_repeating_task_from_start(
                 move || {
                     info!(
                         "{}: {}",
@@ -65,7 +64,7 @@ impl Runnable for MultiEchoApp {
                         count.fetch_add(1, Ordering::SeqCst)
                     )
                 },
-                Instant::now().checked_add(one_second).unwrap(),
+                one_second,
                 one_second,
             );
         }
@@ -74,28 +73,22 @@ impl Runnable for MultiEchoApp {
     fn shutdown(&self) {
         info!("MultiEchoApp shut down!");
     }
-
-    fn run<'a>(&'a self, handle: ClockworkHandle) -> Pin<Box<dyn Future<Output = ()> + Send>> {
-        let duration = Duration::from_secs(self.conf.run_duration_secs as u64);
-        Box::pin(async move {
-            sleep(duration).await;
-            handle.stop()
-        })
-    }
 }
 
-fn main() {
+fn main() -> Result<(), Box<dyn Error>> {
     let conf_str = r#"
+        [clockwork]
+        run_for = '10s'
+
         [app]
         every_half_second = 'Hello'
         every_second = 'World'
-        run_duration_secs = 10
-        
+
         [logger]
         write_target = 'STDOUT'
     "#
     .to_string();
 
     let app: ClockworkApp<MultiEchoApp> = ClockworkApp::from_config_str(conf_str);
-    app.start();
+    app.start()
 }
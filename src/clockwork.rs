@@ -1,172 +1,4134 @@
 use super::ClockworkConfig;
-use crate::Runnable;
+use crate::clockwork_clock::{Clock, ClockInterval, SharedClock, TokioClock};
+use crate::clockwork_config::{DuplicateTaskPolicy, TaskLimitPolicy};
+use crate::clockwork_flags::FlagStore;
+use crate::clockwork_lifecycle::{Lifecycle, LifecycleState, TaskTracker};
+#[cfg(feature = "logging")]
+use crate::clockwork_logger::{LogLevelControl, LoggerLevel};
+#[cfg(feature = "metrics")]
+use crate::clockwork_metrics::{MetricsConfig, RuntimeMetrics};
+use crate::clockwork_priority::{PriorityQueue, TaskPriority};
+use crate::clockwork_state::StateFile;
+#[cfg(feature = "metrics")]
+use crate::clockwork_tasks::TaskStats;
+use crate::clockwork_tasks::{
+    panic_message, SpawnError, TaskHandle, TaskInfo, TaskKind, TaskRegistry,
+};
+use crate::clockwork_tasks::{TaskResult, TaskRun};
+use crate::clockwork_thread::{spawn_thread, ClockworkJoinHandle};
+use crate::clockwork_watchdog::StallWatchdog;
+use crate::{Runnable, WaitForConfig};
+use crate::{ShutdownReport, StopReason};
+#[cfg(feature = "tz")]
+use chrono::{DateTime, NaiveTime, Utc};
+#[cfg(feature = "tz")]
+use chrono_tz::Tz;
+use std::any::Any;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::error::Error;
 use std::future::Future;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::runtime::{Builder, Runtime};
-use tokio::time::{interval_at, sleep, Duration, Instant};
+use std::task::{Context, Poll};
+use std::time::SystemTime;
+use tokio::runtime::{Builder, Handle, Runtime, RuntimeFlavor};
+use tokio::sync::mpsc::{self, Sender};
+use tokio::sync::oneshot::{self, Receiver};
+use tokio::sync::{Notify, Semaphore};
+use tokio::task::LocalSet;
+use tokio::time::{timeout, Duration, Instant, MissedTickBehavior};
 
 type SharedRuntime = Arc<Runtime>;
-type SharedAtomicBool = Arc<AtomicBool>;
+type SharedLifecycle = Arc<LifecycleState>;
+type SharedTaskTracker = Arc<TaskTracker>;
+type SharedSemaphore = Arc<Semaphore>;
+type SharedPriorityQueue = Arc<PriorityQueue>;
+type SharedTaskRegistry = Arc<TaskRegistry>;
+type SharedStateFile = Arc<StateFile>;
+
+/// Builds a `!Send` future on the thread that runs it, see `ClockworkHandle::spawn_local_task`
+type LocalTask = Box<dyn FnOnce() -> Pin<Box<dyn Future<Output = ()>>> + Send>;
+
+/// A future that resolves once the `Clockwork` runtime has been stopped.
+/// Obtained from `ClockworkHandle::stop_signal` and passed to `Runnable::run`.
+pub struct StopSignal {
+    inner: Pin<Box<dyn Future<Output = ()> + Send>>,
+}
+
+impl StopSignal {
+    fn new(lifecycle: SharedLifecycle) -> Self {
+        Self {
+            inner: Box::pin(async move {
+                lifecycle
+                    .wait_until(|state| state == Lifecycle::Stopped)
+                    .await
+            }),
+        }
+    }
+}
+
+impl Future for StopSignal {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.as_mut().poll(cx)
+    }
+}
+
+/// Records when a scheduled task last fired, as an offset from the handle's `start_instant`, and
+/// counts its runs (and the runs that timed out)
+#[derive(Clone)]
+struct TickMarker {
+    clock: SharedClock,
+    start_instant: Instant,
+    last_tick: Arc<AtomicU64>,
+    task_runs: Arc<AtomicU64>,
+    task_ran: Arc<Notify>,
+    task_timeouts: Arc<AtomicU64>,
+}
+
+impl TickMarker {
+    /// Counts a run cancelled for overrunning its timeout, see
+    /// `schedule_repeating_task_with_timeout`
+    fn timed_out(&self) {
+        self.task_timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn mark(&self) {
+        self.task_runs.fetch_add(1, Ordering::Relaxed);
+        self.task_ran.notify_waiters();
+        self.touch();
+    }
+
+    /// Same as `mark`, without counting a run
+    fn touch(&self) {
+        let offset = self
+            .clock
+            .now()
+            .saturating_duration_since(self.start_instant);
+        self.last_tick
+            .fetch_max(offset.as_millis() as u64, Ordering::Relaxed);
+    }
+}
 
 #[derive(Clone)]
 pub struct ClockworkHandle {
-    rt: SharedRuntime,
-    stopped: SharedAtomicBool,
+    rt: Option<SharedRuntime>,
+    tokio_handle: Handle,
+    lifecycle: SharedLifecycle,
+    tasks: SharedTaskTracker,
+    clock: SharedClock,
+    start_instant: Instant,
+    last_tick: Arc<AtomicU64>,
+    task_runs: Arc<AtomicU64>,
+    /// Notified whenever `task_runs` goes up
+    task_ran: Arc<Notify>,
+    task_timeouts: Arc<AtomicU64>,
+    local_tasks: mpsc::UnboundedSender<LocalTask>,
+    local_tasks_rx: Arc<tokio::sync::Mutex<mpsc::UnboundedReceiver<LocalTask>>>,
+    task_permits: Option<SharedSemaphore>,
+    task_limit_policy: TaskLimitPolicy,
+    priority_queue: SharedPriorityQueue,
+    registry: SharedTaskRegistry,
+    duplicate_task_policy: DuplicateTaskPolicy,
+    state: Option<SharedStateFile>,
+    dry_run: bool,
+    late_tick_threshold: Option<Duration>,
+    /// Periods named repeating tasks are scheduled with instead of their own, see `set_task_period`
+    task_periods: Arc<std::sync::Mutex<HashMap<String, Duration>>>,
+    /// Group the named tasks scheduled through this handle are put in, see `in_group`
+    group: Option<String>,
+    flags: FlagStore,
+    #[cfg(feature = "logging")]
+    log_level: Arc<std::sync::Mutex<LogLevelControl>>,
 }
 
 impl ClockworkHandle {
     /// Creates a new ClockworkHandle from a SharedRuntime
     pub fn new(rt: SharedRuntime) -> Self {
+        let tokio_handle = rt.handle().clone();
+        Self::from_runtime_parts(Some(rt), tokio_handle)
+    }
+
+    /// Creates a new ClockworkHandle that spawns its tasks onto an existing tokio runtime.
+    /// No runtime is created, the runtime behind `tokio_handle` has to be driven by its owner.
+    pub fn from_tokio_handle(tokio_handle: Handle) -> Self {
+        Self::from_runtime_parts(None, tokio_handle)
+    }
+
+    fn from_runtime_parts(rt: Option<SharedRuntime>, tokio_handle: Handle) -> Self {
+        let (local_tasks, local_tasks_rx) = mpsc::unbounded_channel();
         Self {
             rt,
-            stopped: SharedAtomicBool::new(AtomicBool::new(false)),
+            tokio_handle,
+            lifecycle: SharedLifecycle::default(),
+            tasks: SharedTaskTracker::default(),
+            clock: Arc::new(TokioClock),
+            start_instant: Instant::now(),
+            last_tick: Arc::new(AtomicU64::new(0)),
+            task_runs: Arc::new(AtomicU64::new(0)),
+            task_ran: Arc::new(Notify::new()),
+            task_timeouts: Arc::new(AtomicU64::new(0)),
+            local_tasks,
+            local_tasks_rx: Arc::new(tokio::sync::Mutex::new(local_tasks_rx)),
+            task_permits: None,
+            task_limit_policy: TaskLimitPolicy::default(),
+            priority_queue: SharedPriorityQueue::default(),
+            registry: SharedTaskRegistry::new(TaskRegistry::new(16)),
+            duplicate_task_policy: DuplicateTaskPolicy::default(),
+            state: None,
+            dry_run: false,
+            late_tick_threshold: None,
+            task_periods: Default::default(),
+            group: None,
+            flags: FlagStore::new(),
+            #[cfg(feature = "logging")]
+            log_level: Default::default(),
+        }
+    }
+
+    /// Limits the number of futures spawned through `spawn_task` that may run at once.
+    /// Scheduled tasks do not count towards the limit, only the futures they spawn do.
+    pub fn with_task_limit(mut self, max_concurrent_tasks: usize, policy: TaskLimitPolicy) -> Self {
+        assert!(
+            max_concurrent_tasks > 0,
+            "`max_concurrent_tasks` must be non-zero."
+        );
+        self.task_permits = Some(SharedSemaphore::new(Semaphore::new(max_concurrent_tasks)));
+        self.task_limit_policy = policy;
+        self
+    }
+
+    /// Sets the number of recent runs kept per named task, `0` disables the history.
+    /// Has to be called before any named task is scheduled.
+    pub fn with_task_history_size(mut self, task_history_size: usize) -> Self {
+        self.registry = SharedTaskRegistry::new(TaskRegistry::new(task_history_size));
+        self
+    }
+
+    /// Sets what happens when a named task is scheduled while a task of the same name is still
+    /// scheduled: REPLACE (the default) unschedules the earlier task, REJECT keeps it and does not
+    /// schedule the new one. Either way, a warning is logged if the `logging` feature is enabled.
+    pub fn with_duplicate_task_policy(mut self, policy: DuplicateTaskPolicy) -> Self {
+        self.duplicate_task_policy = policy;
+        self
+    }
+
+    /// Persists the last-run time of named repeating tasks to the file at `path`, so that a task
+    /// that ran less than its period ago is not run again straight after a restart: its first run
+    /// is delayed until its period has passed since the last one. Useful for jobs that must run at
+    /// most once per period in environments where the process may crash and be restarted.
+    /// The file is loaded here, a missing file is treated as empty. Panics if it cannot be parsed.
+    pub fn with_persist_state(mut self, path: PathBuf) -> Self {
+        self.state = Some(SharedStateFile::new(StateFile::load(path)));
+        self
+    }
+
+    /// Replaces the handle's feature flags with `flags`, e.g. a store also updated from an
+    /// external source (see `flags`)
+    pub fn with_flags(mut self, flags: FlagStore) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Replaces the clock that scheduled tasks are timed against, e.g. with a `ManualClock` in
+    /// tests. Has to be called before any task is scheduled, `start_instant` is reset to the new
+    /// clock's current time.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.start_instant = clock.now();
+        self.clock = clock;
+        self
+    }
+
+    /// Enables/disables dry-run mode.
+    /// In dry-run mode, `schedule_*` calls only log what would have been scheduled (if the
+    /// `logging` feature is enabled) and no task is spawned.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Logs a warning (if the `logging` feature is enabled) whenever a repeating task runs more
+    /// than `threshold` after the tick it was due at, e.g. because the runtime is saturated or an
+    /// earlier run overran its period. Has to be called before any task is scheduled.
+    pub fn with_late_tick_threshold(mut self, threshold: Duration) -> Self {
+        self.late_tick_threshold = Some(threshold);
+        self
+    }
+
+    /// Schedules the named repeating tasks in `task_periods` with the period given there instead
+    /// of their own, e.g. to tune them from the config (see `set_task_period`)
+    pub fn with_task_periods(mut self, task_periods: HashMap<String, Duration>) -> Self {
+        self.task_periods = Arc::new(std::sync::Mutex::new(task_periods));
+        self
+    }
+
+    /// Logs a `schedule_*` call, returns whether the task should actually be spawned
+    #[cfg_attr(not(feature = "logging"), allow(unused_variables))]
+    fn should_schedule(
+        &self,
+        task: Option<&str>,
+        delay: Duration,
+        period: Option<Duration>,
+    ) -> bool {
+        if self.lifecycle() != Lifecycle::Running {
+            return false;
+        }
+
+        if self.dry_run {
+            #[cfg(feature = "logging")]
+            tracing::info!(
+                task,
+                ?delay,
+                ?period,
+                first_fire = ?(self.clock.system_now() + delay),
+                "Dry run, task not scheduled"
+            );
+            return false;
         }
+
+        #[cfg(feature = "logging")]
+        tracing::debug!(task, ?delay, ?period, "Scheduled task");
+        true
     }
 
     /// Schedules a task that repeats every interval starting from the specified time until
-    /// runtime is stopped
-    pub fn schedule_repeating_task_at<F>(&self, f: F, start: Instant, period: Duration)
-    where
+    /// runtime is stopped.
+    /// `missed_tick_behavior` decides what happens to ticks missed because a run (or the runtime)
+    /// was slow: `Burst` runs them back-to-back to catch up, `Delay` runs one straight away and
+    /// carries on a period after it, `Skip` runs one straight away and carries on from the next
+    /// tick still aligned with `start`.
+    /// A `start` in the past is treated as now: the task first runs straight away and carries on
+    /// every `period` from then on, the ticks between `start` and now are not caught up on.
+    /// If runtime is no longer running (draining or stopped), the task is not scheduled.
+    pub fn schedule_repeating_task_at<F>(
+        &self,
+        f: F,
+        start: Instant,
+        period: Duration,
+        missed_tick_behavior: MissedTickBehavior,
+    ) where
         F: 'static + Fn() + std::marker::Sync + std::marker::Send,
     {
-        let stopped = Arc::clone(&self.stopped);
-        self.spawn_task(async move {
-            let interval = interval_at(start, period);
-            tokio::pin!(interval);
+        let now = self.clock.now();
+        let start = start.max(now);
+        if !self.should_schedule(None, start - now, Some(period)) {
+            return;
+        }
 
-            while !stopped.load(Ordering::Relaxed) {
-                interval.as_mut().tick().await;
+        self.spawn_repeating(
+            None,
+            move || {
                 f();
+                true
+            },
+            start,
+            period,
+            missed_tick_behavior,
+        );
+    }
+
+    /// Runs `f` every tick until runtime is no longer running, or until `f` returns `false`.
+    /// `task` is the name the task is logged under, if it has one.
+    #[cfg_attr(not(feature = "logging"), allow(unused_variables))]
+    fn spawn_repeating<F>(
+        &self,
+        task: Option<String>,
+        f: F,
+        start: Instant,
+        period: Duration,
+        missed_tick_behavior: MissedTickBehavior,
+    ) where
+        F: 'static + Fn() -> bool + std::marker::Sync + std::marker::Send,
+    {
+        let lifecycle = Arc::clone(&self.lifecycle);
+        let clock = Arc::clone(&self.clock);
+        let ticks = self.tick_marker();
+        let late_tick_threshold = self.late_tick_threshold;
+        let mut interval = ClockInterval::new(Arc::clone(&clock), start, period)
+            .with_missed_tick_behavior(missed_tick_behavior);
+        self.spawn_tracked(async move {
+            loop {
+                tokio::select! {
+                    biased;
+                    _ = lifecycle.wait_until(|state| state != Lifecycle::Running) => break,
+                    due = interval.tick() => {
+                        // `stop` may have been called since the lifecycle was polled above
+                        if lifecycle.get() != Lifecycle::Running {
+                            break;
+                        }
+                        ticks.mark();
+
+                        #[cfg(feature = "logging")]
+                        if let Some(threshold) = late_tick_threshold {
+                            let lateness = clock.now().saturating_duration_since(due);
+                            if lateness > threshold {
+                                tracing::warn!(
+                                    task = task.as_deref(),
+                                    ?lateness,
+                                    ?period,
+                                    "Scheduled task ran late"
+                                );
+                            }
+                        }
+
+                        if !f() {
+                            break;
+                        }
+                    }
+                }
             }
         });
     }
 
-    /// Schedules a task that repeats every interval until runtime is stopped
+    /// Schedules a task that repeats every interval, first running once `delay_before_first` has
+    /// elapsed from now, until runtime is stopped.
+    /// Same as `schedule_repeating_task_at`, without having to compute the starting `Instant`.
+    /// If runtime is no longer running (draining or stopped), the task is not scheduled.
+    pub fn schedule_repeating_task_in<F>(
+        &self,
+        f: F,
+        delay_before_first: impl Into<Duration>,
+        period: impl Into<Duration>,
+    ) where
+        F: 'static + Fn() + std::marker::Sync + std::marker::Send,
+    {
+        let start = self.clock.now() + delay_before_first.into();
+        self.schedule_repeating_task_at(f, start, period.into(), MissedTickBehavior::Burst);
+    }
+
+    /// Schedules a task that repeats every interval until runtime is stopped.
+    /// If runtime is no longer running (draining or stopped), the task is not scheduled.
     pub fn schedule_repeating_task<F>(&self, f: F, period: Duration)
     where
         F: 'static + Fn() + std::marker::Sync + std::marker::Send,
     {
-        self.schedule_repeating_task_at(f, Instant::now(), period);
+        self.schedule_repeating_task_at(f, self.clock.now(), period, MissedTickBehavior::Burst);
     }
 
-    /// Schedules a task that runs once after duration elapsed.
-    /// If runtime is stopped before duration elapsed, the task may not be run.
-    pub fn schedule_oneof_task<F>(&self, f: F, duration: Duration)
+    /// Schedules a task that repeats every interval until runtime is stopped, skipping the ticks
+    /// on which the feature flag `flag` is off (see `flags`). The flag is checked on every tick,
+    /// so the task can be turned on and off with `set_flag` without being rescheduled.
+    /// If runtime is no longer running (draining or stopped), the task is not scheduled.
+    pub fn schedule_repeating_task_gated<F>(&self, flag: &str, f: F, period: Duration)
     where
         F: 'static + Fn() + std::marker::Sync + std::marker::Send,
     {
-        self.spawn_task(async move {
-            sleep(duration).await;
-            f();
-        });
+        let flags = self.flags.clone();
+        let flag = flag.to_string();
+        self.schedule_repeating_task(
+            move || {
+                if flags.is_enabled(&flag) {
+                    f();
+                }
+            },
+            period,
+        );
     }
 
-    /// Spawns a future
-    pub fn spawn_task<F>(&self, future: F)
+    /// Schedules a task that repeats every interval, on ticks measured from `start_instant`
+    /// rather than from now: the task runs at `start_instant + offset + n * period`, whenever it
+    /// is scheduled. Ticks already in the past are skipped, the first run is on the next one.
+    /// Combined with a `ManualClock` (or tokio's paused time), this makes a schedule reproducible.
+    /// If runtime is no longer running (draining or stopped), the task is not scheduled.
+    pub fn schedule_repeating_task_from_start<F>(&self, f: F, offset: Duration, period: Duration)
     where
-        F: Future + Send + 'static,
-        F::Output: Send + 'static,
+        F: 'static + Fn() + std::marker::Sync + std::marker::Send,
     {
-        self.rt.spawn(future);
+        let mut start = self.start_instant + offset;
+        let now = self.clock.now();
+        if start < now && !period.is_zero() {
+            let missed = (now - start).as_nanos().div_ceil(period.as_nanos());
+            // The skipped ticks add up to less than `now - start + period`, they only fail to fit
+            // for a period of centuries, in which case the next tick is taken as now
+            start = missed
+                .checked_mul(period.as_nanos())
+                .and_then(|skipped| u64::try_from(skipped).ok())
+                .and_then(|skipped| start.checked_add(Duration::from_nanos(skipped)))
+                .unwrap_or(now);
+        }
+        self.schedule_repeating_task_at(f, start, period, MissedTickBehavior::Burst);
     }
 
-    /// Raise the 'stopped' flag
-    pub fn stop(&self) {
-        self.stopped.store(true, Ordering::SeqCst);
-    }
-    pub fn stopped(&self) -> bool {
-        self.stopped.load(Ordering::Relaxed)
+    /// Schedules a task that repeats until runtime is stopped, waiting a fresh random duration
+    /// between `min` and `max` (inclusive) before every run, the first one included. Unlike a
+    /// period with jitter, runs do not stay aligned to any tick, which spreads maintenance or
+    /// gossip-style work out across tasks and instances. Each task draws from its own RNG.
+    /// If runtime is no longer running (draining or stopped), the task is not scheduled.
+    /// Panics if `min` is greater than `max`.
+    pub fn schedule_repeating_task_random<F>(&self, f: F, min: Duration, max: Duration)
+    where
+        F: 'static + Fn() + std::marker::Sync + std::marker::Send,
+    {
+        assert!(min <= max, "Random interval has min greater than max!");
+        if !self.should_schedule(None, min, None) {
+            return;
+        }
+
+        let nanos = |duration: Duration| u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        let range = nanos(min)..=nanos(max);
+        let mut rng = fastrand::Rng::new();
+        let lifecycle = Arc::clone(&self.lifecycle);
+        let clock = Arc::clone(&self.clock);
+        let ticks = self.tick_marker();
+        self.spawn_tracked(async move {
+            loop {
+                let delay = Duration::from_nanos(rng.u64(range.clone()));
+                tokio::select! {
+                    biased;
+                    _ = lifecycle.wait_until(|state| state != Lifecycle::Running) => break,
+                    _ = clock.sleep(delay) => {}
+                }
+
+                // `stop` may have been called since the lifecycle was polled above
+                if lifecycle.get() != Lifecycle::Running {
+                    break;
+                }
+                ticks.mark();
+                f();
+            }
+        });
     }
 
-    /// Runs a future on the runtime, blocking until completion
-    fn run<F: Future>(&self, f: F) {
-        self.rt.block_on(f);
+    /// Schedules every `(period, f)` job as a repeating task (see `schedule_repeating_task`), e.g.
+    /// to register a whole schedule from `Runnable::setup`.
+    /// Returns a `TaskHandle` per job, in the same order, to cancel it later with. If runtime is
+    /// no longer running (draining or stopped), no job is scheduled and every handle comes back
+    /// already cancelled.
+    pub fn schedule_batch(
+        &self,
+        jobs: Vec<(Duration, Box<dyn Fn() + Send + Sync>)>,
+    ) -> Vec<TaskHandle> {
+        jobs.into_iter()
+            .map(|(period, f)| {
+                let task = TaskHandle::default();
+                if !self.should_schedule(None, Duration::ZERO, Some(period)) {
+                    task.cancel();
+                    return task;
+                }
+
+                let cancelled = task.clone();
+                self.spawn_repeating(
+                    None,
+                    move || {
+                        if cancelled.is_cancelled() {
+                            return false;
+                        }
+                        f();
+                        true
+                    },
+                    self.clock.now(),
+                    period,
+                    MissedTickBehavior::Burst,
+                );
+                task
+            })
+            .collect()
     }
-}
 
-pub struct Clockwork {
-    handle: ClockworkHandle,
-}
+    /// Schedules an async task that repeats every interval until runtime is stopped.
+    /// Each invocation is wrapped in `tokio::time::timeout`: one that does not complete within
+    /// `timeout_after` is cancelled, counted in `task_timeouts` (and logged if the `logging`
+    /// feature is enabled), and the loop then carries on with the next tick.
+    /// The timeout is measured on the runtime's timer, not on the handle's `Clock`.
+    /// If runtime is no longer running (draining or stopped), the task is not scheduled.
+    pub fn schedule_repeating_task_with_timeout<F, Fut>(
+        &self,
+        f: F,
+        period: Duration,
+        timeout_after: Duration,
+    ) where
+        F: 'static + Fn() -> Fut + std::marker::Sync + std::marker::Send,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        if !self.should_schedule(None, Duration::ZERO, Some(period)) {
+            return;
+        }
 
-impl Clockwork {
-    /// Creates a new Clockwork instance from a ClockworkHandle
-    pub fn new(handle: ClockworkHandle) -> Self {
-        Self { handle }
+        let lifecycle = Arc::clone(&self.lifecycle);
+        let clock = Arc::clone(&self.clock);
+        let ticks = self.tick_marker();
+        let mut interval = ClockInterval::new(Arc::clone(&clock), clock.now(), period);
+        self.spawn_tracked(async move {
+            loop {
+                tokio::select! {
+                    biased;
+                    _ = lifecycle.wait_until(|state| state != Lifecycle::Running) => break,
+                    _ = interval.tick() => ticks.mark(),
+                }
+
+                if timeout(timeout_after, f()).await.is_err() {
+                    ticks.timed_out();
+                    #[cfg(feature = "logging")]
+                    tracing::warn!(?timeout_after, "Scheduled task timed out");
+                }
+            }
+        });
     }
 
-    /// Schedules a task that repeats every interval starting from the specified time until
-    /// runtime is stopped
-    pub fn schedule_repeating_task_at<F>(&self, f: F, start: Instant, period: Duration)
+    /// Schedules a task that runs once after duration elapsed.
+    /// The duration counts from this call. A zero duration never runs the task inline, it runs on
+    /// the runtime as soon as the runtime gets to it.
+    /// If runtime is stopped before duration elapsed, the task is cancelled and not run. If it is
+    /// stopped while the task is running, the run is not interrupted and completes.
+    /// If runtime is no longer running (draining or stopped), the task is not scheduled.
+    /// Returns a `TaskHandle` to defuse the task with: cancelled before duration elapsed, the task
+    /// never runs. It comes back already cancelled if the task was not scheduled.
+    pub fn schedule_oneof_task<F>(&self, f: F, duration: Duration) -> TaskHandle
     where
         F: 'static + Fn() + std::marker::Sync + std::marker::Send,
     {
-        self.handle().schedule_repeating_task_at(f, start, period)
+        if !self.should_schedule(None, duration, None) {
+            let task = TaskHandle::default();
+            task.cancel();
+            return task;
+        }
+
+        self.spawn_oneof(f, duration)
     }
 
-    /// Schedules a task that repeats every interval until runtime is stopped
-    pub fn schedule_repeating_task<F>(&self, f: F, period: Duration)
+    fn spawn_oneof<F>(&self, f: F, duration: Duration) -> TaskHandle
+    where
+        F: 'static + FnOnce() + std::marker::Send,
+    {
+        let lifecycle = Arc::clone(&self.lifecycle);
+        let clock = Arc::clone(&self.clock);
+        let ticks = self.tick_marker();
+        let stop = self.stop_signal();
+        let task = TaskHandle::default();
+        let cancelled = task.clone();
+        // Taken now rather than once the task is first polled, which may be a while later
+        let deadline = clock.now() + duration;
+        self.spawn_tracked(async move {
+            // Once the sleep is over `f` runs to completion, a `stop` (or `cancel`) during the run
+            // is only observed after it
+            tokio::select! {
+                biased;
+                _ = stop => {}
+                _ = cancelled.cancelled() => {}
+                _ = clock.sleep_until(deadline) => {
+                    if lifecycle.get() == Lifecycle::Stopped {
+                        return;
+                    }
+                    ticks.mark();
+                    f();
+                }
+            }
+        });
+        task
+    }
+
+    /// Schedules a task that runs once after duration elapsed, and sends its result through the
+    /// returned receiver. Runs the same as `schedule_oneof_task`, the duration counts from this
+    /// call.
+    /// If the task does not run, either because runtime is no longer running (draining or
+    /// stopped) or because it is stopped before duration elapsed, the receiver resolves to an error.
+    pub fn schedule_oneof_task_with_result<F, R>(&self, f: F, duration: Duration) -> Receiver<R>
+    where
+        F: 'static + FnOnce() -> R + std::marker::Send,
+        R: 'static + std::marker::Send,
+    {
+        let (result_tx, result_rx) = oneshot::channel();
+        if !self.should_schedule(None, duration, None) {
+            return result_rx;
+        }
+
+        self.spawn_oneof(
+            move || {
+                let _ = result_tx.send(f());
+            },
+            duration,
+        );
+        result_rx
+    }
+
+    /// Schedules a task that runs once at the given wall-clock time.
+    /// Times in the past run as soon as possible.
+    /// If runtime is stopped before then, the task is cancelled and not run. If it is stopped
+    /// while the task is running, the run is not interrupted and completes.
+    /// If runtime is no longer running (draining or stopped), the task is not scheduled.
+    /// Returns a `TaskHandle` to defuse the task with, see `schedule_oneof_task`.
+    pub fn schedule_oneof_task_at_systemtime<F>(&self, f: F, when: SystemTime) -> TaskHandle
     where
         F: 'static + Fn() + std::marker::Sync + std::marker::Send,
     {
-        self.handle().schedule_repeating_task(f, period)
+        let delay = when
+            .duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO);
+        self.schedule_oneof_task(f, delay)
     }
 
-    /// Schedules a task that runs once after duration elapsed.
-    /// If runtime is stopped before duration elapsed, the task may not be run.
-    pub fn schedule_oneof_task<F>(&self, f: F, duration: Duration)
+    /// Schedules a task that runs every day at the given local time in timezone `tz`, until
+    /// runtime is stopped. The wall clock is the handle's `Clock` (see `Clock::system_now`), so a
+    /// `ManualClock` drives the task too. The next fire time is recomputed from it after each run,
+    /// so DST transitions are respected: on a fall-back day, a time that occurs twice runs only
+    /// once (at its first occurrence); on a spring-forward day, a time that is skipped runs as
+    /// soon as the clock jumps past it.
+    /// If runtime is no longer running (draining or stopped), the task is not scheduled.
+    #[cfg(feature = "tz")]
+    pub fn schedule_daily_at<F>(&self, f: F, time: NaiveTime, tz: Tz)
     where
         F: 'static + Fn() + std::marker::Sync + std::marker::Send,
     {
-        self.handle().schedule_oneof_task(f, duration)
+        let now = DateTime::<Utc>::from(self.clock.system_now());
+        let first_fire = crate::clockwork_calendar::next_daily_fire(now, time, tz);
+        let delay = (first_fire - now).to_std().unwrap_or(Duration::ZERO);
+        if !self.should_schedule(None, delay, Some(Duration::from_secs(24 * 60 * 60))) {
+            return;
+        }
+
+        let lifecycle = Arc::clone(&self.lifecycle);
+        let clock = Arc::clone(&self.clock);
+        let ticks = self.tick_marker();
+        self.spawn_tracked(async move {
+            let mut fire = first_fire;
+            loop {
+                let now = DateTime::<Utc>::from(clock.system_now());
+                let delay = (fire - now).to_std().unwrap_or(Duration::ZERO);
+                tokio::select! {
+                    biased;
+                    _ = lifecycle.wait_until(|state| state != Lifecycle::Running) => break,
+                    _ = clock.sleep(delay) => {
+                        ticks.mark();
+                        f();
+                    }
+                }
+
+                let now = DateTime::<Utc>::from(clock.system_now());
+                fire = crate::clockwork_calendar::next_daily_fire(now.max(fire), time, tz);
+            }
+        });
+    }
+
+    /// Schedules a named task that repeats every interval until runtime is stopped.
+    /// `f` may return `()` or a `Result`; each run is recorded in the task's history (see
+    /// `task_history`). A panicking run is recorded as such and does not end the task.
+    /// If runtime is no longer running (draining or stopped), the task is not scheduled.
+    /// If a task of the same name is still scheduled, it is replaced or this task is rejected
+    /// according to the handle's `DuplicateTaskPolicy` (see `with_duplicate_task_policy`).
+    /// If state is persisted (see `with_persist_state`) and the task last ran less than `period`
+    /// ago, the first run is delayed until `period` has passed since that run.
+    /// Returns `false` if the task was not scheduled.
+    pub fn schedule_named_repeating_task<F, R>(&self, name: &str, f: F, period: Duration) -> bool
+    where
+        F: 'static + Fn() -> R + std::marker::Sync + std::marker::Send,
+        R: TaskResult,
+    {
+        let period = self
+            .task_periods
+            .lock()
+            .unwrap()
+            .get(name)
+            .copied()
+            .unwrap_or(period);
+        let delay = match &self.state {
+            Some(state) => state
+                .last_run(name)
+                .and_then(|last_run| SystemTime::now().duration_since(last_run).ok())
+                .map_or(Duration::ZERO, |elapsed| period.saturating_sub(elapsed)),
+            None => Duration::ZERO,
+        };
+        if !self.should_schedule(Some(name), delay, Some(period)) {
+            return false;
+        }
+
+        let start = self.clock.now() + delay;
+        let policy = self.duplicate_task_policy;
+        match self.registry.register(
+            name,
+            self.group.as_deref(),
+            f,
+            TaskKind::Repeating,
+            Some(period),
+            start,
+            policy,
+        ) {
+            Some(generation) => {
+                self.spawn_named_repeating(name.to_string(), generation, start, period);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Runs the named task `name` every tick, until its `generation` is replaced
+    fn spawn_named_repeating(
+        &self,
+        name: String,
+        generation: u64,
+        start: Instant,
+        period: Duration,
+    ) {
+        let registry = Arc::clone(&self.registry);
+        let state = self.state.clone();
+        // Recorded before the run starts, a crash mid-run does not lead to a second run
+        let record = move |name: &str| {
+            if let Some(state) = &state {
+                state.record(name, SystemTime::now());
+            }
+        };
+        self.spawn_repeating(
+            Some(name.clone()),
+            move || registry.run_with(&name, generation, || record(&name)),
+            start,
+            period,
+            MissedTickBehavior::Burst,
+        );
+    }
+
+    /// Schedules a named task that runs once after duration elapsed.
+    /// The run is recorded in the task's history (see `task_history`).
+    /// If runtime is stopped before duration elapsed, the task is cancelled and not run. If it is
+    /// stopped while the task is running, the run is not interrupted and completes.
+    /// If runtime is no longer running (draining or stopped), the task is not scheduled.
+    /// If a task of the same name is still scheduled, it is replaced or this task is rejected
+    /// according to the handle's `DuplicateTaskPolicy` (see `with_duplicate_task_policy`).
+    /// Returns `false` if the task was not scheduled.
+    pub fn schedule_named_oneof_task<F, R>(&self, name: &str, f: F, duration: Duration) -> bool
+    where
+        F: 'static + Fn() -> R + std::marker::Sync + std::marker::Send,
+        R: TaskResult,
+    {
+        if !self.should_schedule(Some(name), duration, None) {
+            return false;
+        }
+
+        let name = name.to_string();
+        let first_fire = self.clock.now() + duration;
+        let registry = Arc::clone(&self.registry);
+        let policy = self.duplicate_task_policy;
+        let group = self.group.as_deref();
+        match registry.register(&name, group, f, TaskKind::OneShot, None, first_fire, policy) {
+            Some(generation) => {
+                self.spawn_oneof(
+                    move || {
+                        registry.run(&name, generation);
+                    },
+                    duration,
+                );
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Creates a bounded channel for tasks to pass messages through, holding up to `capacity`
+    /// messages. Typically a producer task sends into it (`try_send` from a synchronous task) and
+    /// the receiver is handed to `schedule_consumer_task`.
+    pub fn channel<T>(&self, capacity: usize) -> (Sender<T>, mpsc::Receiver<T>) {
+        mpsc::channel(capacity)
     }
 
-    /// Spawns a future
-    pub fn spawn_task<F>(&self, future: F)
+    /// Schedules a task that receives from `receiver` and passes every message to `handler`, one at
+    /// a time, until runtime is stopped or every sender has been dropped.
+    /// Unlike repeating tasks, the consumer keeps running while draining, so messages already sent
+    /// are still handled before `shutdown_graceful` completes (provided the senders are dropped).
+    /// If runtime is no longer running (draining or stopped), the task is not scheduled.
+    pub fn schedule_consumer_task<T, F>(&self, mut receiver: mpsc::Receiver<T>, handler: F)
+    where
+        T: 'static + std::marker::Send,
+        F: 'static + Fn(T) + std::marker::Sync + std::marker::Send,
+    {
+        if !self.should_schedule(None, Duration::ZERO, None) {
+            return;
+        }
+
+        let stop = self.stop_signal();
+        let ticks = self.tick_marker();
+        self.spawn_tracked(async move {
+            tokio::pin!(stop);
+            loop {
+                tokio::select! {
+                    biased;
+                    _ = &mut stop => break,
+                    message = receiver.recv() => match message {
+                        Some(message) => {
+                            ticks.mark();
+                            handler(message);
+                        }
+                        None => break,
+                    },
+                }
+            }
+        });
+    }
+
+    /// Spawns a future.
+    /// Futures spawned while draining are awaited by `shutdown_graceful` like any other in-flight
+    /// task.
+    /// Counts towards the concurrent task limit (see `with_task_limit`): under the WAIT policy the
+    /// future is spawned but only starts once a permit is free, under REJECT it is not spawned.
+    /// Returns an error if the spawn was rejected, either because the runtime is stopped or due
+    /// to the concurrent task limit.
+    pub fn spawn_task<F>(&self, future: F) -> Result<(), SpawnError>
     where
         F: Future + Send + 'static,
         F::Output: Send + 'static,
     {
-        self.handle().spawn_task(future);
+        if self.stopped() {
+            return Err(SpawnError::Stopped);
+        }
+
+        let spawned = match &self.task_permits {
+            None => self.spawn_tracked(future),
+            Some(permits) => match self.task_limit_policy {
+                TaskLimitPolicy::WAIT => {
+                    let permits = Arc::clone(permits);
+                    self.spawn_tracked(async move {
+                        let _permit = permits.acquire_owned().await;
+                        future.await;
+                    })
+                }
+                TaskLimitPolicy::REJECT => {
+                    let permit = Arc::clone(permits)
+                        .try_acquire_owned()
+                        .map_err(|_| SpawnError::TaskLimit)?;
+                    self.spawn_tracked(async move {
+                        let _permit = permit;
+                        future.await;
+                    })
+                }
+            },
+        };
+
+        if spawned {
+            Ok(())
+        } else {
+            Err(SpawnError::Stopped)
+        }
     }
 
-    /// Runs a future on the runtime, blocking until completion
-    pub fn run<F: Runnable>(&self, f: &F) {
-        self.handle.run(f.run(self.handle()));
+    /// Spawns a future tracked for `shutdown_graceful`, outside of the concurrent task limit, as
+    /// the scheduled tasks' loops are. Returns `false` if the runtime is stopped.
+    fn spawn_tracked<F>(&self, future: F) -> bool
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        if self.stopped() {
+            return false;
+        }
+
+        let guard = self.tasks.track();
+        self.tokio_handle.spawn(async move {
+            let _guard = guard;
+            future.await
+        });
+        true
     }
 
-    /// Returns a clone of the ClockworkHandle (allows scheduling from different thread, etc)
-    pub fn handle(&self) -> ClockworkHandle {
-        self.handle.clone()
+    /// Spawns a future that is dropped (cancelled at its next `.await`) once runtime is stopped,
+    /// for background loops that should end with the runtime without racing `stop_signal`
+    /// themselves. Its output, if it completes first, is discarded.
+    /// Returns an error if the spawn was rejected, see `spawn_task`.
+    pub fn spawn_until_stopped<F>(&self, future: F) -> Result<(), SpawnError>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let stop = self.stop_signal();
+        self.spawn_task(async move {
+            tokio::select! {
+                biased;
+                _ = stop => {}
+                _ = future => {}
+            }
+        })
     }
 
-    /// Raise the 'stopped' flag, triggers shutdown sequence on the next spin 'tick'
-    pub fn stop(&mut self) {
-        self.handle.stop();
+    /// Spawns a future that does not have to be `Send` (e.g. one holding an `Rc` or a `RefCell`).
+    /// As such a future cannot be moved to the runtime's thread, `f` is sent there instead and
+    /// builds the future in place; it is then run on a `LocalSet` driven by `Clockwork::run`.
+    /// Local futures only run while `Clockwork::run` is running, those spawned before it starts
+    /// wait for it, those still pending once it returns are dropped. They do not count towards the
+    /// concurrent task limit, but are awaited by `shutdown_graceful` like any other task.
+    /// Returns `false` if the runtime is stopped.
+    pub fn spawn_local_task<F, Fut>(&self, f: F) -> bool
+    where
+        F: 'static + FnOnce() -> Fut + std::marker::Send,
+        Fut: Future + 'static,
+    {
+        if self.stopped() {
+            return false;
+        }
+
+        let guard = self.tasks.track();
+        let task: LocalTask = Box::new(move || {
+            Box::pin(async move {
+                let _guard = guard;
+                f().await;
+            })
+        });
+        self.local_tasks.send(task).is_ok()
     }
-}
 
-impl From<ClockworkConfig> for Clockwork {
-    fn from(conf: ClockworkConfig) -> Self {
-        let mut builder = Builder::new_current_thread();
-        if conf.runtime.enable_io {
-            builder.enable_io();
+    /// Spawns the local tasks sent through `spawn_local_task` onto the current `LocalSet`, until
+    /// runtime is stopped
+    async fn drive_local_tasks(&self) {
+        let mut local_tasks = self.local_tasks_rx.lock().await;
+        let mut stop = self.stop_signal();
+        loop {
+            tokio::select! {
+                biased;
+                _ = &mut stop => break,
+                task = local_tasks.recv() => match task {
+                    Some(task) => {
+                        tokio::task::spawn_local(task());
+                    }
+                    None => break,
+                },
+            }
         }
+    }
 
-        if conf.runtime.enable_time {
-            builder.enable_time();
+    /// Queues a future to be run according to its priority.
+    /// Prioritized futures are run one at a time by a dispatcher task, which always picks the
+    /// highest priority future pending next; futures of equal priority run in submission order.
+    /// Keep prioritized futures short, a running future is not preempted by a higher priority one.
+    /// Pending futures are dropped once the runtime is stopped.
+    pub fn spawn_task_with_priority<F>(&self, future: F, priority: TaskPriority)
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let task = Box::pin(async move {
+            future.await;
+        });
+
+        if self.priority_queue.push(priority, task) {
+            let queue = Arc::clone(&self.priority_queue);
+            let stop = self.stop_signal();
+            self.tokio_handle
+                .spawn(async move { queue.dispatch(stop).await });
         }
+    }
+
+    /// Raise the 'stopped' flag, waking up any pending `StopSignal`.
+    /// Everything done before `stop` is visible to any thread that then observes `stopped`.
+    /// Once `stop` has returned, scheduling through any clone of the handle, from any thread, is a
+    /// no-op, and no scheduled task starts another run (a run already in progress completes).
+    /// A task scheduled concurrently with `stop` may be accepted, but it only runs if the
+    /// runtime is still running when its run is due.
+    /// On a child handle (see `child`), only the child's scope is stopped.
+    /// See `drain_and_stop` for a soft stop that lets tasks already spawned complete.
+    pub fn stop(&self) {
+        self.try_stop();
+    }
 
-        builder.max_blocking_threads(conf.runtime.max_threads);
+    /// Same as `stop`, but also tells whether this call is the one that stopped the runtime.
+    /// Returns `true` for exactly one caller, however many race to stop it, from however many
+    /// threads; `false` if it was already stopped. The winner can then run one-time shutdown
+    /// logic without a lock of its own (the `register_on_stop` callbacks have run by then).
+    pub fn try_stop(&self) -> bool {
+        self.stop_with(StopReason::Requested, None)
+    }
 
-        Self::new(ClockworkHandle::new(Arc::new(
-            builder.build().expect("Failed to Build Runtime"),
-        )))
+    /// Same as `try_stop`, recording `reason` (and `drained`) as why the runtime stopped
+    fn stop_with(&self, reason: StopReason, drained: Option<bool>) -> bool {
+        self.lifecycle.stop(reason, drained)
     }
-}
 
-impl Default for Clockwork {
-    fn default() -> Self {
-        Self::from(ClockworkConfig::default())
+    /// Tears down after `f`'s `setup` or `run` panicked with `payload`: stops the runtime with
+    /// `StopReason::Panicked`, logs the payload and calls `Runnable::shutdown`, best effort (a
+    /// panic in `shutdown` itself is swallowed, the original one is what gets reported)
+    pub(crate) fn runnable_panicked<F: Runnable>(&self, f: &F, payload: &(dyn Any + Send)) {
+        self.stop_with(StopReason::Panicked, None);
+        let _message = panic_message(payload);
+        #[cfg(feature = "logging")]
+        tracing::error!(panic = %_message, "Runnable panicked, shutting down");
+        let _ = catch_unwind(AssertUnwindSafe(|| f.shutdown()));
+    }
+
+    /// Registers `f` to be called when the runtime is stopped, before `Runnable::shutdown`, e.g.
+    /// so that each module can register its own teardown. Callbacks are called in registration
+    /// order, on the thread that calls `stop` (or that completes `shutdown_graceful` or
+    /// `drain_and_stop`), and only once however many times `stop` is called.
+    /// If the runtime is already stopped, `f` is called straight away.
+    pub fn register_on_stop<F>(&self, f: F)
+    where
+        F: 'static + FnOnce() + std::marker::Send,
+    {
+        self.lifecycle.register_on_stop(Box::new(f));
+    }
+
+    /// Whether the 'stopped' flag has been raised
+    pub fn stopped(&self) -> bool {
+        self.lifecycle() == Lifecycle::Stopped
+    }
+
+    /// The current lifecycle state of the runtime
+    pub fn lifecycle(&self) -> Lifecycle {
+        self.lifecycle.get()
+    }
+
+    /// Creates a handle on a child scope of this one, e.g. to group one feature's tasks so that
+    /// they can be stopped without tearing down the whole runtime.
+    /// `stop` on the child (or on any of its clones) stops the tasks scheduled through it and
+    /// through its own children, while the parent and its other tasks carry on. The child follows
+    /// its parent, it drains and stops along with it (and comes back stopped if the parent already
+    /// is), so `stopped` is true once either of them is stopped.
+    /// The child shares its parent's runtime, clock, named tasks and task limit. Its
+    /// `register_on_stop` callbacks run when it is stopped, before its parent's. Its tasks count as
+    /// in flight for the parent too, `shutdown_graceful` on the child only waits for the child's.
+    pub fn child(&self) -> ClockworkHandle {
+        Self {
+            lifecycle: self.lifecycle.child(),
+            tasks: self.tasks.child(),
+            priority_queue: SharedPriorityQueue::default(),
+            ..self.clone()
+        }
+    }
+
+    /// Creates a handle that puts the named tasks scheduled through it in `group`, so that they
+    /// can be paused, resumed or cancelled together (see `pause_group`, `resume_group` and
+    /// `cancel_group`), e.g. every task of one feature. Anonymous tasks are not grouped.
+    /// Apart from that, the returned handle is the same as this one, it shares its scope.
+    pub fn in_group(&self, group: &str) -> ClockworkHandle {
+        Self {
+            group: Some(group.to_string()),
+            ..self.clone()
+        }
+    }
+
+    /// Shuts down gracefully: moves the runtime to `Lifecycle::Draining`, so that no new tasks are
+    /// scheduled and repeating tasks exit, then waits up to `timeout_after` for the tasks still in
+    /// flight (including any spawned while draining) to complete, before stopping the runtime.
+    /// Returns `true` if every task completed in time.
+    /// Await this from `Runnable::run` rather than from a spawned task, as the spawned task would
+    /// itself count as in flight.
+    pub async fn shutdown_graceful(&self, timeout_after: Duration) -> bool {
+        self.lifecycle.advance(Lifecycle::Draining);
+        let drained = timeout(timeout_after, self.tasks.wait_idle()).await.is_ok();
+        self.stop_with(StopReason::Drained, Some(drained));
+        drained
+    }
+
+    /// Soft stop: moves the runtime to `Lifecycle::Draining` and returns straight away, the runtime
+    /// then stops by itself once no task is in flight any more.
+    /// Unlike `stop`, which cancels one-shot tasks still waiting to fire, every task already
+    /// spawned gets to complete: one-shot tasks still fire when due, and consumer tasks keep
+    /// handling messages until their senders are dropped. As with `stop`, nothing new is scheduled
+    /// and repeating tasks exit after their current tick. The `StopSignal` (and so the default
+    /// `Runnable::run`) resolves once the runtime is stopped.
+    /// Unlike `shutdown_graceful`, there is no timeout: a task that never completes keeps the
+    /// runtime draining until `stop` is called.
+    pub fn drain_and_stop(&self) {
+        if !self.lifecycle.advance(Lifecycle::Draining) {
+            return;
+        }
+
+        // Not spawned as a tracked task, as it would be waiting for itself
+        let handle = self.clone();
+        self.tokio_handle.spawn(async move {
+            handle.tasks.wait_idle().await;
+            handle.stop_with(StopReason::Drained, Some(true));
+        });
+    }
+
+    /// Returns a future that resolves once the runtime is stopped
+    pub fn stop_signal(&self) -> StopSignal {
+        StopSignal::new(Arc::clone(&self.lifecycle))
+    }
+
+    /// Replaces the body of the named task `name` with `f`, keeping its schedule (and tick
+    /// alignment) as is. Returns `false` if no task of that name was scheduled.
+    /// The swap is atomic with respect to the task's runs: a run already in progress completes
+    /// with the old body, every tick after `update_task` returns runs `f`.
+    /// May be called from any thread.
+    pub fn update_task<F, R>(&self, name: &str, f: F) -> bool
+    where
+        F: 'static + Fn() -> R + std::marker::Sync + std::marker::Send,
+        R: TaskResult,
+    {
+        self.registry.replace(name, f)
+    }
+
+    /// Pauses the named task `name`: it stays scheduled, but its runs are skipped until
+    /// `resume_task` is called. A one-shot task that comes due while paused is dropped.
+    /// Returns `false` if no task of that name was scheduled.
+    pub fn pause_task(&self, name: &str) -> bool {
+        self.registry.set_paused(name, true)
+    }
+
+    /// Resumes the named task `name` after `pause_task`, from its next tick on.
+    /// Returns `false` if no task of that name was scheduled.
+    pub fn resume_task(&self, name: &str) -> bool {
+        self.registry.set_paused(name, false)
+    }
+
+    /// Pauses every named task of `group` still scheduled (see `in_group` and `pause_task`).
+    /// Tasks scheduled into the group afterwards are not paused.
+    /// Returns how many tasks were paused.
+    pub fn pause_group(&self, group: &str) -> usize {
+        self.registry.set_group_paused(group, true)
+    }
+
+    /// Resumes every named task of `group` still scheduled after `pause_group`, from their next
+    /// tick on. Returns how many tasks were resumed.
+    pub fn resume_group(&self, group: &str) -> usize {
+        self.registry.set_group_paused(group, false)
+    }
+
+    /// Unschedules every named task of `group` still scheduled (see `in_group`): one-shot tasks
+    /// that have not fired yet never run, repeating tasks do not run again. A run already in
+    /// progress completes. The tasks' history is kept and their names are free to be scheduled
+    /// again. Returns how many tasks were cancelled.
+    pub fn cancel_group(&self, group: &str) -> usize {
+        self.registry.cancel_group(group)
+    }
+
+    /// Runs the named repeating task `name` every `fast_period` for `duration`, then goes back to
+    /// its original period, e.g. to poll faster during an incident. The next run is brought
+    /// forward to at most `fast_period` from now; once the boost ends, the next run comes a full
+    /// original period after the last boosted one.
+    /// Boosting an already boosted task replaces the earlier boost. Replacing the task (by
+    /// scheduling another of the same name) ends the boost.
+    /// Returns `false` if no repeating task of that name was scheduled, or if runtime is no longer
+    /// running (draining or stopped).
+    pub fn boost_task(&self, name: &str, fast_period: Duration, duration: Duration) -> bool {
+        if self.lifecycle() != Lifecycle::Running {
+            return false;
+        }
+
+        let (generation, start) = match self.registry.boost(name, fast_period, self.clock.now()) {
+            Some(boosted) => boosted,
+            None => return false,
+        };
+        self.spawn_named_repeating(name.to_string(), generation, start, fast_period);
+
+        // Not spawned as a tracked task, so that it does not hold up `shutdown_graceful`
+        let handle = self.clone();
+        let name = name.to_string();
+        let boost_end = self.clock.now() + duration;
+        self.tokio_handle.spawn(async move {
+            tokio::select! {
+                biased;
+                _ = handle.lifecycle.wait_until(|state| state != Lifecycle::Running) => {}
+                _ = handle.clock.sleep_until(boost_end) => {
+                    if let Some((generation, period, start)) =
+                        handle.registry.unboost(&name, generation)
+                    {
+                        handle.spawn_named_repeating(name, generation, start, period);
+                    }
+                }
+            }
+        });
+        true
+    }
+
+    /// Changes the period of the named repeating task `name` to `period`, e.g. to tune it at
+    /// runtime without restarting the app. The next run comes `period` after the last one (or
+    /// straight away if that has already passed), and a boost (see `boost_task`) ends.
+    /// The period is kept for a task of that name scheduled afterwards, overriding the period it
+    /// is scheduled with, whether or not one was scheduled at the time.
+    /// Returns `false` if no repeating task of that name was scheduled, or if runtime is no longer
+    /// running (draining or stopped).
+    pub fn set_task_period(&self, name: &str, period: Duration) -> bool {
+        self.task_periods
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), period);
+        if self.lifecycle() != Lifecycle::Running {
+            return false;
+        }
+
+        match self.registry.set_period(name, period, self.clock.now()) {
+            Some((generation, start)) => {
+                self.spawn_named_repeating(name.to_string(), generation, start, period);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Lists the named tasks that are still scheduled to run, ordered by name.
+    /// Anonymous tasks (scheduled through the non-named `schedule_*` methods) are not listed.
+    /// Empty once runtime is no longer running (draining or stopped), as no task runs again then.
+    pub fn scheduled_tasks(&self) -> Vec<TaskInfo> {
+        match self.lifecycle() {
+            Lifecycle::Running => self.registry.scheduled(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Renders the named repeating tasks still scheduled (see `scheduled_tasks`) as a crontab, one
+    /// line per task with the task's name as the command, e.g. to diff what the app schedules
+    /// against the system's cron. Times are in UTC and in phase with each task's next run, to the
+    /// minute. Tasks whose period cron cannot express (anything but a number of minutes dividing
+    /// an hour, or of hours dividing a day) are listed as comments, as are paused tasks.
+    pub fn export_crontab(&self) -> String {
+        crate::clockwork_crontab::export(
+            &self.scheduled_tasks(),
+            self.clock.now(),
+            self.clock.system_now(),
+        )
+    }
+
+    /// When the named task `name` is next due to fire: the tick it last fired on plus its period
+    /// for a repeating task, its fire time for a one-shot task that has not run yet.
+    /// The time is on the handle's clock, and is when the task is due rather than when it will
+    /// actually run (a busy runtime may run it late). A paused task keeps advancing its ticks.
+    /// `None` if no task of that name is scheduled, or once runtime is no longer running.
+    pub fn next_fire(&self, name: &str) -> Option<Instant> {
+        match self.lifecycle() {
+            Lifecycle::Running => self.registry.next_fire(name),
+            _ => None,
+        }
+    }
+
+    /// The most recent runs of the named task `name`, oldest first.
+    /// Empty if no task of that name was scheduled.
+    pub fn task_history(&self, name: &str) -> Vec<TaskRun> {
+        self.registry.history(name)
+    }
+
+    /// Waits until the named task `name` has completed its first run (whatever its outcome), e.g.
+    /// to hold back readiness until an initial sync has gone through. Returns at once if it already
+    /// has, and may be awaited before the task is even scheduled. Runs skipped while paused do not
+    /// count. Waits forever if the task never runs, wrap it in `tokio::time::timeout` to bound it.
+    pub async fn wait_for_first_run(&self, name: &str) {
+        self.registry.wait_for_first_run(name).await
+    }
+
+    /// The time at which this handle (and its runtime) was created
+    pub fn start_instant(&self) -> Instant {
+        self.start_instant
+    }
+
+    /// The feature flags gating `schedule_repeating_task_gated` tasks, shared by every clone of
+    /// this handle. Set from the `flags` table of the config, and by `set_flag` while running.
+    pub fn flags(&self) -> FlagStore {
+        self.flags.clone()
+    }
+
+    /// Turns the feature flag `name` on or off, see `flags`
+    pub fn set_flag(&self, name: &str, value: bool) {
+        self.flags.set(name, value)
+    }
+
+    /// Why the runtime stopped, `None` while it has not
+    pub fn stop_reason(&self) -> Option<StopReason> {
+        self.lifecycle.stopped_by().map(|(reason, _)| reason)
+    }
+
+    /// Number of times a scheduled task has run (a consumer task handling a message counts too),
+    /// whatever the outcome. Spawned futures (`spawn_task`) do not count.
+    pub fn task_runs(&self) -> u64 {
+        self.task_runs.load(Ordering::Relaxed)
+    }
+
+    /// Number of runs of `schedule_repeating_task_with_timeout` tasks that were cancelled for not
+    /// completing within their timeout
+    pub fn task_timeouts(&self) -> u64 {
+        self.task_timeouts.load(Ordering::Relaxed)
+    }
+
+    /// Summary of how the runtime ended, as of now, see `ClockworkApp::start_with_report`
+    pub(crate) fn shutdown_report(&self) -> ShutdownReport {
+        let (reason, drained) = self
+            .lifecycle
+            .stopped_by()
+            .unwrap_or((StopReason::Returned, None));
+        ShutdownReport {
+            reason,
+            uptime: self.uptime(),
+            task_runs: self.task_runs(),
+            task_timeouts: self.task_timeouts(),
+            drained,
+        }
+    }
+
+    /// How long the runtime has been up for
+    pub fn uptime(&self) -> Duration {
+        self.clock
+            .now()
+            .saturating_duration_since(self.start_instant)
+    }
+
+    /// Milliseconds since a scheduled task last fired (a consumer task handling a message counts
+    /// too), or since the handle was created if none has fired yet.
+    /// Spawned futures (`spawn_task`) do not count.
+    pub fn millis_since_last_tick(&self) -> u64 {
+        let now = self.uptime().as_millis() as u64;
+        now.saturating_sub(self.last_tick.load(Ordering::Relaxed))
+    }
+
+    fn tick_marker(&self) -> TickMarker {
+        TickMarker {
+            clock: Arc::clone(&self.clock),
+            start_instant: self.start_instant,
+            last_tick: Arc::clone(&self.last_tick),
+            task_runs: Arc::clone(&self.task_runs),
+            task_ran: Arc::clone(&self.task_ran),
+            task_timeouts: Arc::clone(&self.task_timeouts),
+        }
+    }
+
+    /// Stops the runtime once no scheduled task has fired for `idle_timeout`, counting from when
+    /// this is called. Resolves once the runtime is stopped, whichever way.
+    pub(crate) async fn stop_when_idle(&self, idle_timeout: Duration) {
+        self.tick_marker().touch();
+        let mut stop = self.stop_signal();
+        loop {
+            let idle = Duration::from_millis(self.millis_since_last_tick());
+            if idle >= idle_timeout {
+                #[cfg(feature = "logging")]
+                tracing::info!(?idle_timeout, "No task fired within idle timeout, stopping");
+                self.stop_with(StopReason::IdleTimeout, None);
+                return;
+            }
+
+            tokio::select! {
+                _ = &mut stop => return,
+                _ = self.clock.sleep(idle_timeout - idle) => {}
+            }
+        }
+    }
+
+    /// Stops the runtime once the handle's clock reaches `deadline`.
+    /// Resolves once the runtime is stopped, whichever way.
+    pub(crate) async fn stop_at(&self, deadline: Instant) {
+        tokio::select! {
+            biased;
+            _ = self.stop_signal() => {}
+            _ = self.clock.sleep_until(deadline) => {
+                self.stop_with(StopReason::RunFor, None);
+            }
+        }
+    }
+
+    /// Stops the runtime once `duration` has elapsed on the handle's clock, counting from when this
+    /// is called. Resolves once the runtime is stopped, whichever way.
+    pub(crate) async fn stop_after(&self, duration: Duration) {
+        self.stop_at(self.clock.now() + duration).await
+    }
+
+    /// Stops the runtime once scheduled tasks have run `runs` times in total (see `task_runs`).
+    /// Resolves once the runtime is stopped, whichever way.
+    pub(crate) async fn stop_after_runs(&self, runs: u64) {
+        let mut stop = self.stop_signal();
+        loop {
+            // Created before checking, otherwise a run counted in between would be missed
+            let ran = self.task_ran.notified();
+            if self.task_runs() >= runs {
+                self.stop_with(StopReason::RunCount, None);
+                return;
+            }
+
+            tokio::select! {
+                biased;
+                _ = &mut stop => return,
+                _ = ran => {}
+            }
+        }
+    }
+
+    /// Stops the runtime once no spawned task is in flight, i.e. every one-shot task has run and
+    /// no repeating task is scheduled. Resolves once the runtime is stopped, whichever way.
+    pub(crate) async fn stop_when_done(&self) {
+        tokio::select! {
+            biased;
+            _ = self.stop_signal() => {}
+            _ = self.tasks.wait_idle() => {
+                #[cfg(feature = "logging")]
+                tracing::info!("No task left to run, stopping");
+                self.stop_with(StopReason::Done, None);
+            }
+        }
+    }
+
+    /// Number of spawned tasks that have not completed yet
+    #[cfg(feature = "metrics")]
+    pub(crate) fn tasks_in_flight(&self) -> usize {
+        self.tasks.in_flight()
+    }
+
+    /// Worker count, queue depths, etc. of the tokio runtime, as a snapshot.
+    /// Blocking pool metrics are only included when built with `--cfg tokio_unstable`.
+    #[cfg(feature = "metrics")]
+    pub fn runtime_metrics(&self) -> RuntimeMetrics {
+        RuntimeMetrics::from(self.tokio_handle.metrics())
+    }
+
+    /// Cumulative run counters of every named task, ordered by name
+    #[cfg(feature = "metrics")]
+    pub(crate) fn task_stats(&self) -> Vec<(String, TaskStats)> {
+        self.registry.stats()
+    }
+
+    /// Changes the logger's `log_level` at runtime, e.g. to raise verbosity for a while on an admin
+    /// command. Targets with a `log_level` of their own keep it.
+    /// Returns false if there is no logger to change, i.e. it has not been installed by
+    /// `ClockworkApp::start` or was replaced with `ClockworkApp::with_dispatch`.
+    #[cfg(feature = "logging")]
+    pub fn set_log_level(&self, level: LoggerLevel) -> bool {
+        self.log_level.lock().unwrap().set(level)
+    }
+
+    /// Hands the handle the installed logger's level control, see `set_log_level`
+    #[cfg(feature = "logging")]
+    pub(crate) fn set_log_level_control(&self, control: LogLevelControl) {
+        *self.log_level.lock().unwrap() = control;
+    }
+
+    /// Lets go of the runtime ahead of the handle being dropped.
+    /// Dropping a runtime waits on its blocking pool, which tokio does not allow from within
+    /// another runtime (e.g. after `run` was called from one). The runtime is shut down in the
+    /// background there instead, if this holds the last reference to it.
+    pub(crate) fn release_runtime(&mut self) {
+        if Handle::try_current().is_err() {
+            return;
+        }
+        if let Some(rt) = self.rt.take().and_then(|rt| Arc::try_unwrap(rt).ok()) {
+            rt.shutdown_background();
+        }
+    }
+
+    /// Runs a future on the runtime, blocking until completion.
+    /// Called from within a multi-thread tokio runtime, the worker thread is handed over to
+    /// `block_in_place` for as long as it blocks. A current-thread runtime cannot be blocked, this
+    /// panics with an explanation rather than tokio's opaque one.
+    fn run<F: Future>(&self, f: F) {
+        let block_on = || match &self.rt {
+            Some(rt) => rt.block_on(f),
+            None => self.tokio_handle.block_on(f),
+        };
+
+        match Handle::try_current().map(|current| current.runtime_flavor()) {
+            Err(_) => block_on(),
+            Ok(RuntimeFlavor::CurrentThread) => panic!(
+                "Clockwork cannot block the current-thread tokio runtime it is run from, run it \
+                 outside of the runtime, from a multi-thread runtime, or with `spawn_on_runtime`"
+            ),
+            Ok(_) => tokio::task::block_in_place(block_on),
+        };
+    }
+}
+
+pub struct Clockwork {
+    handle: ClockworkHandle,
+    /// The settings `run` and `try_setup` go by, as set by the `with_*` builders
+    config: ClockworkConfig,
+}
+
+impl Clockwork {
+    /// Creates a new Clockwork instance from a ClockworkHandle
+    pub fn new(handle: ClockworkHandle) -> Self {
+        Self {
+            handle,
+            config: ClockworkConfig::default(),
+        }
+    }
+
+    /// Stops the runtime once `run_for` has elapsed since `run` was called
+    pub fn with_run_for(mut self, run_for: Duration) -> Self {
+        self.config.run_for = Some(run_for.into());
+        self
+    }
+
+    /// Stops the runtime once no scheduled task has fired for `idle_timeout`, counting from when
+    /// `run` is called (see `ClockworkHandle::millis_since_last_tick`)
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.config.idle_timeout = Some(idle_timeout.into());
+        self
+    }
+
+    /// Enables/disables stopping the runtime once no task is left to run: every one-shot task
+    /// has run, no repeating task is scheduled and no spawned future is still in flight. Turns
+    /// an app into a batch runner that exits once its jobs are done.
+    /// Checked from when `run` is called, so the jobs have to be scheduled by then (e.g. in
+    /// `Runnable::setup`): if none are, the runtime stops straight away.
+    pub fn with_stop_when_done(mut self, stop_when_done: bool) -> Self {
+        self.config.stop_when_done = stop_when_done;
+        self
+    }
+
+    /// Retries a failing `Runnable::try_setup` up to `retries` times (see `try_setup`), waiting
+    /// `delay` before the first retry and doubling the wait for every retry after it
+    pub fn with_setup_retries(mut self, retries: u32, delay: Duration) -> Self {
+        self.config.setup_retries = retries;
+        self.config.setup_retry_delay = delay.into();
+        self
+    }
+
+    /// Watches the runtime from a separate thread while `run` runs, and logs an error once it has
+    /// made no progress for `threshold`. On a current-thread runtime, a task that blocks (e.g. on
+    /// `std::thread::sleep` or a blocking lock) stalls every other task along with `stop`, which
+    /// otherwise goes unnoticed. Reported once per stall, and again if it stalls after recovering.
+    /// If `abort` is set, the process is aborted instead of being left hanging.
+    /// Panics if `threshold` is zero.
+    pub fn with_stall_watchdog(mut self, threshold: Duration, abort: bool) -> Self {
+        assert!(!threshold.is_zero(), "`threshold` must be non-zero.");
+        self.config.stall_threshold = Some(threshold.into());
+        self.config.abort_on_stall = abort;
+        self
+    }
+
+    /// Waits for the dependencies in `wait_for` to be ready before setting up (see `try_setup`)
+    pub fn with_wait_for(mut self, wait_for: WaitForConfig) -> Self {
+        self.config.wait_for = Some(wait_for);
+        self
+    }
+
+    /// Serves Prometheus metrics over HTTP while `run` is running
+    #[cfg(feature = "metrics")]
+    pub fn with_metrics(mut self, metrics: MetricsConfig) -> Self {
+        self.config.metrics = Some(metrics);
+        self
+    }
+
+    /// The configuration in effect, with defaults filled in, e.g. for printing the effective
+    /// settings at startup or rebuilding an identical `Clockwork` with `Clockwork::from`.
+    /// A `Clockwork` built from a `ClockworkConfig` returns that config. One built with `new`
+    /// reflects the `with_*` calls made on it since, but not the handle, whose runtime and
+    /// settings were chosen by the caller: `runtime`, `dry_run` and `persist_state` are left at
+    /// their defaults.
+    pub fn config(&self) -> &ClockworkConfig {
+        &self.config
+    }
+
+    /// Schedules a task that repeats every interval starting from the specified time until
+    /// runtime is stopped.
+    /// `missed_tick_behavior` decides what happens to ticks missed because a run (or the runtime)
+    /// was slow: `Burst` runs them back-to-back to catch up, `Delay` runs one straight away and
+    /// carries on a period after it, `Skip` runs one straight away and carries on from the next
+    /// tick still aligned with `start`.
+    /// A `start` in the past is treated as now.
+    /// If runtime is no longer running (draining or stopped), the task is not scheduled.
+    pub fn schedule_repeating_task_at<F>(
+        &self,
+        f: F,
+        start: Instant,
+        period: Duration,
+        missed_tick_behavior: MissedTickBehavior,
+    ) where
+        F: 'static + Fn() + std::marker::Sync + std::marker::Send,
+    {
+        self.handle()
+            .schedule_repeating_task_at(f, start, period, missed_tick_behavior)
+    }
+
+    /// Schedules a task that repeats every interval, first running once `delay_before_first` has
+    /// elapsed from now, until runtime is stopped.
+    /// Same as `schedule_repeating_task_at`, without having to compute the starting `Instant`.
+    /// If runtime is no longer running (draining or stopped), the task is not scheduled.
+    pub fn schedule_repeating_task_in<F>(
+        &self,
+        f: F,
+        delay_before_first: impl Into<Duration>,
+        period: impl Into<Duration>,
+    ) where
+        F: 'static + Fn() + std::marker::Sync + std::marker::Send,
+    {
+        self.handle()
+            .schedule_repeating_task_in(f, delay_before_first, period)
+    }
+
+    /// Schedules a task that repeats every interval until runtime is stopped.
+    /// If runtime is no longer running (draining or stopped), the task is not scheduled.
+    pub fn schedule_repeating_task<F>(&self, f: F, period: Duration)
+    where
+        F: 'static + Fn() + std::marker::Sync + std::marker::Send,
+    {
+        self.handle().schedule_repeating_task(f, period)
+    }
+
+    /// Schedules a task that repeats every interval, skipping the ticks on which the feature flag
+    /// `flag` is off. See `ClockworkHandle::schedule_repeating_task_gated`.
+    pub fn schedule_repeating_task_gated<F>(&self, flag: &str, f: F, period: Duration)
+    where
+        F: 'static + Fn() + std::marker::Sync + std::marker::Send,
+    {
+        self.handle().schedule_repeating_task_gated(flag, f, period)
+    }
+
+    /// Schedules a task that repeats every interval, on ticks measured from the handle's
+    /// `start_instant`. See `ClockworkHandle::schedule_repeating_task_from_start`.
+    pub fn schedule_repeating_task_from_start<F>(&self, f: F, offset: Duration, period: Duration)
+    where
+        F: 'static + Fn() + std::marker::Sync + std::marker::Send,
+    {
+        self.handle()
+            .schedule_repeating_task_from_start(f, offset, period)
+    }
+
+    /// Schedules a task that repeats until runtime is stopped, a random duration between `min` and
+    /// `max` apart. See `ClockworkHandle::schedule_repeating_task_random`.
+    pub fn schedule_repeating_task_random<F>(&self, f: F, min: Duration, max: Duration)
+    where
+        F: 'static + Fn() + std::marker::Sync + std::marker::Send,
+    {
+        self.handle().schedule_repeating_task_random(f, min, max)
+    }
+
+    /// Schedules every `(period, f)` job as a repeating task.
+    /// See `ClockworkHandle::schedule_batch`.
+    pub fn schedule_batch(
+        &self,
+        jobs: Vec<(Duration, Box<dyn Fn() + Send + Sync>)>,
+    ) -> Vec<TaskHandle> {
+        self.handle().schedule_batch(jobs)
+    }
+
+    /// Schedules an async task that repeats every interval until runtime is stopped.
+    /// An invocation that does not complete within `timeout_after` is cancelled (and logged if the
+    /// `logging` feature is enabled), the loop then carries on with the next tick.
+    /// If runtime is no longer running (draining or stopped), the task is not scheduled.
+    pub fn schedule_repeating_task_with_timeout<F, Fut>(
+        &self,
+        f: F,
+        period: Duration,
+        timeout_after: Duration,
+    ) where
+        F: 'static + Fn() -> Fut + std::marker::Sync + std::marker::Send,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.handle()
+            .schedule_repeating_task_with_timeout(f, period, timeout_after)
+    }
+
+    /// Schedules a task that runs once after duration elapsed.
+    /// A zero duration never runs the task inline, it runs on the runtime as soon as possible.
+    /// If runtime is stopped before duration elapsed, the task is cancelled and not run. If it is
+    /// stopped while the task is running, the run is not interrupted and completes.
+    /// If runtime is no longer running (draining or stopped), the task is not scheduled.
+    /// Returns a `TaskHandle` to defuse the task with, see `ClockworkHandle::schedule_oneof_task`.
+    pub fn schedule_oneof_task<F>(&self, f: F, duration: Duration) -> TaskHandle
+    where
+        F: 'static + Fn() + std::marker::Sync + std::marker::Send,
+    {
+        self.handle().schedule_oneof_task(f, duration)
+    }
+
+    /// Schedules a task that runs once after duration elapsed, and sends its result through the
+    /// returned receiver.
+    /// If the task does not run, either because runtime is no longer running (draining or
+    /// stopped) or because it is stopped before duration elapsed, the receiver resolves to an error.
+    pub fn schedule_oneof_task_with_result<F, R>(&self, f: F, duration: Duration) -> Receiver<R>
+    where
+        F: 'static + FnOnce() -> R + std::marker::Send,
+        R: 'static + std::marker::Send,
+    {
+        self.handle().schedule_oneof_task_with_result(f, duration)
+    }
+
+    /// Schedules a task that runs once at the given wall-clock time.
+    /// Times in the past run as soon as possible.
+    /// If runtime is stopped before then, the task is cancelled and not run. If it is stopped
+    /// while the task is running, the run is not interrupted and completes.
+    /// If runtime is no longer running (draining or stopped), the task is not scheduled.
+    /// Returns a `TaskHandle` to defuse the task with, see `ClockworkHandle::schedule_oneof_task`.
+    pub fn schedule_oneof_task_at_systemtime<F>(&self, f: F, when: SystemTime) -> TaskHandle
+    where
+        F: 'static + Fn() + std::marker::Sync + std::marker::Send,
+    {
+        self.handle().schedule_oneof_task_at_systemtime(f, when)
+    }
+
+    /// Schedules a task that runs every day at the given local time in timezone `tz`, until
+    /// runtime is stopped. The wall clock is the handle's `Clock` (see `Clock::system_now`), so a
+    /// `ManualClock` drives the task too. The next fire time is recomputed from it after each run,
+    /// so DST transitions are respected: on a fall-back day, a time that occurs twice runs only
+    /// once (at its first occurrence); on a spring-forward day, a time that is skipped runs as
+    /// soon as the clock jumps past it.
+    /// If runtime is no longer running (draining or stopped), the task is not scheduled.
+    #[cfg(feature = "tz")]
+    pub fn schedule_daily_at<F>(&self, f: F, time: NaiveTime, tz: Tz)
+    where
+        F: 'static + Fn() + std::marker::Sync + std::marker::Send,
+    {
+        self.handle().schedule_daily_at(f, time, tz)
+    }
+
+    /// Schedules a named task that repeats every interval until runtime is stopped.
+    /// `f` may return `()` or a `Result`; each run is recorded in the task's history (see
+    /// `task_history`). A panicking run is recorded as such and does not end the task.
+    /// If runtime is no longer running (draining or stopped), the task is not scheduled.
+    /// If a task of the same name is still scheduled, it is replaced or this task is rejected
+    /// according to the handle's `DuplicateTaskPolicy` (see `with_duplicate_task_policy`).
+    /// If state is persisted (see `with_persist_state`) and the task last ran less than `period`
+    /// ago, the first run is delayed until `period` has passed since that run.
+    /// Returns `false` if the task was not scheduled.
+    pub fn schedule_named_repeating_task<F, R>(&self, name: &str, f: F, period: Duration) -> bool
+    where
+        F: 'static + Fn() -> R + std::marker::Sync + std::marker::Send,
+        R: TaskResult,
+    {
+        self.handle().schedule_named_repeating_task(name, f, period)
+    }
+
+    /// Schedules a named task that runs once after duration elapsed.
+    /// The run is recorded in the task's history (see `task_history`).
+    /// If runtime is stopped before duration elapsed, the task is cancelled and not run. If it is
+    /// stopped while the task is running, the run is not interrupted and completes.
+    /// If runtime is no longer running (draining or stopped), the task is not scheduled.
+    /// If a task of the same name is still scheduled, it is replaced or this task is rejected
+    /// according to the handle's `DuplicateTaskPolicy` (see `with_duplicate_task_policy`).
+    /// Returns `false` if the task was not scheduled.
+    pub fn schedule_named_oneof_task<F, R>(&self, name: &str, f: F, duration: Duration) -> bool
+    where
+        F: 'static + Fn() -> R + std::marker::Sync + std::marker::Send,
+        R: TaskResult,
+    {
+        self.handle().schedule_named_oneof_task(name, f, duration)
+    }
+
+    /// Creates a bounded channel for tasks to pass messages through, holding up to `capacity`
+    /// messages. Typically a producer task sends into it (`try_send` from a synchronous task) and
+    /// the receiver is handed to `schedule_consumer_task`.
+    pub fn channel<T>(&self, capacity: usize) -> (Sender<T>, mpsc::Receiver<T>) {
+        self.handle().channel(capacity)
+    }
+
+    /// Schedules a task that receives from `receiver` and passes every message to `handler`, one at
+    /// a time, until runtime is stopped or every sender has been dropped.
+    /// Unlike repeating tasks, the consumer keeps running while draining, so messages already sent
+    /// are still handled before `shutdown_graceful` completes (provided the senders are dropped).
+    /// If runtime is no longer running (draining or stopped), the task is not scheduled.
+    pub fn schedule_consumer_task<T, F>(&self, receiver: mpsc::Receiver<T>, handler: F)
+    where
+        T: 'static + std::marker::Send,
+        F: 'static + Fn(T) + std::marker::Sync + std::marker::Send,
+    {
+        self.handle().schedule_consumer_task(receiver, handler)
+    }
+
+    /// Spawns a future.
+    /// Returns an error if the spawn was rejected, see `ClockworkHandle::spawn_task`.
+    pub fn spawn_task<F>(&self, future: F) -> Result<(), SpawnError>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.handle().spawn_task(future)
+    }
+
+    /// Spawns a future that is dropped once runtime is stopped.
+    /// See `ClockworkHandle::spawn_until_stopped`.
+    pub fn spawn_until_stopped<F>(&self, future: F) -> Result<(), SpawnError>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.handle().spawn_until_stopped(future)
+    }
+
+    /// Spawns a future that does not have to be `Send`.
+    /// See `ClockworkHandle::spawn_local_task`.
+    pub fn spawn_local_task<F, Fut>(&self, f: F) -> bool
+    where
+        F: 'static + FnOnce() -> Fut + std::marker::Send,
+        Fut: Future + 'static,
+    {
+        self.handle().spawn_local_task(f)
+    }
+
+    /// Queues a future to be run according to its priority.
+    /// See `ClockworkHandle::spawn_task_with_priority`.
+    pub fn spawn_task_with_priority<F>(&self, future: F, priority: TaskPriority)
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.handle().spawn_task_with_priority(future, priority);
+    }
+
+    /// Runs a future on the runtime, blocking until completion
+    /// `Runnable::on_ready` is called from within the runtime right before `Runnable::run`
+    /// If a `run_for` duration is set, the runtime is stopped once it has elapsed.
+    /// If `stop_when_done` is set, the runtime is stopped once no task is left to run.
+    /// If an `idle_timeout` is set, the runtime is stopped once no scheduled task has fired for
+    /// that long.
+    /// If metrics are configured, the endpoint is bound before `on_ready` and served until the
+    /// runtime is stopped.
+    /// May be called from within a multi-thread tokio runtime (e.g. from `#[tokio::main]`), whose
+    /// worker thread it then blocks through `tokio::task::block_in_place`. Panics if called from
+    /// within a current-thread runtime, use `spawn_on_runtime` there instead.
+    pub fn run<F: Runnable>(&self, f: &F) {
+        // Not spawned as tracked tasks, so that they do not hold up `shutdown_graceful` (or keep
+        // `stop_when_done` from seeing the runtime as done)
+        if let Some(run_for) = self.config.run_for {
+            let handle = self.handle();
+            let deadline = handle.clock.now() + run_for.as_std();
+            self.handle
+                .tokio_handle
+                .spawn(async move { handle.stop_at(deadline).await });
+        }
+
+        if let Some(idle_timeout) = self.config.idle_timeout.map(Into::into) {
+            let handle = self.handle();
+            self.handle
+                .tokio_handle
+                .spawn(async move { handle.stop_when_idle(idle_timeout).await });
+        }
+
+        if self.config.stop_when_done {
+            let handle = self.handle();
+            self.handle
+                .tokio_handle
+                .spawn(async move { handle.stop_when_done().await });
+        }
+
+        // Beaten for as long as the runtime makes progress, stops watching once `run` returns
+        let _watchdog = self.config.stall_threshold.map(|threshold| {
+            let threshold = threshold.as_std();
+            let watchdog = StallWatchdog::start(threshold, self.config.abort_on_stall);
+            let heartbeat = watchdog.heartbeat();
+            self.handle.tokio_handle.spawn(async move {
+                let mut beats = tokio::time::interval(threshold / 4);
+                beats.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                while heartbeat.beat() {
+                    beats.tick().await;
+                }
+            });
+            watchdog
+        });
+
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.config.metrics {
+            let listener = crate::clockwork_metrics::bind(metrics);
+            self.handle
+                .tokio_handle
+                .spawn(crate::clockwork_metrics::serve(listener, self.handle()));
+        }
+
+        // Runs inside a `LocalSet` so that `spawn_local_task` futures can be driven alongside
+        let local_set = LocalSet::new();
+        self.handle.run(local_set.run_until(async {
+            let handle = self.handle();
+            tokio::task::spawn_local(async move { handle.drive_local_tasks().await });
+
+            f.on_ready(self.handle());
+            f.run(self.handle(), self.handle.stop_signal()).await
+        }));
+    }
+
+    /// Same as `run`, but runs on a dedicated thread instead of blocking the caller.
+    /// Returns a `ClockworkJoinHandle` to stop and join the thread with.
+    /// The runtime can only be driven from one thread at a time, `run` the same `Clockwork` on
+    /// another thread only once this one has been joined.
+    /// ```
+    /// use clockwork::{Clockwork, ClockworkHandle};
+    /// let cw = Clockwork::default();
+    /// let thread = cw.run_detached(|_handle: ClockworkHandle| {});
+    /// // ... the caller carries on
+    /// thread.stop_and_join().unwrap();
+    /// ```
+    pub fn run_detached<F>(&self, f: F) -> ClockworkJoinHandle
+    where
+        F: Runnable + Send + Sync + 'static,
+    {
+        let cw = Self {
+            handle: self.handle(),
+            config: self.config.clone(),
+        };
+        spawn_thread(self.handle(), move || cw.run(&f), |_| {})
+    }
+
+    /// Waits for the dependencies set by `with_wait_for` to be ready, then calls
+    /// `Runnable::try_setup`, retrying it with backoff as set by `with_setup_retries` while it
+    /// fails. Blocks the calling thread while waiting, call it before `run`.
+    /// Returns `ClockworkError::NotReady` if the dependencies were not ready in time, or the last
+    /// error if every setup attempt failed.
+    pub fn try_setup<F: Runnable>(&self, f: &F) -> Result<(), Box<dyn Error>> {
+        if let Some(wait_for) = &self.config.wait_for {
+            wait_for.wait()?;
+        }
+
+        let mut delay = self.config.setup_retry_delay.as_std();
+        let mut retries = 0;
+        loop {
+            match f.try_setup(self.handle()) {
+                Err(_e) if retries < self.config.setup_retries => {
+                    #[cfg(feature = "logging")]
+                    tracing::warn!(error = %_e, retry_in = ?delay, "Setup failed, retrying");
+                    std::thread::sleep(delay);
+                    delay = delay.saturating_mul(2);
+                    retries += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Calls `body` (e.g. `f`'s setup or run), isolating a panic in it: `f` is torn down as by
+    /// `ClockworkHandle::runnable_panicked` before the panic carries on unwinding
+    pub(crate) fn isolate_panics<F: Runnable, R>(&self, f: &F, body: impl FnOnce() -> R) -> R {
+        catch_unwind(AssertUnwindSafe(body)).unwrap_or_else(|payload| {
+            self.handle.runnable_panicked(f, payload.as_ref());
+            resume_unwind(payload)
+        })
+    }
+
+    /// Tears the runtime down ahead of it being replaced (see `ClockworkApp::restart`): stops it if
+    /// it was not already, then drives it for up to `timeout_after` while the tasks still in
+    /// flight exit, so that they let go of their handles rather than keep the runtime alive once
+    /// this is dropped. Returns `true` if every task exited in time.
+    pub(crate) fn teardown(&self, timeout_after: Duration) -> bool {
+        self.handle.stop();
+        let mut idle = false;
+        self.handle.run(async {
+            idle = timeout(timeout_after, self.handle.tasks.wait_idle())
+                .await
+                .is_ok();
+        });
+        idle
+    }
+
+    /// Changes the period of the named repeating task `name`, recording it in `config`'s
+    /// `task_periods`. See `ClockworkHandle::set_task_period`.
+    pub fn set_task_period(&mut self, name: &str, period: Duration) -> bool {
+        self.config
+            .task_periods
+            .insert(name.to_string(), period.into());
+        self.handle.set_task_period(name, period)
+    }
+
+    /// Returns a clone of the ClockworkHandle (allows scheduling from different thread, etc)
+    pub fn handle(&self) -> ClockworkHandle {
+        self.handle.clone()
+    }
+
+    /// Raise the 'stopped' flag, resolves the `StopSignal` given to the running `Runnable`
+    pub fn stop(&mut self) {
+        self.handle.stop();
+    }
+}
+
+impl Drop for Clockwork {
+    fn drop(&mut self) {
+        self.handle.release_runtime();
+    }
+}
+
+impl From<ClockworkConfig> for Clockwork {
+    fn from(conf: ClockworkConfig) -> Self {
+        let config = conf.clone();
+        let mut builder = match conf.runtime.worker_threads {
+            Some(worker_threads) => {
+                let mut builder = Builder::new_multi_thread();
+                builder.worker_threads(worker_threads);
+                builder
+            }
+            None => Builder::new_current_thread(),
+        };
+        if conf.runtime.enable_io {
+            builder.enable_io();
+        }
+
+        if conf.runtime.enable_time {
+            builder.enable_time();
+        }
+
+        builder.max_blocking_threads(conf.runtime.max_blocking_threads);
+
+        let mut handle =
+            ClockworkHandle::new(Arc::new(builder.build().expect("Failed to Build Runtime")))
+                .with_task_history_size(conf.runtime.task_history_size)
+                .with_duplicate_task_policy(conf.runtime.duplicate_task_policy)
+                .with_dry_run(conf.dry_run)
+                .with_flags(FlagStore::from(conf.flags));
+        if let Some(max_concurrent_tasks) = conf.runtime.max_concurrent_tasks {
+            handle = handle.with_task_limit(max_concurrent_tasks, conf.runtime.task_limit_policy);
+        }
+
+        if let Some(path) = conf.persist_state {
+            handle = handle.with_persist_state(path);
+        }
+
+        if let Some(threshold) = conf.runtime.late_tick_threshold {
+            handle = handle.with_late_tick_threshold(threshold.into());
+        }
+
+        let task_periods = conf.task_periods.into_iter();
+        handle = handle.with_task_periods(task_periods.map(|(name, p)| (name, p.into())).collect());
+
+        Self { handle, config }
+    }
+}
+
+impl Default for Clockwork {
+    fn default() -> Self {
+        Self::from(ClockworkConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Clock, Clockwork, ClockworkConfig, ClockworkHandle, DuplicateTaskPolicy, Lifecycle,
+        ManualClock, SpawnError, StopReason, TaskKind, TaskLimitPolicy, TaskOutcome, TaskPriority,
+    };
+    use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::SystemTime;
+    use tokio::runtime::{Handle, RuntimeFlavor};
+    use tokio::time::{sleep, Duration, Instant, MissedTickBehavior};
+
+    #[test]
+    fn test_config() {
+        let conf: ClockworkConfig = toml::from_str(
+            r#"
+            run_for = '1m'
+            stall_threshold = '2s'
+
+            [runtime]
+            max_threads = 4
+        "#,
+        )
+        .unwrap();
+        let cw = Clockwork::from(conf);
+        assert_eq!(
+            cw.config().run_for.unwrap().as_std(),
+            Duration::from_secs(60)
+        );
+        // read through the deprecated `max_threads` alias
+        assert_eq!(cw.config().runtime.max_blocking_threads, 4);
+        assert!(cw.config().runtime.enable_io);
+        assert_eq!(
+            cw.config().setup_retry_delay.as_std(),
+            Duration::from_secs(1)
+        );
+        assert_eq!(
+            cw.config().stall_threshold.unwrap().as_std(),
+            Duration::from_secs(2)
+        );
+        assert!(!cw.config().abort_on_stall);
+
+        let cw = Clockwork::new(cw.handle()).with_idle_timeout(Duration::from_secs(5));
+        assert!(cw.config().run_for.is_none());
+        assert_eq!(
+            cw.config().idle_timeout.unwrap().as_std(),
+            Duration::from_secs(5)
+        );
+        // the same defaults as a `Clockwork` built from a default config
+        assert_eq!(
+            cw.config().setup_retry_delay.as_std(),
+            Clockwork::from(ClockworkConfig::default())
+                .config()
+                .setup_retry_delay
+                .as_std()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "`threshold` must be non-zero.")]
+    fn test_zero_stall_threshold() {
+        let _ = Clockwork::default().with_stall_watchdog(Duration::ZERO, false);
+    }
+
+    #[test]
+    fn test_worker_threads() {
+        let conf: ClockworkConfig = toml::from_str("[runtime]\nworker_threads = 2").unwrap();
+        let cw = Clockwork::from(conf);
+        let handle = cw.handle();
+        let multi_thread = Arc::new(AtomicBool::new(false));
+        {
+            let multi_thread = multi_thread.clone();
+            cw.schedule_oneof_task(
+                move || {
+                    let flavor = Handle::current().runtime_flavor();
+                    multi_thread.store(flavor == RuntimeFlavor::MultiThread, Ordering::SeqCst);
+                    handle.stop();
+                },
+                Duration::ZERO,
+            );
+        }
+        cw.run(&|_handle: ClockworkHandle| {});
+        assert!(multi_thread.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_schedule_repeating_after_stop() {
+        let cw = Clockwork::default();
+        let handle = cw.handle();
+        let count = Arc::new(AtomicU32::new(0));
+
+        handle.stop();
+        {
+            let count = count.clone();
+            handle.schedule_repeating_task_at(
+                move || {
+                    count.fetch_add(1, Ordering::SeqCst);
+                },
+                Instant::now(),
+                Duration::from_millis(1),
+                MissedTickBehavior::Burst,
+            );
+        }
+
+        handle.run(async { sleep(Duration::from_millis(20)).await });
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_schedule_oneof_after_stop() {
+        let cw = Clockwork::default();
+        let handle = cw.handle();
+        let count = Arc::new(AtomicU32::new(0));
+
+        handle.stop();
+        {
+            let count = count.clone();
+            handle.schedule_oneof_task(
+                move || {
+                    count.fetch_add(1, Ordering::SeqCst);
+                },
+                Duration::from_millis(0),
+            );
+        }
+
+        handle.run(async { sleep(Duration::from_millis(20)).await });
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_stop_while_oneof_sleeping() {
+        let clock = Arc::new(ManualClock::new());
+        let rt = Arc::new(
+            tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap(),
+        );
+        let handle = ClockworkHandle::new(rt).with_clock(clock.clone());
+        let count = Arc::new(AtomicU32::new(0));
+
+        {
+            let count = count.clone();
+            handle.schedule_oneof_task(
+                move || {
+                    count.fetch_add(1, Ordering::SeqCst);
+                },
+                Duration::from_secs(10),
+            );
+        }
+
+        let settle = || async {
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
+            }
+        };
+        handle.run(async {
+            settle().await;
+            handle.stop();
+            clock.advance(Duration::from_secs(10));
+            settle().await;
+        });
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_stop_while_oneof_running() {
+        let clock = Arc::new(ManualClock::new());
+        let rt = Arc::new(
+            tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap(),
+        );
+        let handle = ClockworkHandle::new(rt).with_clock(clock.clone());
+        let count = Arc::new(AtomicU32::new(0));
+
+        {
+            let count = count.clone();
+            let task_handle = handle.clone();
+            handle.schedule_oneof_task(
+                move || {
+                    task_handle.stop();
+                    count.fetch_add(1, Ordering::SeqCst);
+                },
+                Duration::from_secs(10),
+            );
+        }
+
+        let settle = || async {
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
+            }
+        };
+        handle.run(async {
+            settle().await;
+            clock.advance(Duration::from_secs(10));
+            settle().await;
+        });
+        assert!(handle.stopped());
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_stop_races_schedule() {
+        for _ in 0..50 {
+            let rt = Arc::new(
+                tokio::runtime::Builder::new_multi_thread()
+                    .worker_threads(2)
+                    .enable_time()
+                    .build()
+                    .unwrap(),
+            );
+            let handle = ClockworkHandle::new(rt);
+            let runs = Arc::new(AtomicU32::new(0));
+            let barrier = Arc::new(std::sync::Barrier::new(2));
+
+            let stopper = {
+                let handle = handle.clone();
+                let barrier = barrier.clone();
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    handle.stop();
+                })
+            };
+            let scheduler = {
+                let handle = handle.clone();
+                let runs = runs.clone();
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    handle.schedule_repeating_task(
+                        move || {
+                            runs.fetch_add(1, Ordering::SeqCst);
+                        },
+                        Duration::from_millis(1),
+                    );
+                })
+            };
+            stopper.join().unwrap();
+            scheduler.join().unwrap();
+
+            // scheduled after the stop was observed
+            {
+                let runs = runs.clone();
+                handle.schedule_repeating_task(
+                    move || {
+                        runs.fetch_add(100, Ordering::SeqCst);
+                    },
+                    Duration::from_millis(1),
+                );
+            }
+
+            // whichever side won the race, nothing runs once the stop has returned
+            std::thread::sleep(Duration::from_millis(5));
+            let settled = runs.load(Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(10));
+            assert_eq!(runs.load(Ordering::SeqCst), settled);
+            assert!(settled < 100);
+        }
+    }
+
+    #[test]
+    fn test_uptime() {
+        let cw = Clockwork::default();
+        let handle = cw.handle();
+
+        handle.run(async { sleep(Duration::from_millis(20)).await });
+        assert!(handle.uptime() >= Duration::from_millis(20));
+        assert!(handle.start_instant() <= Instant::now());
+    }
+
+    #[test]
+    fn test_spawn_rejected_over_task_limit() {
+        let conf: ClockworkConfig = toml::from_str(
+            r#"
+            [runtime]
+            max_concurrent_tasks = 1
+            task_limit_policy = 'REJECT'
+        "#,
+        )
+        .unwrap();
+        let cw = Clockwork::from(conf);
+
+        assert!(cw
+            .spawn_task(async { sleep(Duration::from_millis(20)).await })
+            .is_ok());
+        assert_eq!(cw.spawn_task(async {}), Err(SpawnError::TaskLimit));
+
+        cw.handle()
+            .run(async { sleep(Duration::from_millis(40)).await });
+        assert!(cw.spawn_task(async {}).is_ok());
+
+        cw.handle().stop();
+        assert_eq!(cw.spawn_task(async {}), Err(SpawnError::Stopped));
+    }
+
+    #[test]
+    fn test_spawn_waits_over_task_limit() {
+        let cw = Clockwork::default();
+        let handle = cw.handle().with_task_limit(1, TaskLimitPolicy::WAIT);
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let ticks = Arc::new(AtomicU32::new(0));
+
+        // scheduled tasks do not take the only permit
+        for _ in 0..2 {
+            let ticks = ticks.clone();
+            handle.schedule_repeating_task(
+                move || {
+                    ticks.fetch_add(1, Ordering::SeqCst);
+                },
+                Duration::from_millis(5),
+            );
+        }
+        for id in 0..2 {
+            let events = events.clone();
+            assert!(handle
+                .spawn_task(async move {
+                    events.lock().unwrap().push(format!("start {}", id));
+                    sleep(Duration::from_millis(20)).await;
+                    events.lock().unwrap().push(format!("end {}", id));
+                })
+                .is_ok());
+        }
+
+        handle.run(async { sleep(Duration::from_millis(60)).await });
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec!["start 0", "end 0", "start 1", "end 1"]
+        );
+        assert!(ticks.load(Ordering::SeqCst) >= 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "`max_concurrent_tasks` must be non-zero.")]
+    fn test_zero_task_limit() {
+        let _ = Clockwork::default()
+            .handle()
+            .with_task_limit(0, TaskLimitPolicy::WAIT);
+    }
+
+    #[test]
+    fn test_stop_signal() {
+        let cw = Clockwork::default();
+        let handle = cw.handle();
+
+        {
+            let handle = handle.clone();
+            cw.schedule_oneof_task(move || handle.stop(), Duration::from_millis(10));
+        }
+
+        let stop = handle.stop_signal();
+        handle.run(async {
+            tokio::select! {
+                _ = stop => {}
+                _ = sleep(Duration::from_secs(5)) => panic!("stop signal did not fire"),
+            }
+        });
+        assert!(handle.stopped());
+    }
+
+    #[test]
+    fn test_spawn_until_stopped() {
+        let cw = Clockwork::default();
+        let handle = cw.handle();
+        let count = Arc::new(AtomicU32::new(0));
+
+        {
+            let count = count.clone();
+            assert!(cw
+                .spawn_until_stopped(async move {
+                    loop {
+                        count.fetch_add(1, Ordering::SeqCst);
+                        sleep(Duration::from_millis(1)).await;
+                    }
+                })
+                .is_ok());
+        }
+        {
+            let handle = handle.clone();
+            cw.schedule_oneof_task(move || handle.stop(), Duration::from_millis(20));
+        }
+
+        let stop = handle.stop_signal();
+        handle.run(async {
+            stop.await;
+            // the loop never ends by itself, so this only succeeds if it was cancelled
+            assert!(handle.shutdown_graceful(Duration::from_secs(1)).await);
+        });
+
+        let count_after_stop = count.load(Ordering::SeqCst);
+        assert!(count_after_stop > 0);
+        handle.run(async { sleep(Duration::from_millis(10)).await });
+        assert_eq!(count.load(Ordering::SeqCst), count_after_stop);
+        assert_eq!(cw.spawn_until_stopped(async {}), Err(SpawnError::Stopped));
+    }
+
+    #[test]
+    fn test_repeating_task_with_timeout() {
+        let cw = Clockwork::default();
+        let started = Arc::new(AtomicU32::new(0));
+        let finished = Arc::new(AtomicU32::new(0));
+
+        {
+            let started = started.clone();
+            let finished = finished.clone();
+            cw.schedule_repeating_task_with_timeout(
+                move || {
+                    let started = started.clone();
+                    let finished = finished.clone();
+                    async move {
+                        started.fetch_add(1, Ordering::SeqCst);
+                        sleep(Duration::from_secs(5)).await;
+                        finished.fetch_add(1, Ordering::SeqCst);
+                    }
+                },
+                Duration::from_millis(5),
+                Duration::from_millis(10),
+            );
+        }
+
+        cw.handle()
+            .run(async { sleep(Duration::from_millis(100)).await });
+        assert!(started.load(Ordering::SeqCst) > 1);
+        assert_eq!(finished.load(Ordering::SeqCst), 0);
+        assert!(cw.handle().task_timeouts() > 1);
+    }
+
+    #[test]
+    fn test_spawn_task_with_priority() {
+        let cw = Clockwork::default();
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        for (id, priority) in [
+            (0, TaskPriority::Low),
+            (1, TaskPriority::Normal),
+            (2, TaskPriority::High),
+            (3, TaskPriority::Low),
+            (4, TaskPriority::High),
+        ] {
+            let order = order.clone();
+            cw.spawn_task_with_priority(async move { order.lock().unwrap().push(id) }, priority);
+        }
+
+        cw.handle()
+            .run(async { sleep(Duration::from_millis(20)).await });
+        assert_eq!(*order.lock().unwrap(), vec![2, 4, 1, 0, 3]);
+    }
+
+    #[test]
+    fn test_schedule_oneof_task_at_systemtime() {
+        let cw = Clockwork::default();
+        let count = Arc::new(AtomicU32::new(0));
+
+        for when in [
+            SystemTime::now() - Duration::from_secs(60),
+            SystemTime::now() + Duration::from_millis(5),
+            SystemTime::now() + Duration::from_secs(60),
+        ] {
+            let count = count.clone();
+            cw.schedule_oneof_task_at_systemtime(
+                move || {
+                    count.fetch_add(1, Ordering::SeqCst);
+                },
+                when,
+            );
+        }
+
+        cw.handle()
+            .run(async { sleep(Duration::from_millis(30)).await });
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_stop_visible_across_threads() {
+        let cw = Clockwork::default();
+        let payload = Arc::new(AtomicU32::new(0));
+
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let handle = cw.handle();
+                let payload = payload.clone();
+                std::thread::spawn(move || {
+                    while !handle.stopped() {
+                        std::hint::spin_loop();
+                    }
+                    payload.load(Ordering::Relaxed)
+                })
+            })
+            .collect();
+
+        payload.store(42, Ordering::Relaxed);
+        cw.handle().stop();
+
+        for reader in readers {
+            assert_eq!(reader.join().unwrap(), 42);
+        }
+    }
+
+    #[test]
+    fn test_drain_and_stop() {
+        let cw = Clockwork::default();
+        let handle = cw.handle();
+        let ticks = Arc::new(AtomicU32::new(0));
+        let fired = Arc::new(AtomicBool::new(false));
+
+        {
+            let ticks = ticks.clone();
+            handle.schedule_repeating_task(
+                move || {
+                    ticks.fetch_add(1, Ordering::SeqCst);
+                },
+                Duration::from_millis(1),
+            );
+        }
+
+        {
+            let fired = fired.clone();
+            let spawner = handle.clone();
+            handle.schedule_oneof_task(
+                move || {
+                    assert_eq!(spawner.lifecycle(), Lifecycle::Draining);
+                    fired.store(true, Ordering::SeqCst);
+                },
+                Duration::from_millis(20),
+            );
+        }
+
+        handle.run(async {
+            sleep(Duration::from_millis(5)).await;
+            handle.drain_and_stop();
+            assert_eq!(handle.lifecycle(), Lifecycle::Draining);
+            sleep(Duration::from_millis(2)).await;
+            let ticks_at_drain = ticks.load(Ordering::SeqCst);
+
+            handle.stop_signal().await;
+            assert_eq!(ticks.load(Ordering::SeqCst), ticks_at_drain);
+        });
+
+        assert!(fired.load(Ordering::SeqCst));
+        assert!(handle.stopped());
+    }
+
+    #[test]
+    fn test_register_on_stop() {
+        let cw = Clockwork::default();
+        let handle = cw.handle();
+        let events = Arc::new(Mutex::new(Vec::new()));
+
+        for event in ["flush", "close"] {
+            let events = events.clone();
+            let handle_in_callback = handle.clone();
+            handle.register_on_stop(move || {
+                assert!(handle_in_callback.stopped());
+                events.lock().unwrap().push(event);
+            });
+        }
+
+        handle.stop();
+        handle.stop();
+        assert_eq!(*events.lock().unwrap(), vec!["flush", "close"]);
+
+        {
+            let events = events.clone();
+            handle.register_on_stop(move || events.lock().unwrap().push("late"));
+        }
+        assert_eq!(*events.lock().unwrap(), vec!["flush", "close", "late"]);
+    }
+
+    #[test]
+    fn test_try_stop() {
+        let cw = Clockwork::default();
+        let winners = Arc::new(AtomicU32::new(0));
+
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let handle = cw.handle();
+                let winners = winners.clone();
+                std::thread::spawn(move || {
+                    if handle.try_stop() {
+                        winners.fetch_add(1, Ordering::SeqCst);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(winners.load(Ordering::SeqCst), 1);
+        assert!(cw.handle().stopped());
+        assert!(!cw.handle().try_stop());
+    }
+
+    #[test]
+    fn test_shutdown_graceful() {
+        let cw = Clockwork::default();
+        let handle = cw.handle();
+        let completed = Arc::new(AtomicU32::new(0));
+        let ticks = Arc::new(AtomicU32::new(0));
+        let drained = Arc::new(AtomicBool::new(false));
+
+        {
+            let ticks = ticks.clone();
+            handle.schedule_repeating_task(
+                move || {
+                    ticks.fetch_add(1, Ordering::SeqCst);
+                },
+                Duration::from_millis(1),
+            );
+        }
+
+        {
+            let completed = completed.clone();
+            let spawner = handle.clone();
+            handle.schedule_oneof_task(
+                move || {
+                    assert_eq!(spawner.lifecycle(), Lifecycle::Draining);
+                    completed.fetch_add(1, Ordering::SeqCst);
+
+                    let completed = completed.clone();
+                    assert!(spawner
+                        .spawn_task(async move {
+                            sleep(Duration::from_millis(10)).await;
+                            completed.fetch_add(1, Ordering::SeqCst);
+                        })
+                        .is_ok());
+                },
+                Duration::from_millis(20),
+            );
+        }
+
+        {
+            let handle = handle.clone();
+            let drained = drained.clone();
+            handle.clone().run(async move {
+                sleep(Duration::from_millis(5)).await;
+                let ticks_at_drain = ticks.load(Ordering::SeqCst);
+                drained.store(
+                    handle.shutdown_graceful(Duration::from_secs(5)).await,
+                    Ordering::SeqCst,
+                );
+                assert_eq!(ticks.load(Ordering::SeqCst), ticks_at_drain);
+            });
+        }
+
+        assert!(drained.load(Ordering::SeqCst));
+        assert_eq!(completed.load(Ordering::SeqCst), 2);
+        assert_eq!(handle.lifecycle(), Lifecycle::Stopped);
+        assert_eq!(handle.stop_reason(), Some(StopReason::Drained));
+        assert_eq!(handle.spawn_task(async {}), Err(SpawnError::Stopped));
+    }
+
+    #[test]
+    fn test_task_history() {
+        let conf: ClockworkConfig = toml::from_str(
+            r#"
+            [runtime]
+            task_history_size = 2
+        "#,
+        )
+        .unwrap();
+        let cw = Clockwork::from(conf);
+        let count = Arc::new(AtomicU32::new(0));
+
+        {
+            let count = count.clone();
+            cw.schedule_named_repeating_task(
+                "flaky",
+                move || match count.fetch_add(1, Ordering::SeqCst) % 3 {
+                    0 => Ok(()),
+                    1 => Err("failed"),
+                    _ => panic!("boom"),
+                },
+                Duration::from_millis(2),
+            );
+        }
+        cw.schedule_named_oneof_task("once", || {}, Duration::from_millis(0));
+
+        cw.handle()
+            .run(async { sleep(Duration::from_millis(100)).await });
+
+        let history = cw.handle().task_history("flaky");
+        assert!(count.load(Ordering::SeqCst) >= 3);
+        assert_eq!(history.len(), 2);
+        assert!(history
+            .windows(2)
+            .all(|runs| runs[0].started_at <= runs[1].started_at));
+
+        let once = cw.handle().task_history("once");
+        assert_eq!(once.len(), 1);
+        assert_eq!(once[0].outcome, TaskOutcome::Ok);
+    }
+
+    #[test]
+    fn test_wait_for_first_run() {
+        let clock = Arc::new(ManualClock::new());
+        let rt = Arc::new(
+            tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap(),
+        );
+        let handle = ClockworkHandle::new(rt).with_clock(clock.clone());
+        let ready = Arc::new(AtomicBool::new(false));
+
+        let settle = || async {
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
+            }
+        };
+        handle.run(async {
+            // waiting before the task is even scheduled
+            {
+                let (handle, ready) = (handle.clone(), ready.clone());
+                tokio::spawn(async move {
+                    handle.wait_for_first_run("initial_sync").await;
+                    ready.store(true, Ordering::SeqCst);
+                });
+            }
+            settle().await;
+            handle.schedule_named_repeating_task(
+                "initial_sync",
+                || Err("upstream down"),
+                Duration::from_secs(10),
+            );
+            handle.pause_task("initial_sync");
+
+            // a paused run does not count
+            clock.advance(Duration::from_secs(10));
+            settle().await;
+            assert!(!ready.load(Ordering::SeqCst));
+
+            handle.resume_task("initial_sync");
+            clock.advance(Duration::from_secs(10));
+            settle().await;
+            assert!(ready.load(Ordering::SeqCst));
+
+            // returns at once from then on, failed run or not
+            handle.wait_for_first_run("initial_sync").await;
+        });
+    }
+
+    #[test]
+    fn test_run_for() {
+        let conf: ClockworkConfig = toml::from_str("run_for = '10ms'").unwrap();
+        let cw = Clockwork::from(conf);
+
+        cw.run(&|_| {});
+        assert!(cw.handle().stopped());
+        assert_eq!(cw.handle().stop_reason(), Some(StopReason::RunFor));
+    }
+
+    #[test]
+    fn test_stop_when_done() {
+        let conf: ClockworkConfig = toml::from_str(
+            r#"
+            stop_when_done = true
+            run_for = '10s'
+        "#,
+        )
+        .unwrap();
+        let cw = Clockwork::from(conf);
+        let count = Arc::new(AtomicU32::new(0));
+
+        for delay in [5, 10, 20] {
+            let count = count.clone();
+            cw.schedule_oneof_task(
+                move || {
+                    count.fetch_add(1, Ordering::SeqCst);
+                },
+                Duration::from_millis(delay),
+            );
+        }
+
+        // stops once the last job has run, well before `run_for` is up
+        let start = std::time::Instant::now();
+        cw.run(&|_| {});
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+        assert_eq!(cw.handle().stop_reason(), Some(StopReason::Done));
+        assert_eq!(cw.handle().task_runs(), 3);
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let conf: ClockworkConfig = toml::from_str(
+            r#"
+            dry_run = true
+            run_for_secs = 0
+        "#,
+        )
+        .unwrap();
+        let cw = Clockwork::from(conf);
+        let count = Arc::new(AtomicU32::new(0));
+
+        {
+            let count = count.clone();
+            cw.schedule_repeating_task(
+                move || {
+                    count.fetch_add(1, Ordering::SeqCst);
+                },
+                Duration::from_millis(1),
+            );
+        }
+        {
+            let count = count.clone();
+            cw.schedule_named_oneof_task(
+                "once",
+                move || {
+                    count.fetch_add(1, Ordering::SeqCst);
+                },
+                Duration::from_millis(0),
+            );
+        }
+
+        cw.handle()
+            .run(async { sleep(Duration::from_millis(20)).await });
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+
+        cw.run(&|_| {});
+        assert!(cw.handle().stopped());
+    }
+
+    #[test]
+    fn test_update_task() {
+        let cw = Clockwork::default();
+        let handle = cw.handle();
+        let value = Arc::new(AtomicU32::new(0));
+
+        {
+            let value = value.clone();
+            cw.schedule_named_repeating_task(
+                "task",
+                move || value.store(1, Ordering::SeqCst),
+                Duration::from_millis(2),
+            );
+        }
+        handle.run(async { sleep(Duration::from_millis(10)).await });
+        assert_eq!(value.load(Ordering::SeqCst), 1);
+
+        {
+            let value = value.clone();
+            assert!(handle.update_task("task", move || value.store(2, Ordering::SeqCst)));
+        }
+        assert!(!handle.update_task("unknown", || {}));
+        handle.run(async { sleep(Duration::from_millis(10)).await });
+        assert_eq!(value.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_manual_clock() {
+        let clock = Arc::new(ManualClock::new());
+        let rt = Arc::new(
+            tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap(),
+        );
+        let handle = ClockworkHandle::new(rt).with_clock(clock.clone());
+        let ticks = Arc::new(AtomicU32::new(0));
+        let fired = Arc::new(AtomicBool::new(false));
+
+        {
+            let ticks = ticks.clone();
+            handle.schedule_repeating_task(
+                move || {
+                    ticks.fetch_add(1, Ordering::SeqCst);
+                },
+                Duration::from_secs(10),
+            );
+        }
+        {
+            let fired = fired.clone();
+            handle.schedule_oneof_task(
+                move || fired.store(true, Ordering::SeqCst),
+                Duration::from_secs(25),
+            );
+        }
+
+        let settle = || async {
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
+            }
+        };
+        handle.run(async {
+            settle().await;
+            assert_eq!(ticks.load(Ordering::SeqCst), 1);
+
+            for _ in 0..3 {
+                clock.advance(Duration::from_secs(10));
+                settle().await;
+            }
+        });
+
+        assert_eq!(ticks.load(Ordering::SeqCst), 4);
+        assert!(fired.load(Ordering::SeqCst));
+        assert_eq!(handle.uptime(), Duration::from_secs(30));
+    }
+
+    #[cfg(feature = "tz")]
+    #[test]
+    fn test_schedule_daily_at_across_dst() {
+        use chrono::{DateTime, NaiveTime};
+        use chrono_tz::America::New_York;
+
+        let settle = || async {
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
+            }
+        };
+        let daily_at = |start: &str, h: u32, m: u32| {
+            let start = DateTime::parse_from_rfc3339(start).unwrap();
+            let clock = Arc::new(ManualClock::at_system_time(start.into()));
+            let rt = Arc::new(
+                tokio::runtime::Builder::new_current_thread()
+                    .build()
+                    .unwrap(),
+            );
+            let handle = ClockworkHandle::new(rt).with_clock(clock.clone());
+            let runs = Arc::new(AtomicU32::new(0));
+            let counted = runs.clone();
+            handle.schedule_daily_at(
+                move || {
+                    counted.fetch_add(1, Ordering::SeqCst);
+                },
+                NaiveTime::from_hms_opt(h, m, 0).unwrap(),
+                New_York,
+            );
+            (clock, handle, runs)
+        };
+        let minutes = |m: u64| Duration::from_secs(m * 60);
+
+        // spring forward: 02:30 is skipped, it runs once the clock jumps to 03:00 EDT
+        let (clock, handle, runs) = daily_at("2021-03-14T01:00:00-05:00", 2, 30);
+        handle.run(async {
+            settle().await;
+            clock.advance(minutes(59));
+            settle().await;
+            assert_eq!(runs.load(Ordering::SeqCst), 0);
+            clock.advance(minutes(1));
+            settle().await;
+            assert_eq!(runs.load(Ordering::SeqCst), 1);
+            // then at 02:30 EDT the next day
+            clock.advance(minutes(23 * 60 + 29));
+            settle().await;
+            assert_eq!(runs.load(Ordering::SeqCst), 1);
+            clock.advance(minutes(1));
+            settle().await;
+            assert_eq!(runs.load(Ordering::SeqCst), 2);
+        });
+
+        // fall back: 01:30 occurs twice, it only runs at the first occurrence
+        let (clock, handle, runs) = daily_at("2021-11-07T01:00:00-04:00", 1, 30);
+        handle.run(async {
+            settle().await;
+            clock.advance(minutes(30));
+            settle().await;
+            assert_eq!(runs.load(Ordering::SeqCst), 1);
+            // 01:30 EST
+            clock.advance(minutes(60));
+            settle().await;
+            assert_eq!(runs.load(Ordering::SeqCst), 1);
+            // 01:30 EST the next day
+            clock.advance(minutes(24 * 60));
+            settle().await;
+            assert_eq!(runs.load(Ordering::SeqCst), 2);
+        });
+    }
+
+    #[test]
+    fn test_schedule_oneof_task_with_result() {
+        let cw = Clockwork::default();
+        let result = cw.schedule_oneof_task_with_result(|| 6 * 7, Duration::from_millis(5));
+        let never = cw.schedule_oneof_task_with_result(|| 0, Duration::from_secs(60));
+
+        cw.handle().run(async { assert_eq!(result.await, Ok(42)) });
+        cw.handle().stop();
+        cw.handle().run(async { assert!(never.await.is_err()) });
+
+        // the duration counts from the call, not from when the task is first polled
+        let clock = Arc::new(ManualClock::new());
+        let rt = Arc::new(
+            tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap(),
+        );
+        let handle = ClockworkHandle::new(rt).with_clock(clock.clone());
+        let mut result = handle.schedule_oneof_task_with_result(|| 42, Duration::from_secs(10));
+        clock.advance(Duration::from_secs(10));
+        handle.run(async {
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
+            }
+        });
+        assert_eq!(result.try_recv(), Ok(42));
+    }
+
+    #[test]
+    fn test_scheduled_tasks() {
+        let clock = Arc::new(ManualClock::new());
+        let rt = Arc::new(
+            tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap(),
+        );
+        let handle = ClockworkHandle::new(rt).with_clock(clock.clone());
+        let runs = Arc::new(AtomicU32::new(0));
+        let start = handle.start_instant();
+
+        {
+            let runs = runs.clone();
+            handle.schedule_named_repeating_task(
+                "every",
+                move || {
+                    runs.fetch_add(1, Ordering::SeqCst);
+                },
+                Duration::from_secs(10),
+            );
+        }
+        handle.schedule_named_oneof_task("once", || {}, Duration::from_secs(5));
+
+        let settle = || async {
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
+            }
+        };
+        handle.run(async {
+            settle().await;
+            let tasks = handle.scheduled_tasks();
+            assert_eq!(tasks.len(), 2);
+            assert_eq!(tasks[0].name, "every");
+            assert_eq!(tasks[0].kind, TaskKind::Repeating);
+            assert_eq!(tasks[0].next_fire, start + Duration::from_secs(10));
+            assert_eq!(tasks[1].name, "once");
+            assert_eq!(tasks[1].kind, TaskKind::OneShot);
+            assert_eq!(tasks[1].next_fire, start + Duration::from_secs(5));
+            assert_eq!(
+                handle.next_fire("every"),
+                Some(start + Duration::from_secs(10))
+            );
+
+            assert!(handle.pause_task("every"));
+            clock.advance(Duration::from_secs(10));
+            settle().await;
+            let tasks = handle.scheduled_tasks();
+            assert_eq!(tasks.len(), 1);
+            assert!(tasks[0].paused);
+            assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+            assert!(handle.resume_task("every"));
+            clock.advance(Duration::from_secs(10));
+            settle().await;
+            assert_eq!(runs.load(Ordering::SeqCst), 2);
+            assert_eq!(
+                handle.next_fire("every"),
+                Some(start + Duration::from_secs(30))
+            );
+            assert_eq!(handle.next_fire("once"), None);
+        });
+
+        handle.stop();
+        assert!(handle.scheduled_tasks().is_empty());
+    }
+
+    #[test]
+    fn test_consumer_task() {
+        let cw = Clockwork::default();
+        let handle = cw.handle();
+        let (tx, rx) = cw.channel(4);
+        let received = Arc::new(Mutex::new(Vec::new()));
+
+        {
+            let received = received.clone();
+            cw.schedule_consumer_task(rx, move |n: u32| received.lock().unwrap().push(n));
+        }
+        cw.schedule_oneof_task(
+            move || {
+                for n in 0..3 {
+                    tx.try_send(n).unwrap();
+                }
+            },
+            Duration::from_millis(0),
+        );
+
+        // the producer drops its sender after running, which ends the consumer
+        handle.run(async {
+            assert!(handle.shutdown_graceful(Duration::from_secs(1)).await);
+        });
+        assert_eq!(*received.lock().unwrap(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_duplicate_named_task() {
+        let clock = Arc::new(ManualClock::new());
+        let rt = Arc::new(
+            tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap(),
+        );
+        let handle = ClockworkHandle::new(rt)
+            .with_clock(clock.clone())
+            .with_duplicate_task_policy(DuplicateTaskPolicy::REJECT);
+        let runs = Arc::new(Mutex::new(Vec::new()));
+        let task = |tag: &'static str| {
+            let runs = runs.clone();
+            move || runs.lock().unwrap().push(tag)
+        };
+
+        assert!(handle.schedule_named_repeating_task(
+            "job",
+            task("first"),
+            Duration::from_secs(10)
+        ));
+        assert!(!handle.schedule_named_repeating_task(
+            "job",
+            task("rejected"),
+            Duration::from_secs(10)
+        ));
+        let handle = handle.with_duplicate_task_policy(DuplicateTaskPolicy::REPLACE);
+        assert!(handle.schedule_named_repeating_task(
+            "job",
+            task("second"),
+            Duration::from_secs(10)
+        ));
+
+        let settle = || async {
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
+            }
+        };
+        handle.run(async {
+            settle().await;
+            clock.advance(Duration::from_secs(10));
+            settle().await;
+        });
+
+        // only the replacing task runs, once per tick
+        assert_eq!(*runs.lock().unwrap(), vec!["second", "second"]);
+        assert_eq!(handle.scheduled_tasks().len(), 1);
+    }
+
+    #[test]
+    fn test_persist_state() {
+        let path =
+            std::env::temp_dir().join(format!("clockwork_persist_{}.toml", std::process::id()));
+        let ran_at = SystemTime::now() - Duration::from_secs(10 * 60);
+        let millis = ran_at
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        std::fs::write(&path, format!("[last_run]\njob = {}", millis)).unwrap();
+
+        let clock = Arc::new(ManualClock::new());
+        let rt = Arc::new(
+            tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap(),
+        );
+        let handle = ClockworkHandle::new(rt)
+            .with_clock(clock.clone())
+            .with_persist_state(path.clone());
+        let runs = Arc::new(AtomicU32::new(0));
+        let start = handle.start_instant();
+
+        {
+            let runs = runs.clone();
+            handle.schedule_named_repeating_task(
+                "job",
+                move || {
+                    runs.fetch_add(1, Ordering::SeqCst);
+                },
+                Duration::from_secs(60 * 60),
+            );
+        }
+
+        let settle = || async {
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
+            }
+        };
+        handle.run(async {
+            // ran 10 minutes ago, not due for another 50
+            settle().await;
+            assert_eq!(runs.load(Ordering::SeqCst), 0);
+            let next_fire = handle.next_fire("job").unwrap();
+            assert!(next_fire > start + Duration::from_secs(49 * 60));
+            assert!(next_fire <= start + Duration::from_secs(50 * 60));
+
+            clock.advance(Duration::from_secs(50 * 60));
+            settle().await;
+            assert_eq!(runs.load(Ordering::SeqCst), 1);
+        });
+
+        let persisted = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(!persisted.contains(&millis.to_string()));
+    }
+
+    #[test]
+    fn test_repeating_task_in() {
+        let clock = Arc::new(ManualClock::new());
+        let rt = Arc::new(
+            tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap(),
+        );
+        let handle = ClockworkHandle::new(rt).with_clock(clock.clone());
+        let ticks = Arc::new(AtomicU32::new(0));
+
+        {
+            let ticks = ticks.clone();
+            handle.schedule_repeating_task_in(
+                move || {
+                    ticks.fetch_add(1, Ordering::SeqCst);
+                },
+                Duration::from_secs(5),
+                Duration::from_secs(10),
+            );
+        }
+
+        let settle = || async {
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
+            }
+        };
+        handle.run(async {
+            settle().await;
+            assert_eq!(ticks.load(Ordering::SeqCst), 0);
+
+            clock.advance(Duration::from_secs(5));
+            settle().await;
+            assert_eq!(ticks.load(Ordering::SeqCst), 1);
+
+            clock.advance(Duration::from_secs(10));
+            settle().await;
+            assert_eq!(ticks.load(Ordering::SeqCst), 2);
+        });
+    }
+
+    #[test]
+    fn test_boost_task() {
+        let clock = Arc::new(ManualClock::new());
+        let rt = Arc::new(
+            tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap(),
+        );
+        let handle = ClockworkHandle::new(rt).with_clock(clock.clone());
+        let runs = Arc::new(AtomicU32::new(0));
+
+        {
+            let runs = runs.clone();
+            handle.schedule_named_repeating_task(
+                "poll",
+                move || {
+                    runs.fetch_add(1, Ordering::SeqCst);
+                },
+                Duration::from_secs(60),
+            );
+        }
+        assert!(!handle.boost_task("missing", Duration::from_secs(10), Duration::from_secs(25)));
+
+        let settle = || async {
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
+            }
+        };
+        handle.run(async {
+            settle().await;
+            assert_eq!(runs.load(Ordering::SeqCst), 1);
+            assert!(handle.boost_task("poll", Duration::from_secs(10), Duration::from_secs(25)));
+            assert_eq!(
+                handle.next_fire("poll"),
+                Some(clock.now() + Duration::from_secs(10))
+            );
+
+            // boosted runs at 10s and 20s
+            for _ in 0..2 {
+                clock.advance(Duration::from_secs(10));
+                settle().await;
+            }
+            assert_eq!(runs.load(Ordering::SeqCst), 3);
+
+            // the boost ends at 25s, the next run comes 60s after the last boosted one
+            clock.advance(Duration::from_secs(5));
+            settle().await;
+            clock.advance(Duration::from_secs(5));
+            settle().await;
+            assert_eq!(runs.load(Ordering::SeqCst), 3);
+            let scheduled = handle.scheduled_tasks();
+            assert_eq!(scheduled[0].period, Some(Duration::from_secs(60)));
+
+            clock.advance(Duration::from_secs(50));
+            settle().await;
+            assert_eq!(runs.load(Ordering::SeqCst), 4);
+            clock.advance(Duration::from_secs(60));
+            settle().await;
+            assert_eq!(runs.load(Ordering::SeqCst), 5);
+        });
+    }
+
+    #[test]
+    fn test_child_handles() {
+        let clock = Arc::new(ManualClock::new());
+        let rt = Arc::new(
+            tokio::runtime::Builder::new_current_thread()
+                .enable_time()
+                .build()
+                .unwrap(),
+        );
+        let handle = ClockworkHandle::new(rt).with_clock(clock.clone());
+        let feature = handle.child();
+        let sub_feature = feature.child();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let counter = |handle: &ClockworkHandle| {
+            let count = Arc::new(AtomicU32::new(0));
+            let ticks = count.clone();
+            handle.schedule_repeating_task(
+                move || {
+                    ticks.fetch_add(1, Ordering::SeqCst);
+                },
+                Duration::from_secs(10),
+            );
+            count
+        };
+        for (scope, name) in [
+            (&handle, "root"),
+            (&feature, "feature"),
+            (&sub_feature, "sub"),
+        ] {
+            let events = events.clone();
+            scope.register_on_stop(move || events.lock().unwrap().push(name));
+        }
+
+        let settle = || async {
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
+            }
+        };
+        handle.run(async {
+            let (root_ticks, feature_ticks) = (counter(&handle), counter(&sub_feature));
+            clock.advance(Duration::from_secs(10));
+            settle().await;
+            assert_eq!(root_ticks.load(Ordering::SeqCst), 2);
+            assert_eq!(feature_ticks.load(Ordering::SeqCst), 2);
+
+            // stopping the child stops its subtree only
+            feature.stop();
+            assert!(feature.stopped() && sub_feature.stopped());
+            assert_eq!(sub_feature.stop_reason(), Some(StopReason::Requested));
+            assert!(!handle.stopped());
+            assert_eq!(*events.lock().unwrap(), vec!["sub", "feature"]);
+            clock.advance(Duration::from_secs(10));
+            settle().await;
+            assert_eq!(root_ticks.load(Ordering::SeqCst), 3);
+            assert_eq!(feature_ticks.load(Ordering::SeqCst), 2);
+            assert_eq!(sub_feature.spawn_task(async {}), Err(SpawnError::Stopped));
+
+            // a child only drains its own tasks, the parent's keep it from being idle
+            let other = handle.child();
+            counter(&other);
+            assert!(other.shutdown_graceful(Duration::from_secs(1)).await);
+            assert!(other.stopped() && !handle.stopped());
+        });
+
+        // stopping the parent stops its children
+        let late = handle.child();
+        handle.stop();
+        assert!(late.stopped());
+        assert_eq!(*events.lock().unwrap(), vec!["sub", "feature", "root"]);
+        assert!(handle.child().stopped());
+    }
+
+    #[test]
+    fn test_run_inside_runtime() {
+        let outer = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .build()
+            .unwrap();
+        let ran = outer.block_on(async {
+            let cw = Clockwork::default().with_run_for(Duration::from_millis(10));
+            let ran = Arc::new(AtomicBool::new(false));
+            {
+                let ran = ran.clone();
+                cw.schedule_oneof_task(move || ran.store(true, Ordering::SeqCst), Duration::ZERO);
+            }
+            cw.run(&|_handle: ClockworkHandle| {});
+            ran.load(Ordering::SeqCst)
+        });
+        assert!(ran);
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot block the current-thread tokio runtime")]
+    fn test_run_inside_current_thread_runtime() {
+        let outer = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        outer.block_on(async {
+            let cw = Clockwork::default().with_run_for(Duration::from_millis(10));
+            cw.run(&|_handle: ClockworkHandle| {});
+        });
+    }
+
+    #[test]
+    fn test_gated_task() {
+        let clock = Arc::new(ManualClock::new());
+        let rt = Arc::new(
+            tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap(),
+        );
+        let handle = ClockworkHandle::new(rt).with_clock(clock.clone());
+        let runs = Arc::new(AtomicU32::new(0));
+        let period = Duration::from_secs(10);
+
+        let settle = || async {
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
+            }
+        };
+        handle.run(async {
+            let counter = runs.clone();
+            handle.schedule_repeating_task_gated(
+                "reports",
+                move || {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                },
+                period,
+            );
+            settle().await;
+            // a flag that was never set is off
+            assert_eq!(runs.load(Ordering::SeqCst), 0);
+
+            handle.set_flag("reports", true);
+            clock.advance(period);
+            settle().await;
+            assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+            // toggled through a clone of the store, e.g. by an external source
+            handle.flags().set("reports", false);
+            clock.advance(period);
+            settle().await;
+            assert_eq!(runs.load(Ordering::SeqCst), 1);
+        });
+        handle.stop();
+
+        let conf: ClockworkConfig = toml::from_str("[flags]\nreports = true").unwrap();
+        let cw = Clockwork::from(conf);
+        assert!(cw.handle().flags().is_enabled("reports"));
+    }
+
+    #[test]
+    fn test_task_groups() {
+        let clock = Arc::new(ManualClock::new());
+        let rt = Arc::new(
+            tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap(),
+        );
+        let handle = ClockworkHandle::new(rt).with_clock(clock.clone());
+        let reporting = handle.in_group("reporting");
+        let runs = Arc::new(Mutex::new(Vec::new()));
+        let record = |name: &'static str| {
+            let runs = runs.clone();
+            move || runs.lock().unwrap().push(name)
+        };
+        let period = Duration::from_secs(10);
+
+        let settle = || async {
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
+            }
+        };
+        handle.run(async {
+            reporting.schedule_named_repeating_task("daily", record("daily"), period);
+            reporting.schedule_named_oneof_task("weekly", record("weekly"), period * 2);
+            handle.schedule_named_repeating_task("ingest", record("ingest"), period);
+            settle().await;
+            runs.lock().unwrap().clear();
+
+            let tasks = handle.scheduled_tasks();
+            assert_eq!(tasks[0].group.as_deref(), Some("reporting"));
+            assert_eq!(tasks[1].group, None);
+
+            assert_eq!(handle.pause_group("reporting"), 2);
+            clock.advance(period);
+            settle().await;
+            assert_eq!(*runs.lock().unwrap(), vec!["ingest"]);
+
+            assert_eq!(handle.resume_group("reporting"), 2);
+            assert_eq!(handle.cancel_group("reporting"), 2);
+            assert_eq!(handle.cancel_group("reporting"), 0);
+            assert_eq!(handle.pause_group("unknown"), 0);
+            clock.advance(period);
+            settle().await;
+            assert_eq!(*runs.lock().unwrap(), vec!["ingest", "ingest"]);
+            let names: Vec<_> = handle
+                .scheduled_tasks()
+                .into_iter()
+                .map(|t| t.name)
+                .collect();
+            assert_eq!(names, vec!["ingest"]);
+            assert_eq!(handle.task_history("daily").len(), 1);
+
+            // a cancelled task's name can be scheduled again
+            assert!(reporting.schedule_named_repeating_task("daily", record("daily"), period));
+            settle().await;
+            assert_eq!(runs.lock().unwrap().last(), Some(&"daily"));
+        });
+        handle.stop();
+    }
+
+    #[test]
+    fn test_cancel_oneof_task() {
+        let clock = Arc::new(ManualClock::new());
+        let rt = Arc::new(
+            tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap(),
+        );
+        let handle = ClockworkHandle::new(rt).with_clock(clock.clone());
+        let defused = Arc::new(AtomicU32::new(0));
+        let fired = Arc::new(AtomicU32::new(0));
+
+        let settle = || async {
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
+            }
+        };
+        handle.run(async {
+            let timeout = {
+                let defused = defused.clone();
+                handle.schedule_oneof_task(
+                    move || {
+                        defused.fetch_add(1, Ordering::SeqCst);
+                    },
+                    Duration::from_secs(10),
+                )
+            };
+            let other = {
+                let fired = fired.clone();
+                handle.schedule_oneof_task(
+                    move || {
+                        fired.fetch_add(1, Ordering::SeqCst);
+                    },
+                    Duration::from_secs(10),
+                )
+            };
+
+            clock.advance(Duration::from_secs(5));
+            settle().await;
+            timeout.cancel();
+            assert!(timeout.is_cancelled());
+            assert!(!other.is_cancelled());
+
+            clock.advance(Duration::from_secs(5));
+            settle().await;
+            assert_eq!(defused.load(Ordering::SeqCst), 0);
+            assert_eq!(fired.load(Ordering::SeqCst), 1);
+        });
+
+        handle.stop();
+        assert!(handle
+            .schedule_oneof_task(|| {}, Duration::ZERO)
+            .is_cancelled());
+    }
+
+    #[test]
+    fn test_schedule_batch() {
+        let clock = Arc::new(ManualClock::new());
+        let rt = Arc::new(
+            tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap(),
+        );
+        let handle = ClockworkHandle::new(rt).with_clock(clock.clone());
+        let fast = Arc::new(AtomicU32::new(0));
+        let slow = Arc::new(AtomicU32::new(0));
+
+        let tasks = {
+            let fast = fast.clone();
+            let slow = slow.clone();
+            handle.schedule_batch(vec![
+                (
+                    Duration::from_secs(10),
+                    Box::new(move || {
+                        fast.fetch_add(1, Ordering::SeqCst);
+                    }),
+                ),
+                (
+                    Duration::from_secs(30),
+                    Box::new(move || {
+                        slow.fetch_add(1, Ordering::SeqCst);
+                    }),
+                ),
+            ])
+        };
+        assert_eq!(tasks.len(), 2);
+
+        let settle = || async {
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
+            }
+        };
+        handle.run(async {
+            settle().await;
+            for _ in 0..3 {
+                clock.advance(Duration::from_secs(10));
+                settle().await;
+            }
+            assert_eq!(fast.load(Ordering::SeqCst), 4);
+            assert_eq!(slow.load(Ordering::SeqCst), 2);
+
+            tasks[0].cancel();
+            clock.advance(Duration::from_secs(30));
+            settle().await;
+            assert_eq!(fast.load(Ordering::SeqCst), 4);
+            assert_eq!(slow.load(Ordering::SeqCst), 3);
+        });
+
+        handle.stop();
+        let tasks = handle.schedule_batch(vec![(Duration::from_secs(10), Box::new(|| {}))]);
+        assert!(tasks[0].is_cancelled());
+    }
+
+    #[test]
+    fn test_schedule_repeating_task_from_start() {
+        let clock = Arc::new(ManualClock::new());
+        let rt = Arc::new(
+            tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap(),
+        );
+        let handle = ClockworkHandle::new(rt).with_clock(clock.clone());
+        let early = Arc::new(AtomicU32::new(0));
+        let late = Arc::new(AtomicU32::new(0));
+
+        {
+            let early = early.clone();
+            handle.schedule_repeating_task_from_start(
+                move || {
+                    early.fetch_add(1, Ordering::SeqCst);
+                },
+                Duration::from_secs(5),
+                Duration::from_secs(10),
+            );
+        }
+
+        let settle = || async {
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
+            }
+        };
+        handle.run(async {
+            // scheduled 17s after start, the ticks at 5s and 15s are skipped
+            clock.advance(Duration::from_secs(17));
+            settle().await;
+            assert_eq!(early.load(Ordering::SeqCst), 2);
+
+            let late_ticks = late.clone();
+            handle.schedule_repeating_task_from_start(
+                move || {
+                    late_ticks.fetch_add(1, Ordering::SeqCst);
+                },
+                Duration::from_secs(5),
+                Duration::from_secs(10),
+            );
+            settle().await;
+            assert_eq!(late.load(Ordering::SeqCst), 0);
+
+            // both tasks tick at 25s
+            clock.advance(Duration::from_secs(8));
+            settle().await;
+            assert_eq!(early.load(Ordering::SeqCst), 3);
+            assert_eq!(late.load(Ordering::SeqCst), 1);
+        });
+    }
+
+    #[test]
+    fn test_export_crontab_manual_clock() {
+        // 1970-01-02 00:00 UTC
+        let clock = Arc::new(ManualClock::at_system_time(
+            SystemTime::UNIX_EPOCH + Duration::from_secs(24 * 60 * 60),
+        ));
+        let handle = Clockwork::default().handle().with_clock(clock.clone());
+
+        clock.advance(Duration::from_secs(7 * 60));
+        handle.schedule_named_repeating_task("quarterly", || {}, Duration::from_secs(15 * 60));
+        assert_eq!(
+            handle.export_crontab(),
+            "# Exported from clockwork, times are in UTC\n7,22,37,52 * * * * quarterly\n"
+        );
+    }
+
+    #[test]
+    fn test_schedule_repeating_task_from_start_many_missed_ticks() {
+        let clock = Arc::new(ManualClock::new());
+        let rt = Arc::new(
+            tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap(),
+        );
+        let handle = ClockworkHandle::new(rt).with_clock(clock.clone());
+        let ticks = Arc::new(AtomicU32::new(0));
+
+        let settle = || async {
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
+            }
+        };
+        handle.run(async {
+            // more than `u32::MAX` ticks of 1µs have been missed
+            clock.advance(Duration::from_secs(5000) + Duration::from_nanos(500));
+            let counted = ticks.clone();
+            handle.schedule_repeating_task_from_start(
+                move || {
+                    counted.fetch_add(1, Ordering::SeqCst);
+                },
+                Duration::ZERO,
+                Duration::from_micros(1),
+            );
+            settle().await;
+            assert_eq!(ticks.load(Ordering::SeqCst), 0);
+
+            // the next tick is the one at 5000.000001s
+            clock.advance(Duration::from_nanos(500));
+            settle().await;
+            assert_eq!(ticks.load(Ordering::SeqCst), 1);
+        });
+
+        // a period too long for the skipped ticks to add up does not panic
+        handle.schedule_repeating_task_from_start(|| {}, Duration::ZERO, Duration::MAX);
+    }
+
+    #[cfg(feature = "logging")]
+    #[test]
+    fn test_dry_run_log() {
+        struct Captured(Arc<Mutex<Vec<u8>>>);
+
+        impl std::io::Write for Captured {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let clock = Arc::new(ManualClock::at_system_time(
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1000),
+        ));
+        let handle = Clockwork::default()
+            .handle()
+            .with_clock(clock)
+            .with_dry_run(true);
+
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let subscriber = {
+            let output = output.clone();
+            tracing_subscriber::fmt()
+                .with_writer(move || Captured(output.clone()))
+                .with_ansi(false)
+                .finish()
+        };
+        tracing::subscriber::with_default(subscriber, || {
+            handle.schedule_named_oneof_task("once", || {}, Duration::from_secs(60));
+        });
+
+        // the first fire is on the handle's clock, not the wall clock
+        let output = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        assert!(output.contains("Dry run, task not scheduled"));
+        assert!(output.contains(&format!(
+            "first_fire={:?}",
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1060)
+        )));
+    }
+
+    #[cfg(feature = "logging")]
+    #[test]
+    fn test_late_tick_warning() {
+        struct Captured(Arc<Mutex<Vec<u8>>>);
+
+        impl std::io::Write for Captured {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let clock = Arc::new(ManualClock::new());
+        let rt = Arc::new(
+            tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap(),
+        );
+        let handle = ClockworkHandle::new(rt)
+            .with_clock(clock.clone())
+            .with_late_tick_threshold(Duration::from_secs(1));
+        handle.schedule_named_repeating_task("report", || {}, Duration::from_secs(10));
+
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let subscriber = {
+            let output = output.clone();
+            tracing_subscriber::fmt()
+                .with_writer(move || Captured(output.clone()))
+                .with_ansi(false)
+                .finish()
+        };
+        let settle = || async {
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
+            }
+        };
+        let warnings = || {
+            String::from_utf8(output.lock().unwrap().clone())
+                .unwrap()
+                .matches("Scheduled task ran late")
+                .count()
+        };
+
+        tracing::subscriber::with_default(subscriber, || {
+            handle.run(async {
+                // on time
+                settle().await;
+                clock.advance(Duration::from_secs(10));
+                settle().await;
+                assert_eq!(warnings(), 0);
+
+                // the tick at 20s runs 5s late
+                clock.advance(Duration::from_secs(15));
+                settle().await;
+                assert_eq!(warnings(), 1);
+            })
+        });
+        let output = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        assert!(output.contains("task=\"report\""));
+        assert!(output.contains("lateness=5s"));
+    }
+
+    #[test]
+    fn test_schedule_repeating_task_random() {
+        let clock = Arc::new(ManualClock::new());
+        let rt = Arc::new(
+            tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap(),
+        );
+        let handle = ClockworkHandle::new(rt).with_clock(clock.clone());
+        let count = Arc::new(AtomicU32::new(0));
+
+        {
+            let count = count.clone();
+            handle.schedule_repeating_task_random(
+                move || {
+                    count.fetch_add(1, Ordering::SeqCst);
+                },
+                Duration::from_secs(10),
+                Duration::from_secs(20),
+            );
+        }
+
+        let settle = || async {
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
+            }
+        };
+        handle.run(async {
+            settle().await;
+            clock.advance(Duration::from_secs(9));
+            settle().await;
+            assert_eq!(count.load(Ordering::SeqCst), 0);
+
+            // every wait is at least 10s and at most 20s, so each 20s step runs exactly once
+            for runs in 1..=5 {
+                clock.advance(Duration::from_secs(11));
+                settle().await;
+                clock.advance(Duration::from_secs(9));
+                settle().await;
+                assert_eq!(count.load(Ordering::SeqCst), runs);
+            }
+        });
+    }
+
+    #[test]
+    fn test_schedule_boundaries() {
+        let clock = Arc::new(ManualClock::new());
+        let rt = Arc::new(
+            tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap(),
+        );
+        let handle = ClockworkHandle::new(rt).with_clock(clock.clone());
+        let repeating = Arc::new(AtomicU32::new(0));
+        let oneof = Arc::new(AtomicU32::new(0));
+
+        let settle = || async {
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
+            }
+        };
+        handle.run(async {
+            clock.advance(Duration::from_secs(100));
+
+            // started 95s ago, but the 9 ticks since are not caught up on
+            let ticks = repeating.clone();
+            handle.schedule_repeating_task_at(
+                move || {
+                    ticks.fetch_add(1, Ordering::SeqCst);
+                },
+                clock.now() - Duration::from_secs(95),
+                Duration::from_secs(10),
+                MissedTickBehavior::Burst,
+            );
+            let runs = oneof.clone();
+            handle.schedule_oneof_task(
+                move || {
+                    runs.fetch_add(1, Ordering::SeqCst);
+                },
+                Duration::ZERO,
+            );
+            assert_eq!(oneof.load(Ordering::SeqCst), 0);
+
+            settle().await;
+            assert_eq!(repeating.load(Ordering::SeqCst), 1);
+            assert_eq!(oneof.load(Ordering::SeqCst), 1);
+
+            // the next tick is a period after the task was scheduled
+            clock.advance(Duration::from_secs(9));
+            settle().await;
+            assert_eq!(repeating.load(Ordering::SeqCst), 1);
+            clock.advance(Duration::from_secs(1));
+            settle().await;
+            assert_eq!(repeating.load(Ordering::SeqCst), 2);
+
+            // the delay counts from the call, not from when the task is first polled
+            let runs = oneof.clone();
+            handle.schedule_oneof_task(
+                move || {
+                    runs.fetch_add(1, Ordering::SeqCst);
+                },
+                Duration::from_secs(5),
+            );
+            clock.advance(Duration::from_secs(5));
+            settle().await;
+            assert_eq!(oneof.load(Ordering::SeqCst), 2);
+        });
+    }
+
+    #[test]
+    fn test_missed_tick_behavior() {
+        let clock = Arc::new(ManualClock::new());
+        let rt = Arc::new(
+            tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap(),
+        );
+        let handle = ClockworkHandle::new(rt).with_clock(clock.clone());
+        let behaviors = [
+            MissedTickBehavior::Burst,
+            MissedTickBehavior::Delay,
+            MissedTickBehavior::Skip,
+        ];
+        let ticks: Vec<_> = behaviors
+            .iter()
+            .map(|_| Arc::new(AtomicU32::new(0)))
+            .collect();
+
+        for (behavior, ticks) in behaviors.iter().zip(&ticks) {
+            let ticks = ticks.clone();
+            handle.schedule_repeating_task_at(
+                move || {
+                    ticks.fetch_add(1, Ordering::SeqCst);
+                },
+                clock.now(),
+                Duration::from_secs(10),
+                *behavior,
+            );
+        }
+
+        let settle = || async {
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
+            }
+        };
+        let counts = || {
+            ticks
+                .iter()
+                .map(|t| t.load(Ordering::SeqCst))
+                .collect::<Vec<_>>()
+        };
+        handle.run(async {
+            settle().await;
+            assert_eq!(counts(), [1, 1, 1]);
+
+            // the ticks at 10s, 20s and 30s are all late
+            clock.advance(Duration::from_secs(35));
+            settle().await;
+            assert_eq!(counts(), [4, 2, 2]);
+
+            // Skip stays aligned with the start, Delay carries on 10s after the late tick
+            clock.advance(Duration::from_secs(5));
+            settle().await;
+            assert_eq!(counts(), [5, 2, 3]);
+
+            clock.advance(Duration::from_secs(5));
+            settle().await;
+            assert_eq!(counts(), [5, 3, 3]);
+        });
+    }
+
+    #[test]
+    fn test_stop_when_idle() {
+        let clock = Arc::new(ManualClock::new());
+        let rt = Arc::new(
+            tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap(),
+        );
+        let handle = ClockworkHandle::new(rt).with_clock(clock.clone());
+        handle.schedule_oneof_task(|| {}, Duration::from_secs(30));
+
+        let settle = || async {
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
+            }
+        };
+        handle.run(async {
+            let watchdog = handle.clone();
+            handle
+                .tokio_handle
+                .spawn(async move { watchdog.stop_when_idle(Duration::from_secs(60)).await });
+            settle().await;
+
+            // the one-shot task fires at 30s, which pushes the timeout back to 90s
+            clock.advance(Duration::from_secs(30));
+            settle().await;
+            assert_eq!(handle.millis_since_last_tick(), 0);
+            clock.advance(Duration::from_secs(50));
+            settle().await;
+            assert!(!handle.stopped());
+            assert_eq!(handle.millis_since_last_tick(), 50_000);
+
+            clock.advance(Duration::from_secs(10));
+            settle().await;
+            assert!(handle.stopped());
+        });
+    }
+
+    #[test]
+    fn test_spawn_local_task() {
+        let cw = Clockwork::default();
+        let handle = cw.handle();
+        let ran = Arc::new(AtomicBool::new(false));
+
+        {
+            let ran = ran.clone();
+            let stopper = handle.clone();
+            assert!(cw.spawn_local_task(move || async move {
+                // `Rc` makes the future !Send
+                let shared = std::rc::Rc::new(std::cell::RefCell::new(0));
+                sleep(Duration::from_millis(1)).await;
+                *shared.borrow_mut() += 1;
+                ran.store(*shared.borrow() == 1, Ordering::SeqCst);
+                stopper.stop();
+            }));
+        }
+
+        cw.run(&|_| {});
+        assert!(ran.load(Ordering::SeqCst));
+        assert!(!handle.spawn_local_task(|| async {}));
     }
 }
@@ -3,17 +3,41 @@ use super::App;
 use super::Clockwork;
 use super::ClockworkConfig;
 use crate::clockwork::ClockworkHandle;
+use crate::clockwork_error::ClockworkError;
+use crate::clockwork_keys::unknown_keys;
+use crate::clockwork_lifecycle::ShutdownReport;
 #[cfg(feature = "logging")]
 use crate::clockwork_logger::{ClockworkLogger, LoggerConfig};
 use serde::de::DeserializeOwned;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
 use std::fs::File;
 use std::io::{BufReader, Read};
+use std::panic::{catch_unwind, AssertUnwindSafe};
 use std::path::PathBuf;
 
+/// How long `ClockworkApp::from_url` waits for the config
+#[cfg(feature = "http-config")]
+const URL_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// How long `ClockworkApp::restart` waits for the old runtime's tasks to exit
+const RESTART_TEARDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// A place to load the config from, see `ClockworkApp::from_sources`
+/// EnvPath - the file at the path held by the named environment variable
+/// Path - the file at the path
+/// Str - the config itself, e.g. a default baked into the executable
+#[derive(Clone, Debug)]
+pub enum ConfigSource {
+    EnvPath(String),
+    Path(PathBuf),
+    Str(String),
+}
+
 /// A type that stores information required to configure a `ClockworkApp`
 /// `T` has to be a type that implements `Deserialize`
-#[derive(Deserialize)]
+/// See `ClockworkApp::app_config` for the config an app was built from
+#[derive(Deserialize, Serialize, Clone)]
 pub struct ClockworkAppConfig<T> {
     #[serde(default)]
     pub(crate) clockwork: ClockworkConfig,
@@ -23,6 +47,149 @@ pub struct ClockworkAppConfig<T> {
     pub(crate) app: T,
 }
 
+impl<T> ClockworkAppConfig<T> {
+    /// The `clockwork` section
+    pub fn clockwork(&self) -> &ClockworkConfig {
+        &self.clockwork
+    }
+
+    /// The `logger` section
+    #[cfg(feature = "logging")]
+    pub fn logger(&self) -> &LoggerConfig {
+        &self.logger
+    }
+
+    /// The `app` section
+    pub fn app(&self) -> &T {
+        &self.app
+    }
+
+    /// Swaps out the app section for `app`, returning the new config and the original app section
+    fn replace_app<U>(self, app: U) -> (ClockworkAppConfig<U>, T) {
+        let conf = ClockworkAppConfig {
+            clockwork: self.clockwork,
+            #[cfg(feature = "logging")]
+            logger: self.logger,
+            app,
+        };
+
+        (conf, self.app)
+    }
+}
+
+impl Default for ClockworkAppConfig<toml::Value> {
+    fn default() -> Self {
+        Self {
+            clockwork: Default::default(),
+            #[cfg(feature = "logging")]
+            logger: Default::default(),
+            app: toml::Value::Table(Default::default()),
+        }
+    }
+}
+
+/// Checks the `clockwork` and `logger` sections of `raw` for unknown keys (see
+/// `deny_unknown_keys`), as well as for sections other than those and `app`
+fn check_keys(raw: &toml::Value, clockwork: &ClockworkConfig) -> Result<(), toml::de::Error> {
+    let mut unknown = Vec::new();
+    if let toml::Value::Table(sections) = raw {
+        for (section, value) in sections {
+            match section.as_str() {
+                "clockwork" => unknown.extend(unknown_keys::<ClockworkConfig>(value, section)),
+                #[cfg(feature = "logging")]
+                "logger" => unknown.extend(unknown_keys::<LoggerConfig>(value, section)),
+                #[cfg(not(feature = "logging"))]
+                "logger" => {}
+                "app" => {}
+                _ => unknown.push(section.clone()),
+            }
+        }
+    }
+
+    if unknown.is_empty() {
+        return Ok(());
+    }
+    if clockwork.deny_unknown_keys {
+        return Err(serde::de::Error::custom(format!(
+            "unknown config keys: {}",
+            unknown.join(", ")
+        )));
+    }
+    // The logger is not up yet, the warning can only go to stderr
+    for key in unknown {
+        eprintln!("Warning: unknown config key `{}` ignored", key);
+    }
+    Ok(())
+}
+
+/// Exit code for the outcome of `ClockworkApp::start`, see `start_and_exit`
+fn exit_code(result: &std::thread::Result<Result<(), Box<dyn Error>>>) -> i32 {
+    match result {
+        Ok(Ok(())) => 0,
+        Ok(Err(_)) => 1,
+        Err(_) => 101,
+    }
+}
+
+/// Deep-merges `overlay` into `base`, values in `overlay` win.
+/// Tables are merged recursively, anything else is replaced as a whole.
+fn merge_toml(base: &mut toml::Value, overlay: toml::Value) {
+    match (base, overlay) {
+        (toml::Value::Table(base), toml::Value::Table(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_toml(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Parses a patch for `ClockworkApp::apply_patch`, as JSON if it is a JSON object (`logging` or
+/// `http-config` feature only), as TOML otherwise
+fn parse_patch(patch: &str) -> Result<toml::Value, toml::de::Error> {
+    #[cfg(any(feature = "logging", feature = "http-config"))]
+    if patch.trim_start().starts_with('{') {
+        return serde_json::from_str(patch).map_err(serde::de::Error::custom);
+    }
+
+    toml::from_str(patch)
+}
+
+/// Splits `value` into its leaves, i.e. everything but tables, along with their path
+fn leaves(
+    value: toml::Value,
+    path: &mut Vec<String>,
+    leaves_out: &mut Vec<(Vec<String>, toml::Value)>,
+) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table {
+                path.push(key);
+                leaves(value, path, leaves_out);
+                path.pop();
+            }
+        }
+        value => leaves_out.push((path.clone(), value)),
+    }
+}
+
+/// Whether the config key at `path` can be changed while the app runs, see
+/// `ClockworkApp::apply_patch`
+fn is_hot_reloadable(path: &[String]) -> bool {
+    let path: Vec<&str> = path.iter().map(String::as_str).collect();
+    match path[..] {
+        #[cfg(feature = "logging")]
+        ["logger", "log_level"] => true,
+        ["clockwork", "task_periods", _] => true,
+        _ => false,
+    }
+}
+
 /// An application using the `Clockwork` run time
 /// Maintains a `Clockwork` instance and the Logger (if `logging` feature is enabled)
 /// `T` has to be a type that implements `App`
@@ -31,26 +198,36 @@ pub struct ClockworkApp<T: App> {
     #[cfg(feature = "logging")]
     logger: Option<ClockworkLogger>,
     app: T,
+    config: ClockworkAppConfig<toml::Value>,
 }
 
 impl<T: App> ClockworkApp<T> {
     /// FIXME: There may be a more elegant way to write these two functions
     /// Constructs a `ClockworkApp` instance from a `ClockworkAppConfig` with a logger
+    /// `raw_app` is the app section as written, retained for `effective_config_toml`
     #[cfg(feature = "logging")]
-    pub(crate) fn from_config(conf: ClockworkAppConfig<T::Config>) -> Self {
+    pub(crate) fn from_config(conf: ClockworkAppConfig<T::Config>, raw_app: toml::Value) -> Self {
+        let (conf, app_conf) = conf.replace_app(raw_app);
         Self {
-            cw: Clockwork::from(conf.clockwork),
-            logger: Some(ClockworkLogger::from(conf.logger)),
-            app: T::from(conf.app),
+            cw: Clockwork::from(conf.clockwork.clone()),
+            logger: match conf.logger.install_subscriber {
+                true => Some(ClockworkLogger::from(conf.logger.clone())),
+                false => None,
+            },
+            app: T::from(app_conf),
+            config: conf,
         }
     }
 
     /// Constructs a `ClockworkApp` instance from a `ClockworkAppConfig`
+    /// `raw_app` is the app section as written, retained for `effective_config_toml`
     #[cfg(not(feature = "logging"))]
-    pub(crate) fn from_config(conf: ClockworkAppConfig<T::Config>) -> Self {
+    pub(crate) fn from_config(conf: ClockworkAppConfig<T::Config>, raw_app: toml::Value) -> Self {
+        let (conf, app_conf) = conf.replace_app(raw_app);
         Self {
-            cw: Clockwork::from(conf.clockwork),
-            app: T::from(conf.app),
+            cw: Clockwork::from(conf.clockwork.clone()),
+            app: T::from(app_conf),
+            config: conf,
         }
     }
 
@@ -79,10 +256,157 @@ impl<T: App> ClockworkApp<T> {
     where
         T::Config: DeserializeOwned,
     {
-        let conf: ClockworkAppConfig<T::Config> =
+        let raw: toml::Value =
             toml::from_str(conf_string.as_str()).expect("Failed to parse config!");
 
-        Self::from_config(conf)
+        Self::from_config_value(raw)
+    }
+
+    /// Same as `from_config_str`, but returns an error instead of panicking if the config cannot
+    /// be parsed
+    pub fn try_from_config_str(conf_string: String) -> Result<Self, ClockworkError>
+    where
+        T::Config: DeserializeOwned,
+    {
+        toml::from_str(conf_string.as_str())
+            .and_then(Self::try_from_config_value)
+            .map_err(|source| ClockworkError::Parse { path: None, source })
+    }
+
+    /// Constructs a `ClockworkApp` instance from a config fetched over HTTP(S) from `url`
+    /// (`http-config` feature only), e.g. from a centralized config store.
+    /// The config is read as JSON if the server says it is (`Content-Type: application/json`) or
+    /// `url` ends in `.json`, as TOML otherwise. The request times out after 30 seconds.
+    /// Panics if the config cannot be fetched or parsed, see `try_from_url`.
+    #[cfg(feature = "http-config")]
+    pub fn from_url(url: &str) -> Self
+    where
+        T::Config: DeserializeOwned,
+    {
+        Self::try_from_url(url).unwrap_or_else(|e| panic!("Failed to load config: {}!", e))
+    }
+
+    /// Same as `from_url`, but returns an error instead of panicking if the config cannot be
+    /// fetched (including non-success responses and timeouts) or parsed
+    #[cfg(feature = "http-config")]
+    pub fn try_from_url(url: &str) -> Result<Self, ClockworkError>
+    where
+        T::Config: DeserializeOwned,
+    {
+        let fetch_error = |source| ClockworkError::Fetch {
+            url: url.to_string(),
+            source,
+        };
+        let response = reqwest::blocking::Client::builder()
+            .timeout(URL_TIMEOUT)
+            .build()
+            .and_then(|client| client.get(url).send())
+            .map_err(fetch_error)?;
+        if !response.status().is_success() {
+            return Err(ClockworkError::Status {
+                url: url.to_string(),
+                status: response.status().as_u16(),
+            });
+        }
+
+        let is_json = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .is_some_and(|content_type| content_type.contains("json"))
+            || url.ends_with(".json");
+        let body = response.text().map_err(fetch_error)?;
+        let raw = match is_json {
+            true => serde_json::from_str(&body).map_err(serde::de::Error::custom),
+            false => toml::from_str(&body),
+        };
+
+        raw.and_then(Self::try_from_config_value)
+            .map_err(|source| ClockworkError::Parse { path: None, source })
+    }
+
+    /// Constructs a `ClockworkApp` instance from an already parsed config
+    fn from_config_value(raw: toml::Value) -> Self
+    where
+        T::Config: DeserializeOwned,
+    {
+        Self::try_from_config_value(raw).expect("Failed to parse config!")
+    }
+
+    fn try_from_config_value(raw: toml::Value) -> Result<Self, toml::de::Error>
+    where
+        T::Config: DeserializeOwned,
+    {
+        let raw_app = raw
+            .get("app")
+            .cloned()
+            .unwrap_or_else(|| toml::Value::Table(Default::default()));
+        let conf: ClockworkAppConfig<T::Config> = raw.clone().try_into()?;
+        check_keys(&raw, &conf.clockwork)?;
+
+        Ok(Self::from_config(conf, raw_app))
+    }
+
+    /// Constructs a `ClockworkApp` instance from a config String, where the `clockwork`, `logger`
+    /// and `app` sections are nested under `root` rather than at the top level.
+    /// `root` may be a dotted path to a nested table, e.g. `services.scheduler`.
+    /// ```
+    /// use clockwork::{ClockworkApp, Configurable, Runnable, ClockworkHandle};
+    /// use serde::Deserialize;
+    /// #[derive(Deserialize)]
+    /// struct TestConf{ val: u32 };
+    /// struct TestApp{};
+    /// impl Configurable for TestApp {
+    ///     type Config = TestConf;
+    ///
+    ///     fn from(config: Self::Config) -> Self {
+    ///         Self{}
+    ///     }
+    /// }
+    ///
+    /// impl Runnable for TestApp {
+    ///     fn setup(&self,handle: ClockworkHandle) {}
+    /// }
+    ///
+    /// let conf_str = r#"
+    ///     [database]
+    ///     url = 'postgres://localhost'
+    ///
+    ///     [scheduler.app]
+    ///     val = 1
+    /// "#;
+    /// let app: ClockworkApp<TestApp> =
+    ///     ClockworkApp::from_config_str_with_root(conf_str.to_string(), "scheduler");
+    /// ```
+    pub fn from_config_str_with_root(conf_string: String, root: &str) -> Self
+    where
+        T::Config: DeserializeOwned,
+    {
+        Self::try_from_config_str_with_root(conf_string, root)
+            .unwrap_or_else(|e| panic!("Failed to load config: {}!", e))
+    }
+
+    /// Same as `from_config_str_with_root`, but returns an error instead of panicking if the
+    /// config cannot be parsed or has no `root` section
+    pub fn try_from_config_str_with_root(
+        conf_string: String,
+        root: &str,
+    ) -> Result<Self, ClockworkError>
+    where
+        T::Config: DeserializeOwned,
+    {
+        toml::from_str(conf_string.as_str())
+            .and_then(|raw: toml::Value| {
+                let raw_root = root
+                    .split('.')
+                    .try_fold(&raw, |table, key| table.get(key))
+                    .cloned()
+                    .ok_or_else(|| {
+                        serde::de::Error::custom(format!("config has no `{}` section", root))
+                    })?;
+                Self::try_from_config_value(raw_root)
+            })
+            .map_err(|source| ClockworkError::Parse { path: None, source })
     }
 
     /// Constructs a `ClockworkApp` instance from a path to the config file
@@ -108,7 +432,7 @@ impl<T: App> ClockworkApp<T> {
     ///
     /// let app: ClockworkApp<TestApp> = ClockworkApp::from_path(PathBuf::from_str("").unwrap());
     /// ```
-    pub fn from_path<'a>(path: PathBuf) -> Self
+    pub fn from_path(path: PathBuf) -> Self
     where
         T::Config: DeserializeOwned,
     {
@@ -122,87 +446,1457 @@ impl<T: App> ClockworkApp<T> {
         Self::from_config_str(contents)
     }
 
-    /// Starts the application, blocks on `Clockwork::run`
-    /// Enables the logger if `logging` feature is enabled
-    pub fn start(&self) {
-        #[cfg(feature = "logging")]
-        if self.logger.is_some() {
-            self.logger.as_ref().unwrap().enable_logging();
-        }
+    /// Same as `from_path`, but returns an error instead of panicking if the file cannot be read or
+    /// parsed. The error names the file, e.g. `failed to parse /etc/app/config.toml: ...`
+    pub fn try_from_path(path: PathBuf) -> Result<Self, ClockworkError>
+    where
+        T::Config: DeserializeOwned,
+    {
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(source) => return Err(ClockworkError::Read { path, source }),
+        };
 
-        self.app.setup(self.cw.handle());
-        self.cw.run(&self.app);
-        self.app.shutdown();
+        toml::from_str(contents.as_str())
+            .and_then(Self::try_from_config_value)
+            .map_err(|source| ClockworkError::Parse {
+                path: Some(path),
+                source,
+            })
     }
 
-    /// Exposes the application's `ClockworkHandle`
-    /// Allows other threads to stop the application
-    pub fn handle(&self) -> ClockworkHandle {
-        self.cw.handle()
+    /// Constructs a `ClockworkApp` instance from the first of `sources` that can be read, e.g. the
+    /// file named by `$APP_CONFIG`, falling back to `./config.toml`, falling back to a default.
+    /// A source is skipped if it cannot be read (its environment variable is not set, or its file
+    /// cannot be opened), but a config that is read and fails to parse is not fallen back from.
+    /// Panics if no source can be read or the config cannot be parsed, see `try_from_sources`.
+    /// ```
+    /// use clockwork::{ClockworkApp, ConfigSource, Configurable, Runnable, ClockworkHandle};
+    /// use serde::Deserialize;
+    /// use std::path::PathBuf;
+    /// #[derive(Deserialize)]
+    /// struct TestConf{};
+    /// struct TestApp{};
+    /// impl Configurable for TestApp {
+    ///     type Config = TestConf;
+    ///
+    ///     fn from(config: Self::Config) -> Self {
+    ///         Self{}
+    ///     }
+    /// }
+    ///
+    /// impl Runnable for TestApp {
+    ///     fn setup(&self,handle: ClockworkHandle) {}
+    /// }
+    ///
+    /// let app: ClockworkApp<TestApp> = ClockworkApp::from_sources(&[
+    ///     ConfigSource::EnvPath("APP_CONFIG".to_string()),
+    ///     ConfigSource::Path(PathBuf::from("config.toml")),
+    ///     ConfigSource::Str("[app]".to_string()),
+    /// ]);
+    /// ```
+    pub fn from_sources(sources: &[ConfigSource]) -> Self
+    where
+        T::Config: DeserializeOwned,
+    {
+        Self::try_from_sources(sources).unwrap_or_else(|e| panic!("Failed to load config: {}!", e))
     }
 
-    #[cfg(not(feature = "logging"))]
-    pub(crate) fn new(cw: Clockwork, app: T) -> Self {
-        Self { cw, app }
+    /// Same as `from_sources`, but returns an error instead of panicking. If no source can be
+    /// read, the error lists every source tried and why it was skipped.
+    pub fn try_from_sources(sources: &[ConfigSource]) -> Result<Self, ClockworkError>
+    where
+        T::Config: DeserializeOwned,
+    {
+        let mut tried = Vec::new();
+        for source in sources {
+            let path = match source {
+                ConfigSource::Str(conf_string) => {
+                    return Self::try_from_config_str(conf_string.clone())
+                }
+                ConfigSource::Path(path) => path.clone(),
+                ConfigSource::EnvPath(var) => match std::env::var_os(var) {
+                    Some(path) => PathBuf::from(path),
+                    None => {
+                        tried.push(format!("${} (not set)", var));
+                        continue;
+                    }
+                },
+            };
+
+            match Self::try_from_path(path) {
+                Err(ClockworkError::Read { path, source }) => {
+                    tried.push(format!("{} ({})", path.display(), source))
+                }
+                result => return result,
+            }
+        }
+
+        Err(ClockworkError::NoConfig { tried })
     }
 
-    #[cfg(feature = "logging")]
-    pub(crate) fn new(cw: Clockwork, app: T) -> Self {
-        Self {
-            cw,
-            logger: None,
-            app,
+    /// Constructs a `ClockworkApp` instance from a directory of config fragments (e.g. `conf.d/`)
+    /// Every `*.toml` file in `path` is read in file name order and deep-merged into one config:
+    /// tables are merged key by key, any other value (including arrays) in a later file replaces
+    /// the one from an earlier file. Sub-directories and other files are ignored.
+    /// ```no_run
+    /// use clockwork::{ClockworkApp, Configurable, Runnable, ClockworkHandle};
+    /// use serde::Deserialize;
+    /// use std::path::PathBuf;
+    /// #[derive(Deserialize)]
+    /// struct TestConf{};
+    /// struct TestApp{};
+    /// impl Configurable for TestApp {
+    ///     type Config = TestConf;
+    ///
+    ///     fn from(config: Self::Config) -> Self {
+    ///         Self{}
+    ///     }
+    /// }
+    ///
+    /// impl Runnable for TestApp {
+    ///     fn setup(&self,handle: ClockworkHandle) {}
+    /// }
+    ///
+    /// let app: ClockworkApp<TestApp> = ClockworkApp::from_dir(PathBuf::from("conf.d"));
+    /// ```
+    pub fn from_dir(path: PathBuf) -> Self
+    where
+        T::Config: DeserializeOwned,
+    {
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(path)
+            .expect("Config directory cannot be read!")
+            .map(|entry| entry.expect("Config directory cannot be read!").path())
+            .filter(|path| path.is_file() && path.extension() == Some("toml".as_ref()))
+            .collect();
+        paths.sort();
+
+        let mut merged = toml::Value::Table(Default::default());
+        for path in paths {
+            let contents = std::fs::read_to_string(&path).expect("File cannot be read!");
+            let fragment: toml::Value = toml::from_str(contents.as_str())
+                .unwrap_or_else(|e| panic!("Failed to parse config {}: {}!", path.display(), e));
+            merge_toml(&mut merged, fragment);
         }
+
+        Self::from_config_value(merged)
     }
 
-    pub(crate) fn mut_app(&mut self) -> &mut T {
-        &mut self.app
+    /// Constructs a `ClockworkApp` instance from a config read from standard input until EOF,
+    /// e.g. for configs (or secrets) piped in by a container runtime
+    /// ```no_run
+    /// use clockwork::{ClockworkApp, Configurable, Runnable, ClockworkHandle};
+    /// use serde::Deserialize;
+    /// #[derive(Deserialize)]
+    /// struct TestConf{};
+    /// struct TestApp{};
+    /// impl Configurable for TestApp {
+    ///     type Config = TestConf;
+    ///
+    ///     fn from(config: Self::Config) -> Self {
+    ///         Self{}
+    ///     }
+    /// }
+    ///
+    /// impl Runnable for TestApp {
+    ///     fn setup(&self,handle: ClockworkHandle) {}
+    /// }
+    ///
+    /// let app: ClockworkApp<TestApp> = ClockworkApp::from_stdin();
+    /// ```
+    pub fn from_stdin() -> Self
+    where
+        T::Config: DeserializeOwned,
+    {
+        Self::from_reader(std::io::stdin().lock())
     }
 
-    pub(crate) fn app(&self) -> &T {
-        &self.app
+    /// Same as `from_stdin`, but returns an error instead of panicking if standard input cannot
+    /// be read, is empty or cannot be parsed
+    pub fn try_from_stdin() -> Result<Self, ClockworkError>
+    where
+        T::Config: DeserializeOwned,
+    {
+        Self::try_from_reader(std::io::stdin().lock())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::clockwork_app::ClockworkApp;
-    use crate::{App, ClockworkHandle, Configurable, Runnable};
-    use serde::Deserialize;
+    /// Constructs a `ClockworkApp` instance from a config read from `reader` until EOF
+    /// Panics if `reader` cannot be read, nothing but whitespace was read or the config cannot be
+    /// parsed, see `try_from_reader`.
+    pub fn from_reader<R: Read>(reader: R) -> Self
+    where
+        T::Config: DeserializeOwned,
+    {
+        Self::try_from_reader(reader).unwrap_or_else(|e| panic!("Failed to load config: {}!", e))
+    }
 
-    #[test]
-    fn test_clockwork_app_conf() {
-        struct BasicApp {
-            val: String,
+    /// Same as `from_reader`, but returns an error instead of panicking if `reader` cannot be
+    /// read (`ClockworkError::ReadInput`), ends before anything but whitespace was read
+    /// (`ClockworkError::EmptyInput`) or the config cannot be parsed
+    pub fn try_from_reader<R: Read>(mut reader: R) -> Result<Self, ClockworkError>
+    where
+        T::Config: DeserializeOwned,
+    {
+        let mut contents = String::new();
+        if let Err(source) = reader.read_to_string(&mut contents) {
+            return Err(ClockworkError::ReadInput { source });
         }
-
-        #[derive(Deserialize)]
-        struct BasicAppConf {
-            val: String,
+        if contents.trim().is_empty() {
+            return Err(ClockworkError::EmptyInput);
         }
 
-        impl Configurable for BasicApp {
-            type Config = BasicAppConf;
+        Self::try_from_config_str(contents)
+    }
 
-            fn from(app_conf: Self::Config) -> Self {
-                Self { val: app_conf.val }
+    /// Starts the application, blocks on `Clockwork::run`
+    /// Calls `try_setup`, then `on_ready` and `run` inside the runtime, then `shutdown`
+    /// A failing `try_setup` is retried as configured by `setup_retries` and
+    /// `setup_retry_delay`. If it still fails, the app is not run: `shutdown` is called and the error is returned
+    /// If `setup` or `run` panics, `shutdown` is still called (best effort) and the panic logged
+    /// before it carries on unwinding out of `start`
+    /// Enables the logger if `logging` feature is enabled, if another subscriber is already
+    /// installed a warning is printed to stderr and the app runs without it
+    pub fn start(&self) -> Result<(), Box<dyn Error>> {
+        self.start_with_report().map(|_| ())
+    }
+
+    /// Same as `start`, but reports how the app ended: why it stopped, how long it was up for, how
+    /// many times its tasks ran and whether draining succeeded (see `ShutdownReport`).
+    /// The report is also logged as a final summary line if `logging` feature is enabled.
+    pub fn start_with_report(&self) -> Result<ShutdownReport, Box<dyn Error>> {
+        // The logger is not up, the warning can only go to stderr
+        #[cfg(feature = "logging")]
+        if let Some(logger) = &self.logger {
+            match logger.enable_logging() {
+                Ok(()) => self
+                    .cw
+                    .handle()
+                    .set_log_level_control(logger.log_level.clone()),
+                Err(e) => eprintln!(
+                    "Warning: logger not installed, carrying on without it: {}",
+                    e
+                ),
             }
         }
 
-        impl Runnable for BasicApp {
-            fn setup(&self, handle: ClockworkHandle) {
-                assert!(!self.val.is_empty())
-            }
+        self.run()
+    }
+
+    /// Restarts the app on `cw`, a fresh `Clockwork` (e.g. one on a runtime of another flavor),
+    /// once `start` has returned. Blocks and reports the same as `start_with_report`.
+    /// The old runtime is torn down first: it is stopped if it was not already, and the tasks still
+    /// in flight on it are given a few seconds to exit before it is dropped, so none of them runs
+    /// again nor outlives it. `try_setup` is then called again with a handle on `cw`, the app has
+    /// to schedule its tasks anew there (handles kept from the previous `setup` are stopped, and
+    /// scheduling through them does nothing).
+    /// The logger installed by `start` is carried over, it is not installed again.
+    pub fn restart(&mut self, cw: Clockwork) -> Result<ShutdownReport, Box<dyn Error>> {
+        if !self.cw.teardown(RESTART_TEARDOWN_TIMEOUT) {
+            #[cfg(feature = "logging")]
+            tracing::warn!("Tasks of the old runtime still in flight, restarting regardless");
         }
 
-        let conf_str = r#"
-            [app]
-            val = 'Hello World'
-        "#
-        .to_string();
+        self.config.clockwork = cw.config().clone();
+        self.cw = cw;
+        #[cfg(feature = "logging")]
+        if let Some(logger) = &self.logger {
+            self.cw
+                .handle()
+                .set_log_level_control(logger.log_level.clone());
+        }
 
-        type BasicClockworkApp = ClockworkApp<BasicApp>;
-        let cw_app = BasicClockworkApp::from_config_str(conf_str);
-        assert_eq!(cw_app.app().val, "Hello World")
+        self.run()
+    }
+
+    /// Sets up, runs and shuts down the app, see `start_with_report`
+    fn run(&self) -> Result<ShutdownReport, Box<dyn Error>> {
+        let setup = self
+            .cw
+            .isolate_panics(&self.app, || self.cw.try_setup(&self.app));
+        if let Err(e) = setup {
+            #[cfg(feature = "logging")]
+            tracing::error!(error = %e, "App setup failed");
+            self.app.shutdown();
+            return Err(e);
+        }
+
+        self.cw.isolate_panics(&self.app, || self.cw.run(&self.app));
+        let report = self.cw.handle().shutdown_report();
+        self.app.shutdown();
+        #[cfg(feature = "logging")]
+        tracing::info!("App {}", report);
+        Ok(report)
+    }
+
+    /// Same as `start`, then exits the process with a code reflecting how the app ended:
+    /// 0 if it stopped cleanly, 1 if `try_setup` failed (the error is printed to stderr), and 101
+    /// (the code Rust uses for a panicking `main`) if it panicked.
+    /// Meant as the last call of `main` for apps run as a daemon/process.
+    pub fn start_and_exit(&self) -> ! {
+        let result = catch_unwind(AssertUnwindSafe(|| self.start()));
+        if let Ok(Err(e)) = &result {
+            eprintln!("Error: {}", e);
+        }
+
+        std::process::exit(exit_code(&result))
+    }
+
+    /// Replaces the logger built from the `logger` section with an externally built `dispatch`,
+    /// e.g. a subscriber with extra layers or samplers. It is installed as the global default by
+    /// `start`, whatever `install_subscriber` is set to.
+    #[cfg(feature = "logging")]
+    pub fn with_dispatch(mut self, dispatch: tracing::Dispatch) -> Self {
+        self.logger = Some(ClockworkLogger::from_dispatch(dispatch));
+        self
+    }
+
+    /// Serializes the configuration the app was built with back into TOML, including any defaults
+    /// that were filled in for the `clockwork` and `logger` sections.
+    /// The `app` section is reproduced as it was written.
+    /// Apps assembled without a config report the default `clockwork` and `logger` sections.
+    pub fn effective_config_toml(&self) -> String {
+        toml::to_string(&self.config).expect("Failed to serialize config!")
+    }
+
+    /// The configuration the app was built with, including any defaults that were filled in for
+    /// the `clockwork` and `logger` sections, e.g. to diff it against a reloaded config.
+    /// The `app` section is read again from the config as written (`T::from` consumed the one the
+    /// app was built from), so `T::Config` does not have to be `Clone`.
+    /// Apps assembled without a config have an empty `app` section, which fails to read unless
+    /// every field of `T::Config` has a default.
+    pub fn app_config(&self) -> Result<ClockworkAppConfig<T::Config>, toml::de::Error> {
+        Ok(ClockworkAppConfig {
+            clockwork: self.config.clockwork.clone(),
+            #[cfg(feature = "logging")]
+            logger: self.config.logger.clone(),
+            app: self.config.app.clone().try_into()?,
+        })
+    }
+
+    /// Applies `patch`, a partial config (e.g. `{ "logger": { "log_level": "DEBUG" } }` or
+    /// `clockwork.task_periods.report = '30s'`), to the running app, e.g. on an admin command.
+    /// The patch is read as JSON if it is a JSON object (`logging` or `http-config` feature only),
+    /// as TOML otherwise. It is deep-merged onto the config the app was built with, but only the
+    /// keys that can change while the app runs take effect:
+    /// logger.log_level - through `ClockworkHandle::set_log_level` (`logging` feature only)
+    /// clockwork.task_periods.<name> - through `ClockworkHandle::set_task_period`
+    /// The other keys are left out of the merge and returned (as dotted paths, e.g.
+    /// `clockwork.run_for`), they only take effect by building the app again from a config that
+    /// has them. `effective_config_toml` and `app_config` reflect the keys that were applied.
+    /// Returns an error, and applies nothing, if the patch cannot be parsed or a value it would
+    /// apply is invalid.
+    pub fn apply_patch(&mut self, patch: &str) -> Result<Vec<String>, ClockworkError> {
+        let parse_error = |source| ClockworkError::Parse { path: None, source };
+        let mut patched = Vec::new();
+        leaves(
+            parse_patch(patch).map_err(parse_error)?,
+            &mut Vec::new(),
+            &mut patched,
+        );
+        let (hot, ignored): (Vec<_>, Vec<_>) = patched
+            .into_iter()
+            .partition(|(path, _)| is_hot_reloadable(path));
+        #[cfg_attr(not(feature = "logging"), allow(unused_mut))]
+        let mut ignored: Vec<String> = ignored
+            .into_iter()
+            .map(|(path, _)| path.join("."))
+            .collect();
+
+        // Validated as a whole before anything is applied
+        let mut merged = toml::Value::try_from(&self.config).expect("Failed to serialize config!");
+        for (path, value) in &hot {
+            let overlay = path.iter().rev().fold(value.clone(), |value, key| {
+                toml::Value::Table(std::iter::once((key.clone(), value)).collect())
+            });
+            merge_toml(&mut merged, overlay);
+        }
+        let conf: ClockworkAppConfig<toml::Value> = merged.try_into().map_err(parse_error)?;
+
+        for (path, _) in hot {
+            match path[0].as_str() {
+                #[cfg(feature = "logging")]
+                "logger" => {
+                    let level = conf.logger.log_level;
+                    match &self.logger {
+                        Some(logger) if logger.log_level.set(level) => {
+                            self.config.logger.log_level = level
+                        }
+                        _ => ignored.push(path.join(".")),
+                    }
+                }
+                _ => {
+                    let period = conf.clockwork.task_periods[&path[2]];
+                    self.cw.set_task_period(&path[2], period.into());
+                }
+            }
+        }
+        self.config.clockwork = self.cw.config().clone();
+
+        #[cfg(feature = "logging")]
+        if !ignored.is_empty() {
+            tracing::warn!(keys = ?ignored, "Config keys ignored, they cannot change while running");
+        }
+        Ok(ignored)
+    }
+
+    /// Exposes the application's `ClockworkHandle`
+    /// Allows other threads to stop the application
+    pub fn handle(&self) -> ClockworkHandle {
+        self.cw.handle()
+    }
+
+    /// Constructs a `ClockworkApp` directly from a `Clockwork` instance and an app, without a logger
+    /// ```
+    /// use clockwork::{Clockwork, ClockworkApp, Configurable, Runnable, ClockworkHandle};
+    /// use serde::Deserialize;
+    /// #[derive(Deserialize)]
+    /// struct TestConf{};
+    /// struct TestApp{};
+    /// impl Configurable for TestApp {
+    ///     type Config = TestConf;
+    ///
+    ///     fn from(config: Self::Config) -> Self {
+    ///         Self{}
+    ///     }
+    /// }
+    ///
+    /// impl Runnable for TestApp {
+    ///     fn setup(&self,handle: ClockworkHandle) {}
+    /// }
+    ///
+    /// let app = ClockworkApp::from_parts(Clockwork::default(), TestApp{});
+    /// ```
+    #[cfg(not(feature = "logging"))]
+    pub fn from_parts(cw: Clockwork, app: T) -> Self {
+        Self {
+            cw,
+            app,
+            config: Default::default(),
+        }
+    }
+
+    /// Constructs a `ClockworkApp` directly from a `Clockwork` instance and an app, without a logger
+    #[cfg(feature = "logging")]
+    pub fn from_parts(cw: Clockwork, app: T) -> Self {
+        Self {
+            cw,
+            logger: None,
+            app,
+            config: Default::default(),
+        }
+    }
+
+    /// The app, as constructed from its config, e.g. to assert on its configured state in tests
+    /// ```
+    /// use clockwork::{ClockworkApp, Configurable, Runnable};
+    /// use serde::Deserialize;
+    /// #[derive(Deserialize)]
+    /// struct TestConf{ val: u32 };
+    /// struct TestApp{ val: u32 };
+    /// impl Configurable for TestApp {
+    ///     type Config = TestConf;
+    ///
+    ///     fn from(config: Self::Config) -> Self {
+    ///         Self{ val: config.val }
+    ///     }
+    /// }
+    ///
+    /// impl Runnable for TestApp {}
+    ///
+    /// let app: ClockworkApp<TestApp> = ClockworkApp::from_config_str("[app]\nval = 1".to_string());
+    /// assert_eq!(app.app().val, 1);
+    /// ```
+    pub fn app(&self) -> &T {
+        &self.app
+    }
+
+    /// The app, mutably, e.g. to swap in test doubles before `start`
+    pub fn app_mut(&mut self) -> &mut T {
+        &mut self.app
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clockwork_app::{exit_code, ClockworkApp, ConfigSource};
+    use crate::{
+        Clockwork, ClockworkConfig, ClockworkError, ClockworkHandle, Configurable, Runnable,
+        ShutdownReport, StopReason, StopSignal,
+    };
+    use serde::Deserialize;
+    use std::error::Error;
+    use std::future::Future;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio::runtime::{Builder, Handle, RuntimeFlavor};
+
+    #[test]
+    fn test_clockwork_app_conf() {
+        struct BasicApp {
+            val: String,
+        }
+
+        #[derive(Deserialize)]
+        struct BasicAppConf {
+            val: String,
+        }
+
+        impl Configurable for BasicApp {
+            type Config = BasicAppConf;
+
+            fn from(app_conf: Self::Config) -> Self {
+                Self { val: app_conf.val }
+            }
+        }
+
+        impl Runnable for BasicApp {
+            fn setup(&self, _handle: ClockworkHandle) {
+                assert!(!self.val.is_empty())
+            }
+        }
+
+        let conf_str = r#"
+            [app]
+            val = 'Hello World'
+        "#
+        .to_string();
+
+        type BasicClockworkApp = ClockworkApp<BasicApp>;
+        let cw_app = BasicClockworkApp::from_config_str(conf_str);
+        assert_eq!(cw_app.app().val, "Hello World")
+    }
+
+    #[test]
+    fn test_clockwork_app_conf_with_root() {
+        struct BasicApp {
+            val: String,
+        }
+
+        #[derive(Deserialize)]
+        struct BasicAppConf {
+            val: String,
+        }
+
+        impl Configurable for BasicApp {
+            type Config = BasicAppConf;
+
+            fn from(app_conf: Self::Config) -> Self {
+                Self { val: app_conf.val }
+            }
+        }
+
+        impl Runnable for BasicApp {
+            fn setup(&self, _handle: ClockworkHandle) {}
+        }
+
+        let conf_str = r#"
+            [app]
+            val = 'Top Level'
+
+            [services.scheduler.app]
+            val = 'Nested'
+        "#
+        .to_string();
+
+        let cw_app = ClockworkApp::<BasicApp>::from_config_str_with_root(
+            conf_str.clone(),
+            "services.scheduler",
+        );
+        assert_eq!(cw_app.app().val, "Nested");
+
+        let err = ClockworkApp::<BasicApp>::try_from_config_str_with_root(conf_str, "services.api")
+            .err()
+            .unwrap();
+        assert!(matches!(err, ClockworkError::Parse { path: None, .. }));
+        assert!(err
+            .to_string()
+            .contains("config has no `services.api` section"));
+    }
+
+    #[test]
+    fn test_from_dir() {
+        struct BasicApp {
+            val: String,
+            other: String,
+        }
+
+        #[derive(Deserialize)]
+        struct BasicAppConf {
+            val: String,
+            other: String,
+        }
+
+        impl Configurable for BasicApp {
+            type Config = BasicAppConf;
+
+            fn from(app_conf: Self::Config) -> Self {
+                Self {
+                    val: app_conf.val,
+                    other: app_conf.other,
+                }
+            }
+        }
+
+        impl Runnable for BasicApp {
+            fn setup(&self, _handle: ClockworkHandle) {}
+        }
+
+        let dir = std::env::temp_dir().join(format!("clockwork_conf_d_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("10-override.toml"),
+            "[clockwork]\ndry_run = true\n[app]\nval = 'Override'",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("00-base.toml"),
+            "[app]\nval = 'Base'\nother = 'Kept'",
+        )
+        .unwrap();
+        std::fs::write(dir.join("README"), "not a config").unwrap();
+
+        let cw_app: ClockworkApp<BasicApp> = ClockworkApp::from_dir(dir.clone());
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(cw_app.app().val, "Override");
+        assert_eq!(cw_app.app().other, "Kept");
+        assert!(cw_app.config.clockwork.dry_run);
+    }
+
+    #[test]
+    fn test_try_from_path_errors() {
+        struct BasicApp {}
+
+        #[derive(Deserialize)]
+        struct BasicAppConf {
+            val: u32,
+        }
+
+        impl Configurable for BasicApp {
+            type Config = BasicAppConf;
+
+            fn from(_app_conf: Self::Config) -> Self {
+                Self {}
+            }
+        }
+
+        impl Runnable for BasicApp {}
+
+        let path = std::env::temp_dir().join(format!("clockwork_bad_{}.toml", std::process::id()));
+        std::fs::write(&path, "[app]\nval = 'not a number'").unwrap();
+        let err = ClockworkApp::<BasicApp>::try_from_path(path.clone())
+            .err()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(err, ClockworkError::Parse { .. }));
+        assert!(err
+            .to_string()
+            .starts_with(&format!("failed to parse {}: ", path.display())));
+
+        let err = ClockworkApp::<BasicApp>::try_from_path(path.clone())
+            .err()
+            .unwrap();
+        assert!(matches!(err, ClockworkError::Read { .. }));
+
+        let err = ClockworkApp::<BasicApp>::try_from_config_str("[app]".to_string())
+            .err()
+            .unwrap();
+        assert!(err.to_string().starts_with("failed to parse config: "));
+    }
+
+    #[test]
+    fn test_deny_unknown_keys() {
+        struct BasicApp {}
+
+        #[derive(Deserialize)]
+        struct BasicAppConf {}
+
+        impl Configurable for BasicApp {
+            type Config = BasicAppConf;
+
+            fn from(_app_conf: Self::Config) -> Self {
+                Self {}
+            }
+        }
+
+        impl Runnable for BasicApp {}
+
+        let conf = r#"
+            [clockwork]
+            idle_timout = '5m'
+
+            [clokwork]
+            dry_run = true
+
+            [app]
+        "#;
+        // only warned about by default
+        let cw_app = ClockworkApp::<BasicApp>::try_from_config_str(conf.to_string()).unwrap();
+        assert!(!cw_app.config.clockwork.dry_run);
+
+        let strict = conf.replace("[clockwork]", "[clockwork]\ndeny_unknown_keys = true");
+        let err = ClockworkApp::<BasicApp>::try_from_config_str(strict)
+            .err()
+            .unwrap();
+        assert!(matches!(err, ClockworkError::Parse { path: None, .. }));
+        assert!(err
+            .to_string()
+            .contains("unknown config keys: clockwork.idle_timout, clokwork"));
+    }
+
+    #[cfg(feature = "http-config")]
+    #[test]
+    fn test_from_url() {
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+
+        struct BasicApp {
+            val: u32,
+        }
+
+        #[derive(Deserialize)]
+        struct BasicAppConf {
+            val: u32,
+        }
+
+        impl Configurable for BasicApp {
+            type Config = BasicAppConf;
+
+            fn from(app_conf: Self::Config) -> Self {
+                Self { val: app_conf.val }
+            }
+        }
+
+        impl Runnable for BasicApp {}
+
+        /// Serves a single request, returns the server's url
+        fn serve_once(
+            status: &'static str,
+            content_type: &'static str,
+            body: &'static str,
+        ) -> String {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}/clockwork", listener.local_addr().unwrap());
+            std::thread::spawn(move || {
+                let (mut stream, _) = listener.accept().unwrap();
+                // the whole request has to be read, closing with unread data resets the connection
+                let mut request = Vec::new();
+                let mut buf = [0u8; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buf) {
+                        Ok(0) | Err(_) => break,
+                        Ok(read) => request.extend_from_slice(&buf[..read]),
+                    }
+                }
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    content_type,
+                    body.len(),
+                    body
+                );
+            });
+            url
+        }
+
+        let url = serve_once("200 OK", "text/plain", "[app]\nval = 1");
+        assert_eq!(ClockworkApp::<BasicApp>::from_url(&url).app.val, 1);
+
+        let url = serve_once("200 OK", "application/json", r#"{"app": {"val": 2}}"#);
+        assert_eq!(ClockworkApp::<BasicApp>::from_url(&url).app.val, 2);
+
+        let url = serve_once("404 Not Found", "text/plain", "");
+        let err = ClockworkApp::<BasicApp>::try_from_url(&url).err().unwrap();
+        assert!(matches!(err, ClockworkError::Status { status: 404, .. }));
+        assert_eq!(
+            err.to_string(),
+            format!("failed to fetch {}: server answered 404", url)
+        );
+
+        let url = serve_once("200 OK", "application/json", "{");
+        let err = ClockworkApp::<BasicApp>::try_from_url(&url).err().unwrap();
+        assert!(matches!(err, ClockworkError::Parse { path: None, .. }));
+    }
+
+    #[test]
+    fn test_from_sources() {
+        struct BasicApp {
+            val: u32,
+        }
+
+        #[derive(Deserialize)]
+        struct BasicAppConf {
+            val: u32,
+        }
+
+        impl Configurable for BasicApp {
+            type Config = BasicAppConf;
+
+            fn from(app_conf: Self::Config) -> Self {
+                Self { val: app_conf.val }
+            }
+        }
+
+        impl Runnable for BasicApp {}
+
+        let var = format!("CLOCKWORK_TEST_CONFIG_{}", std::process::id());
+        let path =
+            std::env::temp_dir().join(format!("clockwork_sources_{}.toml", std::process::id()));
+        let missing = std::env::temp_dir().join("clockwork_sources_missing.toml");
+        let sources = [
+            ConfigSource::EnvPath(var.clone()),
+            ConfigSource::Path(missing.clone()),
+            ConfigSource::Str("[app]\nval = 1".to_string()),
+        ];
+
+        // falls back to the baked-in default
+        let app = ClockworkApp::<BasicApp>::from_sources(&sources);
+        assert_eq!(app.app.val, 1);
+
+        std::fs::write(&path, "[app]\nval = 2").unwrap();
+        std::env::set_var(&var, &path);
+        let app = ClockworkApp::<BasicApp>::from_sources(&sources);
+        std::env::remove_var(&var);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(app.app.val, 2);
+
+        let err = ClockworkApp::<BasicApp>::try_from_sources(&sources[..2])
+            .err()
+            .unwrap();
+        assert!(matches!(err, ClockworkError::NoConfig { ref tried } if tried.len() == 2));
+        assert!(err.to_string().starts_with(&format!(
+            "no config found, tried: ${} (not set), {} (",
+            var,
+            missing.display()
+        )));
+
+        // a source that is read but does not parse is not fallen back from
+        let err = ClockworkApp::<BasicApp>::try_from_sources(&[
+            ConfigSource::Str("[app]".to_string()),
+            ConfigSource::Str("[app]\nval = 1".to_string()),
+        ])
+        .err()
+        .unwrap();
+        assert!(matches!(err, ClockworkError::Parse { .. }));
+    }
+
+    #[test]
+    fn test_exit_code() {
+        assert_eq!(exit_code(&Ok(Ok(()))), 0);
+        assert_eq!(exit_code(&Ok(Err("setup failed".into()))), 1);
+        assert_eq!(exit_code(&std::panic::catch_unwind(|| panic!("boom"))), 101);
+    }
+
+    #[test]
+    #[should_panic(expected = "config is empty")]
+    fn test_from_reader_empty() {
+        struct BasicApp {}
+
+        #[derive(Deserialize)]
+        struct BasicAppConf {}
+
+        impl Configurable for BasicApp {
+            type Config = BasicAppConf;
+
+            fn from(_app_conf: Self::Config) -> Self {
+                Self {}
+            }
+        }
+
+        impl Runnable for BasicApp {
+            fn setup(&self, _handle: ClockworkHandle) {}
+        }
+
+        ClockworkApp::<BasicApp>::from_reader(" \n".as_bytes());
+    }
+
+    #[test]
+    fn test_try_from_reader() {
+        struct BasicApp {}
+
+        #[derive(Deserialize)]
+        struct BasicAppConf {}
+
+        impl Configurable for BasicApp {
+            type Config = BasicAppConf;
+
+            fn from(_app_conf: Self::Config) -> Self {
+                Self {}
+            }
+        }
+
+        impl Runnable for BasicApp {}
+
+        let err = ClockworkApp::<BasicApp>::try_from_reader("".as_bytes()).err();
+        assert!(matches!(err, Some(ClockworkError::EmptyInput)));
+        // not UTF-8
+        let err = ClockworkApp::<BasicApp>::try_from_reader(&[0xff, 0xfe][..]).err();
+        assert!(matches!(err, Some(ClockworkError::ReadInput { .. })));
+        let err = ClockworkApp::<BasicApp>::try_from_reader("[app".as_bytes()).err();
+        assert!(matches!(
+            err,
+            Some(ClockworkError::Parse { path: None, .. })
+        ));
+        assert!(ClockworkApp::<BasicApp>::try_from_reader("[app]".as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn test_effective_config_toml() {
+        struct BasicApp {}
+
+        #[derive(Deserialize)]
+        struct BasicAppConf {}
+
+        impl Configurable for BasicApp {
+            type Config = BasicAppConf;
+
+            fn from(_app_conf: Self::Config) -> Self {
+                Self {}
+            }
+        }
+
+        impl Runnable for BasicApp {
+            fn setup(&self, _handle: ClockworkHandle) {}
+        }
+
+        let conf_str = r#"
+            [clockwork.runtime]
+            enable_io = false
+
+            [app]
+            val = 'Hello World'
+        "#
+        .to_string();
+
+        let cw_app: ClockworkApp<BasicApp> = ClockworkApp::from_config_str(conf_str);
+        let effective: toml::Value = toml::from_str(&cw_app.effective_config_toml()).unwrap();
+
+        let runtime = &effective["clockwork"]["runtime"];
+        assert_eq!(runtime["enable_io"].as_bool(), Some(false));
+        assert_eq!(runtime["max_blocking_threads"].as_integer(), Some(512));
+        assert_eq!(effective["app"]["val"].as_str(), Some("Hello World"));
+    }
+
+    #[test]
+    fn test_app_config() {
+        struct BasicApp {}
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct BasicAppConf {
+            val: String,
+            #[serde(default)]
+            retries: u32,
+        }
+
+        impl Configurable for BasicApp {
+            type Config = BasicAppConf;
+
+            fn from(_app_conf: Self::Config) -> Self {
+                Self {}
+            }
+        }
+
+        impl Runnable for BasicApp {}
+
+        let conf_str = r#"
+            [clockwork.runtime]
+            enable_io = false
+
+            [app]
+            val = 'Hello World'
+        "#
+        .to_string();
+
+        let cw_app: ClockworkApp<BasicApp> = ClockworkApp::from_config_str(conf_str);
+        let conf = cw_app.app_config().unwrap();
+        assert!(!conf.clockwork().runtime.enable_io);
+        assert_eq!(
+            *conf.app(),
+            BasicAppConf {
+                val: "Hello World".to_string(),
+                retries: 0,
+            }
+        );
+
+        // there is no `val` to read back without a config
+        let cw_app = ClockworkApp::from_parts(Clockwork::default(), BasicApp {});
+        assert!(cw_app.app_config().is_err());
+    }
+
+    #[test]
+    fn test_apply_patch() {
+        struct BasicApp {}
+
+        #[derive(Deserialize)]
+        struct BasicAppConf {}
+
+        impl Configurable for BasicApp {
+            type Config = BasicAppConf;
+
+            fn from(_app_conf: Self::Config) -> Self {
+                Self {}
+            }
+        }
+
+        impl Runnable for BasicApp {}
+
+        let mut cw_app: ClockworkApp<BasicApp> = ClockworkApp::from_config_str("[app]".to_string());
+        let handle = cw_app.handle();
+        handle.schedule_named_repeating_task("job", || {}, Duration::from_secs(3600));
+
+        let ignored = cw_app
+            .apply_patch(
+                r#"
+                app.val = 1
+                [clockwork]
+                run_for = '1s'
+                task_periods = { job = '1m', later = '2m' }
+            "#,
+            )
+            .unwrap();
+        assert_eq!(ignored, vec!["app.val", "clockwork.run_for"]);
+        assert_eq!(
+            handle.scheduled_tasks()[0].period,
+            Some(Duration::from_secs(60))
+        );
+        let conf = cw_app.app_config().unwrap();
+        assert!(conf.clockwork().run_for.is_none());
+        assert_eq!(conf.clockwork().task_periods.len(), 2);
+
+        // a task scheduled afterwards takes the patched period
+        handle.schedule_named_repeating_task("later", || {}, Duration::from_secs(3600));
+        assert_eq!(
+            handle.scheduled_tasks()[1].period,
+            Some(Duration::from_secs(120))
+        );
+
+        // nothing is applied if a value is invalid
+        assert!(cw_app
+            .apply_patch("clockwork.task_periods.job = 'soon'")
+            .is_err());
+        assert!(cw_app.apply_patch("[clockwork").is_err());
+        assert_eq!(
+            handle.scheduled_tasks()[0].period,
+            Some(Duration::from_secs(60))
+        );
+    }
+
+    #[cfg(feature = "logging")]
+    #[test]
+    fn test_apply_patch_log_level() {
+        struct BasicApp {}
+
+        #[derive(Deserialize)]
+        struct BasicAppConf {}
+
+        impl Configurable for BasicApp {
+            type Config = BasicAppConf;
+
+            fn from(_app_conf: Self::Config) -> Self {
+                Self {}
+            }
+        }
+
+        impl Runnable for BasicApp {}
+
+        let mut cw_app: ClockworkApp<BasicApp> = ClockworkApp::from_config_str("[app]".to_string());
+        let ignored = cw_app
+            .apply_patch(r#"{ "logger": { "log_level": "WARN", "show_line": true } }"#)
+            .unwrap();
+        assert_eq!(ignored, vec!["logger.show_line"]);
+        assert!(cw_app
+            .effective_config_toml()
+            .contains("log_level = \"WARN\""));
+
+        // there is no logger to change once it is replaced
+        let mut cw_app = cw_app.with_dispatch(tracing::Dispatch::none());
+        let ignored = cw_app
+            .apply_patch(r#"{ "logger": { "log_level": "ERROR" } }"#)
+            .unwrap();
+        assert_eq!(ignored, vec!["logger.log_level"]);
+        assert!(cw_app
+            .effective_config_toml()
+            .contains("log_level = \"WARN\""));
+    }
+
+    #[cfg(feature = "logging")]
+    #[test]
+    fn test_logger_not_installed() {
+        struct BasicApp {}
+
+        #[derive(Deserialize)]
+        struct BasicAppConf {}
+
+        impl Configurable for BasicApp {
+            type Config = BasicAppConf;
+
+            fn from(_app_conf: Self::Config) -> Self {
+                Self {}
+            }
+        }
+
+        impl Runnable for BasicApp {
+            fn setup(&self, _handle: ClockworkHandle) {}
+        }
+
+        let conf_str = r#"
+            [logger]
+            install_subscriber = false
+            write_target = 'STDOUT'
+
+            [app]
+        "#
+        .to_string();
+
+        let cw_app: ClockworkApp<BasicApp> = ClockworkApp::from_config_str(conf_str);
+        assert!(cw_app.logger.is_none());
+    }
+
+    #[cfg(feature = "logging")]
+    #[test]
+    fn test_with_dispatch() {
+        struct BasicApp {}
+
+        #[derive(Deserialize)]
+        struct BasicAppConf {}
+
+        impl Configurable for BasicApp {
+            type Config = BasicAppConf;
+
+            fn from(_app_conf: Self::Config) -> Self {
+                Self {}
+            }
+        }
+
+        impl Runnable for BasicApp {
+            fn setup(&self, _handle: ClockworkHandle) {}
+        }
+
+        let conf_str = r#"
+            [logger]
+            install_subscriber = false
+            write_target = 'STDOUT'
+
+            [app]
+        "#
+        .to_string();
+
+        let dispatch = tracing::Dispatch::new(tracing_subscriber::registry());
+        let cw_app: ClockworkApp<BasicApp> =
+            ClockworkApp::from_config_str(conf_str).with_dispatch(dispatch);
+        let logger = cw_app.logger.as_ref().unwrap();
+        assert!(logger.dispatch.is::<tracing_subscriber::Registry>());
+    }
+
+    #[test]
+    fn test_lifecycle_order() {
+        #[derive(Default)]
+        struct LifecycleApp {
+            events: Mutex<Vec<&'static str>>,
+        }
+
+        #[derive(Deserialize)]
+        struct LifecycleAppConf {}
+
+        impl Configurable for LifecycleApp {
+            type Config = LifecycleAppConf;
+
+            fn from(_app_conf: Self::Config) -> Self {
+                Default::default()
+            }
+        }
+
+        impl Runnable for LifecycleApp {
+            fn setup(&self, _handle: ClockworkHandle) {
+                self.events.lock().unwrap().push("setup");
+            }
+
+            fn on_ready(&self, _handle: ClockworkHandle) {
+                self.events.lock().unwrap().push("on_ready");
+            }
+
+            fn run<'a>(
+                &'a self,
+                handle: ClockworkHandle,
+                _stop: StopSignal,
+            ) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>> {
+                Box::pin(async move {
+                    self.events.lock().unwrap().push("run");
+                    handle.stop();
+                })
+            }
+
+            fn shutdown(&self) {
+                self.events.lock().unwrap().push("shutdown");
+            }
+        }
+
+        let cw_app = ClockworkApp::from_parts(Clockwork::default(), LifecycleApp::default());
+        cw_app.start().unwrap();
+        assert_eq!(
+            *cw_app.app().events.lock().unwrap(),
+            vec!["setup", "on_ready", "run", "shutdown"]
+        );
+    }
+
+    #[test]
+    fn test_start_with_report() {
+        struct TickingApp {}
+
+        #[derive(Deserialize)]
+        struct TickingAppConf {}
+
+        impl Configurable for TickingApp {
+            type Config = TickingAppConf;
+
+            fn from(_app_conf: Self::Config) -> Self {
+                Self {}
+            }
+        }
+
+        impl Runnable for TickingApp {
+            fn setup(&self, handle: ClockworkHandle) {
+                handle.schedule_repeating_task(|| {}, Duration::from_millis(1));
+            }
+        }
+
+        let conf: ClockworkConfig = toml::from_str("run_for = '50ms'").unwrap();
+        let cw_app = ClockworkApp::from_parts(Clockwork::from(conf), TickingApp {});
+        let report = cw_app.start_with_report().unwrap();
+        assert_eq!(report.reason, StopReason::RunFor);
+        assert!(report.uptime >= Duration::from_millis(50));
+        assert!(report.task_runs > 0);
+        assert_eq!(report.drained, None);
+
+        let report = ShutdownReport {
+            reason: StopReason::Drained,
+            uptime: Duration::from_micros(65_001_500),
+            task_runs: 42,
+            task_timeouts: 2,
+            drained: Some(false),
+        };
+        assert_eq!(
+            report.to_string(),
+            "stopped (drained) after 1m5s1ms, 42 task runs (2 timed out), tasks still in flight when \
+             drain timed out"
+        );
+    }
+
+    #[test]
+    fn test_failed_setup() {
+        #[derive(Default)]
+        struct FailingApp {
+            events: Mutex<Vec<&'static str>>,
+        }
+
+        #[derive(Deserialize)]
+        struct FailingAppConf {}
+
+        impl Configurable for FailingApp {
+            type Config = FailingAppConf;
+
+            fn from(_app_conf: Self::Config) -> Self {
+                Default::default()
+            }
+        }
+
+        impl Runnable for FailingApp {
+            fn try_setup(&self, _handle: ClockworkHandle) -> Result<(), Box<dyn Error>> {
+                self.events.lock().unwrap().push("setup");
+                Err("address in use".into())
+            }
+
+            fn on_ready(&self, _handle: ClockworkHandle) {
+                self.events.lock().unwrap().push("on_ready");
+            }
+
+            fn shutdown(&self) {
+                self.events.lock().unwrap().push("shutdown");
+            }
+        }
+
+        let cw_app = ClockworkApp::from_parts(Clockwork::default(), FailingApp::default());
+        let err = cw_app.start().unwrap_err();
+        assert_eq!(err.to_string(), "address in use");
+        assert_eq!(
+            *cw_app.app().events.lock().unwrap(),
+            vec!["setup", "shutdown"]
+        );
+    }
+
+    #[test]
+    fn test_panicking_app() {
+        #[derive(Default)]
+        struct PanickingApp {
+            panic_in: &'static str,
+            events: Mutex<Vec<&'static str>>,
+        }
+
+        #[derive(Deserialize)]
+        struct PanickingAppConf {}
+
+        impl Configurable for PanickingApp {
+            type Config = PanickingAppConf;
+
+            fn from(_app_conf: Self::Config) -> Self {
+                Default::default()
+            }
+        }
+
+        impl Runnable for PanickingApp {
+            fn setup(&self, _handle: ClockworkHandle) {
+                self.events.lock().unwrap().push("setup");
+                if self.panic_in == "setup" {
+                    panic!("setup bug");
+                }
+            }
+
+            fn on_ready(&self, _handle: ClockworkHandle) {
+                self.events.lock().unwrap().push("on_ready");
+                if self.panic_in == "run" {
+                    panic!("run bug");
+                }
+            }
+
+            fn shutdown(&self) {
+                self.events.lock().unwrap().push("shutdown");
+            }
+        }
+
+        for (panic_in, events) in [
+            ("setup", vec!["setup", "shutdown"]),
+            ("run", vec!["setup", "on_ready", "shutdown"]),
+        ] {
+            let app = PanickingApp {
+                panic_in,
+                ..Default::default()
+            };
+            let cw_app = ClockworkApp::from_parts(Clockwork::default(), app);
+            let handle = cw_app.handle();
+            assert!(catch_unwind(AssertUnwindSafe(|| cw_app.start())).is_err());
+            assert_eq!(*cw_app.app().events.lock().unwrap(), events);
+            assert_eq!(handle.stop_reason(), Some(StopReason::Panicked));
+        }
+    }
+
+    #[test]
+    fn test_restart() {
+        #[derive(Default)]
+        struct RestartingApp {
+            ticks: Mutex<Vec<Arc<AtomicU32>>>,
+            multi_thread: Arc<Mutex<Vec<bool>>>,
+        }
+
+        #[derive(Deserialize)]
+        struct RestartingAppConf {}
+
+        impl Configurable for RestartingApp {
+            type Config = RestartingAppConf;
+
+            fn from(_app_conf: Self::Config) -> Self {
+                Default::default()
+            }
+        }
+
+        impl Runnable for RestartingApp {
+            fn setup(&self, handle: ClockworkHandle) {
+                let ticks = Arc::new(AtomicU32::new(0));
+                self.ticks.lock().unwrap().push(ticks.clone());
+                let multi_thread = self.multi_thread.clone();
+                handle.schedule_repeating_task(
+                    move || {
+                        ticks.fetch_add(1, Ordering::SeqCst);
+                        let flavor = Handle::current().runtime_flavor();
+                        multi_thread
+                            .lock()
+                            .unwrap()
+                            .push(flavor == RuntimeFlavor::MultiThread);
+                    },
+                    Duration::from_millis(5),
+                );
+            }
+        }
+
+        let cw = Clockwork::default().with_run_for(Duration::from_millis(30));
+        let mut cw_app = ClockworkApp::from_parts(cw, RestartingApp::default());
+        cw_app.start().unwrap();
+        let old_ticks = cw_app.app().ticks.lock().unwrap()[0].clone();
+        let ticked = old_ticks.load(Ordering::SeqCst);
+        assert!(ticked > 0);
+        cw_app.app().multi_thread.lock().unwrap().clear();
+
+        let rt = Builder::new_multi_thread().enable_all().build().unwrap();
+        let cw = Clockwork::new(ClockworkHandle::new(Arc::new(rt)))
+            .with_run_for(Duration::from_millis(30));
+        let report = cw_app.restart(cw).unwrap();
+        assert_eq!(report.reason, StopReason::RunFor);
+
+        // `setup` ran again, and only the new task ran, on the new runtime
+        assert_eq!(cw_app.app().ticks.lock().unwrap().len(), 2);
+        assert!(cw_app.app().ticks.lock().unwrap()[1].load(Ordering::SeqCst) > 0);
+        assert_eq!(old_ticks.load(Ordering::SeqCst), ticked);
+        let multi_thread = cw_app.app().multi_thread.lock().unwrap().clone();
+        assert!(!multi_thread.is_empty());
+        assert!(multi_thread.iter().all(|multi_thread| *multi_thread));
+        // the old task was dropped along with its runtime
+        assert_eq!(Arc::strong_count(&old_ticks), 2);
+    }
+
+    #[test]
+    fn test_setup_retries() {
+        #[derive(Default)]
+        struct FlakyApp {
+            events: Mutex<Vec<&'static str>>,
+        }
+
+        #[derive(Deserialize)]
+        struct FlakyAppConf {}
+
+        impl Configurable for FlakyApp {
+            type Config = FlakyAppConf;
+
+            fn from(_app_conf: Self::Config) -> Self {
+                Default::default()
+            }
+        }
+
+        impl Runnable for FlakyApp {
+            fn try_setup(&self, _handle: ClockworkHandle) -> Result<(), Box<dyn Error>> {
+                let mut events = self.events.lock().unwrap();
+                events.push("setup");
+                match events.len() {
+                    1 | 2 => Err("broker not ready".into()),
+                    _ => Ok(()),
+                }
+            }
+
+            fn on_ready(&self, handle: ClockworkHandle) {
+                self.events.lock().unwrap().push("on_ready");
+                handle.stop();
+            }
+        }
+
+        let conf_str = r#"
+            [clockwork]
+            setup_retries = 2
+            setup_retry_delay = '1ms'
+
+            [logger]
+            install_subscriber = false
+            write_target = 'STDOUT'
+
+            [app]
+        "#
+        .to_string();
+
+        let cw_app: ClockworkApp<FlakyApp> = ClockworkApp::from_config_str(conf_str);
+        cw_app.start().unwrap();
+        assert_eq!(
+            *cw_app.app().events.lock().unwrap(),
+            vec!["setup", "setup", "setup", "on_ready"]
+        );
     }
 }
@@ -0,0 +1,156 @@
+use crate::{ClockworkHandle, Runnable, StopSignal};
+use std::error::Error;
+use std::future::Future;
+use std::pin::Pin;
+
+/// A type-erased `Runnable`, for storing runnables of different types together (e.g. plugins
+/// picked at runtime) and handing any of them to the functions that take a `Runnable`.
+/// `dyn Runnable` itself is not a `Runnable` that can be passed by value, this wrapper is.
+///
+/// ```
+/// use clockwork::{run_runnable, BoxedRunnable, Clockwork, ClockworkHandle, Runnable};
+///
+/// struct Plugin;
+///
+/// impl Runnable for Plugin {
+///     fn setup(&self, handle: ClockworkHandle) {
+///         handle.stop();
+///     }
+/// }
+///
+/// let plugins = vec![
+///     BoxedRunnable::new(Plugin),
+///     BoxedRunnable::new(|handle: ClockworkHandle| handle.stop()),
+/// ];
+/// for plugin in plugins {
+///     run_runnable(Clockwork::default(), plugin);
+/// }
+/// ```
+pub struct BoxedRunnable(Box<dyn Runnable + Send + Sync>);
+
+impl BoxedRunnable {
+    pub fn new<R>(runnable: R) -> Self
+    where
+        R: Runnable + Send + Sync + 'static,
+    {
+        Self(Box::new(runnable))
+    }
+}
+
+impl From<Box<dyn Runnable + Send + Sync>> for BoxedRunnable {
+    fn from(runnable: Box<dyn Runnable + Send + Sync>) -> Self {
+        Self(runnable)
+    }
+}
+
+impl Runnable for BoxedRunnable {
+    fn setup(&self, handle: ClockworkHandle) {
+        self.0.setup(handle)
+    }
+
+    fn try_setup(&self, handle: ClockworkHandle) -> Result<(), Box<dyn Error>> {
+        self.0.try_setup(handle)
+    }
+
+    fn on_ready(&self, handle: ClockworkHandle) {
+        self.0.on_ready(handle)
+    }
+
+    fn shutdown(&self) {
+        self.0.shutdown()
+    }
+
+    fn run<'a>(
+        &'a self,
+        handle: ClockworkHandle,
+        stop: StopSignal,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>> {
+        self.0.run(handle, stop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clockwork_boxed::BoxedRunnable;
+    use crate::{run_runnable, AsyncRunnable, Clockwork, ClockworkHandle, Runnable, StopSignal};
+    use std::error::Error;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::{Arc, Mutex};
+
+    struct Recorder {
+        events: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    impl Runnable for Recorder {
+        fn try_setup(&self, handle: ClockworkHandle) -> Result<(), Box<dyn Error>> {
+            self.events.lock().unwrap().push("try_setup");
+            handle.stop();
+            Ok(())
+        }
+
+        fn on_ready(&self, _handle: ClockworkHandle) {
+            self.events.lock().unwrap().push("on_ready");
+        }
+
+        fn shutdown(&self) {
+            self.events.lock().unwrap().push("shutdown");
+        }
+
+        fn run<'a>(
+            &'a self,
+            _handle: ClockworkHandle,
+            stop: StopSignal,
+        ) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>> {
+            Box::pin(async move {
+                stop.await;
+                self.events.lock().unwrap().push("run");
+            })
+        }
+    }
+
+    #[test]
+    fn test_boxed_runnable_delegates() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let runnable = BoxedRunnable::new(Recorder {
+            events: events.clone(),
+        });
+
+        run_runnable(Clockwork::default(), runnable);
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec!["try_setup", "on_ready", "run", "shutdown"]
+        );
+    }
+
+    struct AsyncRecorder {
+        events: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    impl AsyncRunnable for AsyncRecorder {
+        fn setup(&self, handle: ClockworkHandle) {
+            self.events.lock().unwrap().push("setup");
+            handle.stop();
+        }
+
+        fn shutdown(&self) {
+            self.events.lock().unwrap().push("shutdown");
+        }
+
+        async fn run(&self, _handle: ClockworkHandle, stop: StopSignal) {
+            stop.await;
+            self.events.lock().unwrap().push("run");
+        }
+    }
+
+    #[test]
+    fn test_async_runnable_delegates() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let runnable = BoxedRunnable::new(AsyncRecorder {
+            events: events.clone(),
+        });
+
+        run_runnable(Clockwork::default(), runnable);
+        assert_eq!(*events.lock().unwrap(), vec!["setup", "run", "shutdown"]);
+    }
+}
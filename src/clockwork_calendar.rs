@@ -0,0 +1,88 @@
+use chrono::{DateTime, Days, LocalResult, NaiveDateTime, NaiveTime, TimeDelta, TimeZone, Utc};
+use chrono_tz::Tz;
+
+/// Resolves a local wall-clock time in `tz` to an instant.
+/// A time that occurs twice (DST fall-back) resolves to its first occurrence, a time that is
+/// skipped (DST spring-forward) resolves to the first instant after the gap.
+fn resolve_local(tz: Tz, local: NaiveDateTime) -> DateTime<Utc> {
+    let mut local = local;
+    loop {
+        match tz.from_local_datetime(&local) {
+            LocalResult::Single(dt) => return dt.with_timezone(&Utc),
+            LocalResult::Ambiguous(earliest, _) => return earliest.with_timezone(&Utc),
+            LocalResult::None => local += TimeDelta::minutes(1),
+        }
+    }
+}
+
+/// The first instant strictly after `after` at which the local time in `tz` is `time`
+pub(crate) fn next_daily_fire(after: DateTime<Utc>, time: NaiveTime, tz: Tz) -> DateTime<Utc> {
+    let mut date = after.with_timezone(&tz).date_naive();
+    loop {
+        let fire = resolve_local(tz, date.and_time(time));
+        if fire > after {
+            return fire;
+        }
+        date = date + Days::new(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clockwork_calendar::next_daily_fire;
+    use chrono::{DateTime, NaiveTime, Utc};
+    use chrono_tz::America::New_York;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    fn at(h: u32, m: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(h, m, 0).unwrap()
+    }
+
+    #[test]
+    fn test_next_daily_fire() {
+        // 01:00 EST, later the same day
+        let after = utc("2021-01-10T06:00:00Z");
+        assert_eq!(
+            next_daily_fire(after, at(2, 30), New_York),
+            utc("2021-01-10T07:30:00Z")
+        );
+
+        // exactly at the fire time, moves on to the next day
+        let after = utc("2021-01-10T07:30:00Z");
+        assert_eq!(
+            next_daily_fire(after, at(2, 30), New_York),
+            utc("2021-01-11T07:30:00Z")
+        );
+    }
+
+    #[test]
+    fn test_next_daily_fire_spring_forward() {
+        // 02:30 does not exist on 2021-03-14, fires as the clock jumps to 03:00 EDT
+        let after = utc("2021-03-14T05:00:00Z");
+        let fire = next_daily_fire(after, at(2, 30), New_York);
+        assert_eq!(fire, utc("2021-03-14T07:00:00Z"));
+
+        // and back to 02:30 EDT the day after
+        assert_eq!(
+            next_daily_fire(fire, at(2, 30), New_York),
+            utc("2021-03-15T06:30:00Z")
+        );
+    }
+
+    #[test]
+    fn test_next_daily_fire_fall_back() {
+        // 01:30 occurs twice on 2021-11-07, fires once on the first (EDT) occurrence
+        let after = utc("2021-11-07T04:00:00Z");
+        let fire = next_daily_fire(after, at(1, 30), New_York);
+        assert_eq!(fire, utc("2021-11-07T05:30:00Z"));
+
+        // the second (EST) occurrence is skipped
+        assert_eq!(
+            next_daily_fire(fire, at(1, 30), New_York),
+            utc("2021-11-08T06:30:00Z")
+        );
+    }
+}
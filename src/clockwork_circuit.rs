@@ -0,0 +1,185 @@
+use std::fmt::{Display, Formatter};
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::time::Duration;
+
+/// State of a `CircuitBreaker`
+/// Closed - runs go through, consecutive failures are counted
+/// Open - runs are skipped until the cooldown has elapsed
+/// HalfOpen - a single trial run is going through, it decides whether to close or re-open
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+/// Error returned by a task wrapped in a `CircuitBreaker`
+#[derive(Debug, PartialEq)]
+pub enum CircuitError<E> {
+    /// The circuit is open, the task was not run
+    Open,
+    /// The task was run and failed
+    Failed(E),
+}
+
+impl<E: Display> Display for CircuitError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CircuitError::Open => write!(f, "Circuit open, run skipped"),
+            CircuitError::Failed(e) => e.fmt(f),
+        }
+    }
+}
+
+struct Circuit {
+    state: CircuitState,
+    consecutive_failures: u32,
+    opened_at: Instant,
+}
+
+/// Trips after `threshold` consecutive failures of the task it wraps, after which runs are
+/// skipped for `cooldown`. Once the cooldown has elapsed, one trial run is let through: the circuit
+/// closes again if it succeeds, and re-opens for another `cooldown` if it fails.
+/// Clones share the same circuit, keep one around to inspect its `state`.
+///
+/// ```
+/// use clockwork::{CircuitBreaker, Clockwork};
+/// use tokio::time::Duration;
+///
+/// let cw = Clockwork::default();
+/// let breaker = CircuitBreaker::new(3, Duration::from_secs(30));
+/// cw.schedule_named_repeating_task(
+///     "poll_upstream",
+///     breaker.wrap(|| Err::<(), _>("upstream unavailable")),
+///     Duration::from_secs(1),
+/// );
+/// ```
+#[derive(Clone)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    circuit: Arc<Mutex<Circuit>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            cooldown,
+            circuit: Arc::new(Mutex::new(Circuit {
+                state: CircuitState::Closed,
+                consecutive_failures: 0,
+                opened_at: Instant::now(),
+            })),
+        }
+    }
+
+    /// The current state of the circuit
+    pub fn state(&self) -> CircuitState {
+        self.circuit.lock().unwrap().state
+    }
+
+    /// Wraps a fallible task so that it only runs while the circuit allows it.
+    /// The wrapped task can be passed to `schedule_named_repeating_task`, skipped runs are then
+    /// recorded as failures with `CircuitError::Open`.
+    pub fn wrap<F, E>(&self, f: F) -> impl Fn() -> Result<(), CircuitError<E>>
+    where
+        F: Fn() -> Result<(), E>,
+    {
+        let breaker = self.clone();
+        move || {
+            if !breaker.try_acquire() {
+                return Err(CircuitError::Open);
+            }
+
+            // A panicking run counts as a failure, the panic is passed on to the caller
+            let result = match catch_unwind(AssertUnwindSafe(&f)) {
+                Ok(result) => result,
+                Err(payload) => {
+                    breaker.record(false);
+                    resume_unwind(payload)
+                }
+            };
+            breaker.record(result.is_ok());
+            result.map_err(CircuitError::Failed)
+        }
+    }
+
+    /// Whether a run may go through, moves an open circuit past its cooldown to half-open
+    fn try_acquire(&self) -> bool {
+        let mut circuit = self.circuit.lock().unwrap();
+        match circuit.state {
+            CircuitState::Closed => true,
+            CircuitState::HalfOpen => false,
+            CircuitState::Open if circuit.opened_at.elapsed() >= self.cooldown => {
+                circuit.state = CircuitState::HalfOpen;
+                true
+            }
+            CircuitState::Open => false,
+        }
+    }
+
+    fn record(&self, succeeded: bool) {
+        let mut circuit = self.circuit.lock().unwrap();
+        if succeeded {
+            circuit.state = CircuitState::Closed;
+            circuit.consecutive_failures = 0;
+            return;
+        }
+
+        circuit.consecutive_failures += 1;
+        if circuit.state == CircuitState::HalfOpen || circuit.consecutive_failures >= self.threshold
+        {
+            circuit.state = CircuitState::Open;
+            circuit.opened_at = Instant::now();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clockwork_circuit::{CircuitBreaker, CircuitError, CircuitState};
+    use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+    use std::sync::Arc;
+    use tokio::time::Duration;
+
+    #[test]
+    fn test_circuit_breaker() {
+        let breaker = CircuitBreaker::new(2, Duration::from_millis(20));
+        let healthy = Arc::new(AtomicBool::new(false));
+        let calls = Arc::new(AtomicU32::new(0));
+        let task = {
+            let healthy = healthy.clone();
+            let calls = calls.clone();
+            breaker.wrap(move || {
+                calls.fetch_add(1, Ordering::SeqCst);
+                match healthy.load(Ordering::SeqCst) {
+                    true => Ok(()),
+                    false => Err("unhealthy"),
+                }
+            })
+        };
+
+        assert_eq!(task(), Err(CircuitError::Failed("unhealthy")));
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert_eq!(task(), Err(CircuitError::Failed("unhealthy")));
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert_eq!(task(), Err(CircuitError::Open));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // trial run fails, re-opens straight away
+        std::thread::sleep(Duration::from_millis(25));
+        assert_eq!(task(), Err(CircuitError::Failed("unhealthy")));
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert_eq!(task(), Err(CircuitError::Open));
+
+        // trial run succeeds, closes
+        healthy.store(true, Ordering::SeqCst);
+        std::thread::sleep(Duration::from_millis(25));
+        assert_eq!(task(), Ok(()));
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+    }
+}
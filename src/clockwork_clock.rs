@@ -0,0 +1,168 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tokio::sync::Notify;
+use tokio::time::{Duration, Instant, MissedTickBehavior};
+
+/// The source of time behind a `ClockworkHandle`'s scheduling.
+/// Defaults to `TokioClock`, swap in a `ManualClock` (see `ClockworkHandle::with_clock`) to drive
+/// scheduled tasks deterministically from tests.
+pub trait Clock: Send + Sync + 'static {
+    /// The current time
+    fn now(&self) -> Instant;
+
+    /// The current wall-clock time, what calendar scheduling (e.g. `schedule_daily_at`) goes by.
+    /// Defaults to the system's.
+    fn system_now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    /// Resolves once the clock has reached `deadline`
+    fn sleep_until(&self, deadline: Instant) -> Pin<Box<dyn Future<Output = ()> + Send + '_>>;
+
+    /// Resolves once `duration` has elapsed on the clock
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send + '_>> {
+        self.sleep_until(self.now() + duration)
+    }
+}
+
+/// Real time, as kept by the tokio runtime (including tokio's paused time in tests)
+#[derive(Default)]
+pub struct TokioClock;
+
+impl Clock for TokioClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> Pin<Box<dyn Future<Output = ()> + Send + '_>> {
+        Box::pin(tokio::time::sleep_until(deadline))
+    }
+}
+
+/// A clock that only moves when told to, through `advance`.
+/// Its wall-clock time moves along with it, from the system's time at creation unless set with
+/// `at_system_time`.
+///
+/// ```
+/// use clockwork::{Clockwork, ClockworkHandle, ManualClock};
+/// use std::sync::Arc;
+/// use tokio::time::Duration;
+///
+/// let clock = Arc::new(ManualClock::new());
+/// let rt = Arc::new(tokio::runtime::Runtime::new().unwrap());
+/// let cw = Clockwork::new(ClockworkHandle::new(rt).with_clock(clock.clone()));
+/// cw.schedule_oneof_task(|| println!("an hour later"), Duration::from_secs(60 * 60));
+///
+/// // fires the task straight away
+/// clock.advance(Duration::from_secs(60 * 60));
+/// ```
+pub struct ManualClock {
+    start: Instant,
+    system_start: SystemTime,
+    now: Mutex<Instant>,
+    advanced: Notify,
+}
+
+impl ManualClock {
+    /// Creates a clock that stands still at the current time
+    pub fn new() -> Self {
+        Self::at_system_time(SystemTime::now())
+    }
+
+    /// Creates a clock that stands still at the wall-clock time `system_time`, e.g. to drive
+    /// calendar scheduling across a DST transition
+    pub fn at_system_time(system_time: SystemTime) -> Self {
+        let start = Instant::now();
+        Self {
+            start,
+            system_start: system_time,
+            now: Mutex::new(start),
+            advanced: Notify::new(),
+        }
+    }
+
+    /// Moves the clock forward by `duration`, waking every sleeper whose deadline has passed
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+        self.advanced.notify_waiters();
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap()
+    }
+
+    fn system_now(&self) -> SystemTime {
+        self.system_start + (self.now() - self.start)
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> Pin<Box<dyn Future<Output = ()> + Send + '_>> {
+        Box::pin(async move {
+            loop {
+                // registered before checking, so an `advance` in between is not missed
+                let advanced = self.advanced.notified();
+                if self.now() >= deadline {
+                    return;
+                }
+                advanced.await;
+            }
+        })
+    }
+}
+
+pub(crate) type SharedClock = Arc<dyn Clock>;
+
+/// Ticks every `period` starting from `start`, as measured by a `Clock`.
+/// Missed ticks (ticks already due by the time the previous one fires) are handled according to the
+/// `MissedTickBehavior`, by default caught up on in a burst like `tokio::time::Interval` does.
+pub(crate) struct ClockInterval {
+    clock: SharedClock,
+    next: Instant,
+    period: Duration,
+    missed_tick_behavior: MissedTickBehavior,
+}
+
+impl ClockInterval {
+    pub(crate) fn new(clock: SharedClock, start: Instant, period: Duration) -> Self {
+        Self {
+            clock,
+            next: start,
+            period,
+            missed_tick_behavior: MissedTickBehavior::Burst,
+        }
+    }
+
+    pub(crate) fn with_missed_tick_behavior(mut self, behavior: MissedTickBehavior) -> Self {
+        self.missed_tick_behavior = behavior;
+        self
+    }
+
+    /// Waits for the next tick, returns the instant it was due at
+    pub(crate) async fn tick(&mut self) -> Instant {
+        let due = self.next;
+        self.clock.sleep_until(due).await;
+
+        let next = self.next + self.period;
+        let now = self.clock.now();
+        self.next = match self.missed_tick_behavior {
+            _ if next > now || self.period.is_zero() => next,
+            MissedTickBehavior::Burst => next,
+            MissedTickBehavior::Delay => now + self.period,
+            // the first tick after now that is still aligned with `start`
+            _ => {
+                let missed = (now - next).as_nanos() / self.period.as_nanos() + 1;
+                next + self.period * missed as u32
+            }
+        };
+        due
+    }
+}
@@ -0,0 +1,278 @@
+use crate::{ClockworkHandle, Runnable, StopSignal};
+use std::error::Error;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use std::task::Poll;
+use std::time::Duration;
+
+struct Stage {
+    name: String,
+    runnable: Arc<dyn Runnable + Send + Sync>,
+    depends_on: Vec<String>,
+}
+
+/// A `Runnable` made up of several named sub-runnables (stages), for apps built out of multiple
+/// services sharing one runtime.
+/// Stages are set up dependencies first, run concurrently, then shut down in reverse, i.e. a stage
+/// is shut down before anything it depends on. Stages without dependencies between them keep the
+/// order they were added in.
+///
+/// ```
+/// use clockwork::{run_runnable, Clockwork, ClockworkHandle, CompositeRunnable};
+/// use tokio::time::Duration;
+///
+/// let composite = CompositeRunnable::new()
+///     .with_stage("writer", |_handle: ClockworkHandle| {})
+///     .with_stage_depending_on("ingester", &["writer"], |handle: ClockworkHandle| {
+///         let stopper = handle.clone();
+///         handle.schedule_oneof_task(move || stopper.stop(), Duration::from_millis(10));
+///     })
+///     .with_shutdown_timeout(Duration::from_secs(5));
+///
+/// // set up: writer, ingester; shut down: ingester, writer
+/// run_runnable(Clockwork::default(), composite);
+/// ```
+#[derive(Default)]
+pub struct CompositeRunnable {
+    stages: Vec<Stage>,
+    shutdown_timeout: Option<Duration>,
+}
+
+impl CompositeRunnable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a stage with no dependencies
+    pub fn with_stage<R>(self, name: &str, runnable: R) -> Self
+    where
+        R: Runnable + Send + Sync + 'static,
+    {
+        self.with_stage_depending_on(name, &[], runnable)
+    }
+
+    /// Adds a stage that depends on the stages named in `depends_on`.
+    /// It is set up after them and shut down before them.
+    pub fn with_stage_depending_on<R>(
+        mut self,
+        name: &str,
+        depends_on: &[&str],
+        runnable: R,
+    ) -> Self
+    where
+        R: Runnable + Send + Sync + 'static,
+    {
+        self.stages.push(Stage {
+            name: name.to_string(),
+            runnable: Arc::new(runnable),
+            depends_on: depends_on.iter().map(|dep| dep.to_string()).collect(),
+        });
+        self
+    }
+
+    /// Limits how long each stage's `shutdown` may take. A stage that overruns is left to finish
+    /// in the background (and logged if the `logging` feature is enabled), shutdown then moves on
+    /// to the next stage. Without a timeout, each stage's `shutdown` is waited on indefinitely.
+    pub fn with_shutdown_timeout(mut self, shutdown_timeout: Duration) -> Self {
+        self.shutdown_timeout = Some(shutdown_timeout);
+        self
+    }
+
+    /// Stage indices, dependencies first
+    fn startup_order(&self) -> Vec<usize> {
+        let index_of = |name: &str| {
+            self.stages
+                .iter()
+                .position(|stage| stage.name == name)
+                .unwrap_or_else(|| panic!("CompositeRunnable has no `{}` stage!", name))
+        };
+
+        let mut order = Vec::with_capacity(self.stages.len());
+        let mut placed = vec![false; self.stages.len()];
+        while order.len() < self.stages.len() {
+            let next = (0..self.stages.len()).find(|&i| {
+                !placed[i]
+                    && self.stages[i]
+                        .depends_on
+                        .iter()
+                        .all(|dep| placed[index_of(dep)])
+            });
+
+            let next = next.expect("CompositeRunnable has a dependency cycle!");
+            placed[next] = true;
+            order.push(next);
+        }
+
+        order
+    }
+
+    fn shutdown_stage(&self, stage: &Stage) {
+        let shutdown_timeout = match self.shutdown_timeout {
+            Some(shutdown_timeout) => shutdown_timeout,
+            None => return stage.runnable.shutdown(),
+        };
+
+        let (done_tx, done_rx) = channel();
+        let runnable = Arc::clone(&stage.runnable);
+        std::thread::spawn(move || {
+            runnable.shutdown();
+            let _ = done_tx.send(());
+        });
+
+        if done_rx.recv_timeout(shutdown_timeout).is_err() {
+            #[cfg(feature = "logging")]
+            tracing::warn!(stage = %stage.name, ?shutdown_timeout, "Stage shutdown timed out");
+        }
+    }
+}
+
+impl Runnable for CompositeRunnable {
+    /// Panics if a stage fails to set up, see `try_setup`
+    fn setup(&self, handle: ClockworkHandle) {
+        if let Err(e) = self.try_setup(handle) {
+            panic!("Failed to set up CompositeRunnable stage: {}!", e);
+        }
+    }
+
+    /// Sets the stages up in dependency order, stops at the first stage that fails
+    fn try_setup(&self, handle: ClockworkHandle) -> Result<(), Box<dyn Error>> {
+        for i in self.startup_order() {
+            self.stages[i].runnable.try_setup(handle.clone())?;
+        }
+        Ok(())
+    }
+
+    fn on_ready(&self, handle: ClockworkHandle) {
+        for i in self.startup_order() {
+            self.stages[i].runnable.on_ready(handle.clone());
+        }
+    }
+
+    fn shutdown(&self) {
+        for i in self.startup_order().into_iter().rev() {
+            self.shutdown_stage(&self.stages[i]);
+        }
+    }
+
+    /// Runs every stage concurrently, resolves once all of them have finished
+    fn run<'a>(
+        &'a self,
+        handle: ClockworkHandle,
+        _stop: StopSignal,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>> {
+        let mut runs: Vec<_> = self
+            .stages
+            .iter()
+            .map(|stage| Some(stage.runnable.run(handle.clone(), handle.stop_signal())))
+            .collect();
+
+        Box::pin(std::future::poll_fn(move |cx| {
+            for run in runs.iter_mut() {
+                if let Some(future) = run {
+                    if future.as_mut().poll(cx).is_ready() {
+                        *run = None;
+                    }
+                }
+            }
+
+            match runs.iter().all(Option::is_none) {
+                true => Poll::Ready(()),
+                false => Poll::Pending,
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clockwork_composite::CompositeRunnable;
+    use crate::{run_runnable, Clockwork, ClockworkHandle, Runnable};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    struct Recorder {
+        name: &'static str,
+        events: Arc<Mutex<Vec<String>>>,
+        shutdown_delay: Duration,
+    }
+
+    impl Runnable for Recorder {
+        fn setup(&self, handle: ClockworkHandle) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("setup {}", self.name));
+            handle.stop();
+        }
+
+        fn shutdown(&self) {
+            std::thread::sleep(self.shutdown_delay);
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("shutdown {}", self.name));
+        }
+    }
+
+    #[test]
+    fn test_composite_order() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorder = |name| Recorder {
+            name,
+            events: events.clone(),
+            shutdown_delay: Duration::ZERO,
+        };
+
+        let composite = CompositeRunnable::new()
+            .with_stage_depending_on("ingester", &["writer", "store"], recorder("ingester"))
+            .with_stage_depending_on("writer", &["store"], recorder("writer"))
+            .with_stage("store", recorder("store"));
+        run_runnable(Clockwork::default(), composite);
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                "setup store",
+                "setup writer",
+                "setup ingester",
+                "shutdown ingester",
+                "shutdown writer",
+                "shutdown store"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_composite_shutdown_timeout() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let composite = CompositeRunnable::new()
+            .with_stage(
+                "slow",
+                Recorder {
+                    name: "slow",
+                    events: events.clone(),
+                    shutdown_delay: Duration::from_secs(5),
+                },
+            )
+            .with_shutdown_timeout(Duration::from_millis(10));
+
+        let start = Instant::now();
+        run_runnable(Clockwork::default(), composite);
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(!events
+            .lock()
+            .unwrap()
+            .contains(&"shutdown slow".to_string()));
+    }
+
+    #[test]
+    #[should_panic(expected = "dependency cycle")]
+    fn test_composite_cycle() {
+        CompositeRunnable::new()
+            .with_stage_depending_on("a", &["b"], |_: ClockworkHandle| {})
+            .with_stage_depending_on("b", &["a"], |_: ClockworkHandle| {})
+            .setup(Clockwork::default().handle());
+    }
+}
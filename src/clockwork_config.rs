@@ -1,21 +1,123 @@
-use serde::Deserialize;
+use crate::{Duration, WaitForConfig};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
 
 const fn default_as_true() -> bool {
     true
 }
 
-const fn default_max_thread() -> usize {
+const fn default_max_blocking_threads() -> usize {
     512
 }
 
-#[derive(Deserialize)]
+const fn default_task_history_size() -> usize {
+    16
+}
+
+fn default_setup_retry_delay() -> Duration {
+    std::time::Duration::from_secs(1).into()
+}
+
+/// Reads a thread count, rejecting 0 (tokio panics on a runtime without threads)
+fn deserialize_thread_count<'de, D: Deserializer<'de>>(deserializer: D) -> Result<usize, D::Error> {
+    match usize::deserialize(deserializer)? {
+        0 => Err(D::Error::custom("thread count must be at least 1")),
+        count => Ok(count),
+    }
+}
+
+/// Same as `deserialize_thread_count`, for an optional thread count
+fn deserialize_opt_thread_count<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<usize>, D::Error> {
+    deserialize_thread_count(deserializer).map(Some)
+}
+
+/// Reads an optional task limit, rejecting 0 (no spawned task would ever start)
+fn deserialize_opt_task_limit<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<usize>, D::Error> {
+    match usize::deserialize(deserializer)? {
+        0 => Err(D::Error::custom("task limit must be at least 1")),
+        limit => Ok(Some(limit)),
+    }
+}
+
+/// Reads an optional threshold, rejecting a zero one
+fn deserialize_opt_threshold<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error> {
+    match Duration::deserialize(deserializer)? {
+        threshold if threshold.as_std().is_zero() => {
+            Err(D::Error::custom("threshold must be non-zero"))
+        }
+        threshold => Ok(Some(threshold)),
+    }
+}
+
+/// What to do when a task is spawned while `max_concurrent_tasks` tasks are already running
+/// WAIT - the task is spawned but only starts once a running task completes
+/// REJECT - the task is not spawned at all
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Deserialize, Serialize, Copy, Clone, PartialEq, Debug, Default)]
+pub enum TaskLimitPolicy {
+    #[default]
+    WAIT,
+    REJECT,
+}
+
+/// What to do when a named task is scheduled while a task of the same name is still scheduled
+/// REPLACE - the earlier task is unscheduled in favour of the new one (its history is kept)
+/// REJECT - the new task is not scheduled
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Deserialize, Serialize, Copy, Clone, PartialEq, Debug, Default)]
+pub enum DuplicateTaskPolicy {
+    #[default]
+    REPLACE,
+    REJECT,
+}
+
+/// Runtime configurations
+/// enable_io - enables the IO driver
+/// enable_time - enables the time driver
+/// max_blocking_threads - maximum number of threads in the blocking pool, used by blocking tasks
+///                        (not by the scheduled tasks, may also be written `max_threads`,
+///                        deprecated, must not be 0)
+/// worker_threads - runs a multi-thread runtime with this many worker threads, the scheduled tasks
+///                  run on them (a current-thread runtime if omitted, must not be 0)
+/// max_concurrent_tasks - maximum number of futures spawned through `spawn_task` running at once,
+///                        scheduled tasks do not count (unlimited if omitted, must not be 0)
+/// task_limit_policy - what to do when `max_concurrent_tasks` is reached (WAIT/REJECT)
+/// task_history_size - number of recent runs kept per named task (0 disables the history)
+/// duplicate_task_policy - what to do when a named task is scheduled twice (REPLACE/REJECT)
+/// late_tick_threshold - logs a warning whenever a repeating task runs more than this long after
+///                       the tick it was due at, e.g. `'500ms'` (not checked if omitted)
+#[derive(Deserialize, Serialize, Clone)]
 pub struct RuntimeConfig {
     #[serde(default = "default_as_true")]
     pub enable_io: bool,
     #[serde(default = "default_as_true")]
     pub enable_time: bool,
-    #[serde(default = "default_max_thread")]
-    pub max_threads: usize,
+    #[serde(
+        default = "default_max_blocking_threads",
+        alias = "max_threads",
+        deserialize_with = "deserialize_thread_count"
+    )]
+    pub max_blocking_threads: usize,
+    #[serde(default, deserialize_with = "deserialize_opt_thread_count")]
+    pub worker_threads: Option<usize>,
+    #[serde(default, deserialize_with = "deserialize_opt_task_limit")]
+    pub max_concurrent_tasks: Option<usize>,
+    #[serde(default)]
+    pub task_limit_policy: TaskLimitPolicy,
+    #[serde(default = "default_task_history_size")]
+    pub task_history_size: usize,
+    #[serde(default)]
+    pub duplicate_task_policy: DuplicateTaskPolicy,
+    #[serde(default)]
+    pub late_tick_threshold: Option<Duration>,
 }
 
 impl Default for RuntimeConfig {
@@ -23,35 +125,173 @@ impl Default for RuntimeConfig {
         RuntimeConfig {
             enable_io: default_as_true(),
             enable_time: default_as_true(),
-            max_threads: default_max_thread(),
+            max_blocking_threads: default_max_blocking_threads(),
+            worker_threads: None,
+            max_concurrent_tasks: None,
+            task_limit_policy: Default::default(),
+            task_history_size: default_task_history_size(),
+            duplicate_task_policy: Default::default(),
+            late_tick_threshold: None,
         }
     }
 }
 
-#[derive(Deserialize)]
+/// Clockwork configurations
+/// run_for - stops the runtime this long after it starts running, e.g. `'1m30s'` or a number of
+///           seconds (runs until stopped if omitted, may also be written `run_for_secs`)
+/// idle_timeout - stops the runtime once no scheduled task has fired for this long (never stops
+///                for being idle if omitted, may also be written `idle_timeout_secs`)
+/// stop_when_done - stops the runtime once no task is left to run, i.e. every one-shot task has run
+///                  and no repeating task is scheduled, for batch runs
+/// dry_run - logs what would be scheduled instead of scheduling it, no scheduled task is run
+/// deny_unknown_keys - fails loading the config if the `clockwork` or `logger` section has a key
+///                     that is not a known setting (e.g. a typo), such keys are only warned about
+///                     on stderr otherwise
+/// persist_state - file to persist named repeating tasks' last-run times to, so that they do not
+///                 run again within their period after a restart (not persisted if omitted)
+/// setup_retries - number of times a failing `Runnable::try_setup` is retried before giving up
+/// setup_retry_delay - delay before the first setup retry, doubled for every retry after it, e.g.
+///                     `'500ms'` (defaults to 1s)
+/// stall_threshold - watches the runtime from a separate thread and logs an error once it has made
+///                   no progress for this long, e.g. because a task blocks its thread (not watched
+///                   if omitted, must not be 0)
+/// abort_on_stall - aborts the process once the runtime has stalled for `stall_threshold`
+/// flags - initial values of the handle's feature flags, e.g. `reports = true` (see `FlagStore`)
+/// task_periods - periods to run named repeating tasks at instead of the ones they are scheduled
+///                with, by task name, e.g. `report = '30s'` (see `ClockworkHandle::set_task_period`)
+/// runtime - runtime configurations (see `RuntimeConfig` struct)
+/// wait_for - dependencies to wait for before the app is set up (see `WaitForConfig` struct)
+/// metrics - serves Prometheus metrics when set (see `MetricsConfig` struct, `metrics` feature only)
+/// Tables have to come last for the config to serialize back into TOML.
+#[derive(Deserialize, Serialize, Clone)]
 pub struct ClockworkConfig {
+    #[serde(default)]
+    #[serde(alias = "run_for_secs")]
+    pub run_for: Option<Duration>,
+    #[serde(default)]
+    #[serde(alias = "idle_timeout_secs")]
+    pub idle_timeout: Option<Duration>,
+    #[serde(default)]
+    pub stop_when_done: bool,
+    #[serde(default)]
+    pub dry_run: bool,
+    #[serde(default)]
+    pub deny_unknown_keys: bool,
+    #[serde(default)]
+    pub persist_state: Option<PathBuf>,
+    #[serde(default)]
+    pub setup_retries: u32,
+    #[serde(default = "default_setup_retry_delay")]
+    pub setup_retry_delay: Duration,
+    #[serde(default, deserialize_with = "deserialize_opt_threshold")]
+    pub stall_threshold: Option<Duration>,
+    #[serde(default)]
+    pub abort_on_stall: bool,
+    #[serde(default)]
+    pub flags: HashMap<String, bool>,
+    #[serde(default)]
+    pub task_periods: HashMap<String, Duration>,
     #[serde(default)]
     pub runtime: RuntimeConfig,
+    #[serde(default)]
+    pub wait_for: Option<WaitForConfig>,
+    #[cfg(feature = "metrics")]
+    #[serde(default)]
+    pub metrics: Option<crate::clockwork_metrics::MetricsConfig>,
 }
 
 impl Default for ClockworkConfig {
     fn default() -> Self {
         ClockworkConfig {
-            runtime: RuntimeConfig::default(),
+            run_for: None,
+            idle_timeout: None,
+            stop_when_done: false,
+            dry_run: false,
+            deny_unknown_keys: false,
+            persist_state: None,
+            setup_retries: 0,
+            setup_retry_delay: default_setup_retry_delay(),
+            stall_threshold: None,
+            abort_on_stall: false,
+            flags: HashMap::new(),
+            task_periods: HashMap::new(),
+            runtime: Default::default(),
+            wait_for: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::clockwork_config::{DuplicateTaskPolicy, TaskLimitPolicy};
     use crate::ClockworkConfig;
+    use std::time::Duration;
 
     #[test]
     fn test_default_runtime_conf() {
         let conf: ClockworkConfig = toml::from_str("").unwrap();
 
-        assert_eq!(conf.runtime.enable_time, true);
-        assert_eq!(conf.runtime.enable_io, true);
-        assert_eq!(conf.runtime.max_threads, 512);
+        assert!(conf.runtime.enable_time);
+        assert!(conf.runtime.enable_io);
+        assert_eq!(conf.runtime.max_blocking_threads, 512);
+        assert_eq!(conf.runtime.worker_threads, None);
+        assert_eq!(conf.runtime.max_concurrent_tasks, None);
+        assert_eq!(conf.runtime.task_limit_policy, TaskLimitPolicy::WAIT);
+        assert_eq!(conf.runtime.task_history_size, 16);
+        assert_eq!(
+            conf.runtime.duplicate_task_policy,
+            DuplicateTaskPolicy::REPLACE
+        );
+        assert_eq!(conf.run_for, None);
+        assert_eq!(conf.idle_timeout, None);
+        assert!(!conf.dry_run);
+        assert!(conf.persist_state.is_none());
+        assert_eq!(conf.setup_retries, 0);
+        assert_eq!(conf.setup_retry_delay.as_std(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_zero_values_rejected() {
+        for conf in [
+            "[runtime]\nworker_threads = 0",
+            "[runtime]\nmax_blocking_threads = 0",
+            "[runtime]\nmax_threads = 0",
+        ] {
+            let err = toml::from_str::<ClockworkConfig>(conf).err().unwrap();
+            assert!(err.to_string().contains("thread count must be at least 1"));
+        }
+
+        let err = toml::from_str::<ClockworkConfig>("[runtime]\nmax_concurrent_tasks = 0")
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("task limit must be at least 1"));
+
+        let err = toml::from_str::<ClockworkConfig>("stall_threshold = '0s'")
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("threshold must be non-zero"));
+
+        let conf: ClockworkConfig = toml::from_str("[runtime]\nworker_threads = 2").unwrap();
+        assert_eq!(conf.runtime.worker_threads, Some(2));
+    }
+
+    #[test]
+    fn test_duration_fields() {
+        let conf: ClockworkConfig =
+            toml::from_str("run_for = '1m30s'\nidle_timeout_secs = 30").unwrap();
+
+        assert_eq!(conf.run_for.unwrap().as_std(), Duration::from_secs(90));
+        assert_eq!(conf.idle_timeout.unwrap().as_std(), Duration::from_secs(30));
+
+        let conf: ClockworkConfig =
+            toml::from_str("setup_retry_delay = '250ms'\n[runtime]\nlate_tick_threshold = '500ms'")
+                .unwrap();
+        assert_eq!(conf.setup_retry_delay.as_std(), Duration::from_millis(250));
+        assert_eq!(
+            conf.runtime.late_tick_threshold.unwrap().as_std(),
+            Duration::from_millis(500)
+        );
     }
 }
@@ -0,0 +1,120 @@
+use crate::{TaskInfo, TaskKind};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::Instant;
+
+/// Renders the repeating tasks in `tasks` as crontab lines, see `ClockworkHandle::export_crontab`.
+/// `now` and `wall_now` are the same moment on the handle's clock and on the wall clock.
+pub(crate) fn export(tasks: &[TaskInfo], now: Instant, wall_now: SystemTime) -> String {
+    let mut crontab = String::from("# Exported from clockwork, times are in UTC\n");
+    for task in tasks.iter().filter(|task| task.kind == TaskKind::Repeating) {
+        let period = match task.period {
+            Some(period) => period,
+            None => continue,
+        };
+
+        let next_fire = wall_now + task.next_fire.saturating_duration_since(now);
+        let line = match schedule(period, next_fire) {
+            Some(schedule) if task.paused => format!("# paused: {} {}", schedule, task.name),
+            Some(schedule) => format!("{} {}", schedule, task.name),
+            None => format!(
+                "# {}: every {}, not expressible in cron",
+                task.name,
+                crate::Duration::from(period)
+            ),
+        };
+        crontab.push_str(&line);
+        crontab.push('\n');
+    }
+
+    crontab
+}
+
+/// The five time fields of a cron schedule running every `period`, in phase with `next_fire`.
+/// `None` unless `period` is a whole number of minutes that divides an hour, or a whole number of
+/// hours that divides a day.
+fn schedule(period: Duration, next_fire: SystemTime) -> Option<String> {
+    if period.subsec_nanos() != 0 || period.as_secs() == 0 || !period.as_secs().is_multiple_of(60) {
+        return None;
+    }
+
+    let minutes = period.as_secs() / 60;
+    let fire_minutes = next_fire.duration_since(UNIX_EPOCH).ok()?.as_secs() / 60;
+    let (minute, hour) = (fire_minutes % 60, fire_minutes / 60 % 24);
+    // every `step`th value out of `0..count`, starting from the one in phase with `first`
+    let steps = |first: u64, step: u64, count: u64| {
+        (first % step..count)
+            .step_by(step as usize)
+            .map(|value| value.to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+
+    match minutes {
+        1 => Some("* * * * *".to_string()),
+        _ if 60u64.is_multiple_of(minutes) => {
+            Some(format!("{} * * * *", steps(minute, minutes, 60)))
+        }
+        _ if minutes.is_multiple_of(60) && 24u64.is_multiple_of(minutes / 60) => Some(format!(
+            "{} {} * * *",
+            minute,
+            steps(hour, minutes / 60, 24)
+        )),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clockwork_crontab::export;
+    use crate::{TaskInfo, TaskKind};
+    use std::time::{Duration, UNIX_EPOCH};
+    use tokio::time::Instant;
+
+    #[test]
+    fn test_export() {
+        let now = Instant::now();
+        // 1970-01-02 00:00 UTC
+        let wall_now = UNIX_EPOCH + Duration::from_secs(24 * 60 * 60);
+        let task = |name: &str, kind, period: u64, fire_in: u64, paused| TaskInfo {
+            name: name.to_string(),
+            kind,
+            period: Some(Duration::from_secs(period)).filter(|_| kind == TaskKind::Repeating),
+            next_fire: now + Duration::from_secs(fire_in),
+            paused,
+            group: None,
+        };
+
+        let tasks = [
+            task("every_minute", TaskKind::Repeating, 60, 30, false),
+            task("quarterly", TaskKind::Repeating, 15 * 60, 7 * 60, false),
+            task(
+                "six_hourly",
+                TaskKind::Repeating,
+                6 * 60 * 60,
+                2 * 60 * 60 + 5 * 60,
+                false,
+            ),
+            task(
+                "daily",
+                TaskKind::Repeating,
+                24 * 60 * 60,
+                3 * 60 * 60 + 30 * 60,
+                true,
+            ),
+            task("heartbeat", TaskKind::Repeating, 90, 0, false),
+            task("seven_minutes", TaskKind::Repeating, 7 * 60, 0, false),
+            task("once", TaskKind::OneShot, 0, 60, false),
+        ];
+
+        assert_eq!(
+            export(&tasks, now, wall_now),
+            "# Exported from clockwork, times are in UTC\n\
+             * * * * * every_minute\n\
+             7,22,37,52 * * * * quarterly\n\
+             5 2,8,14,20 * * * six_hourly\n\
+             # paused: 30 3 * * * daily\n\
+             # heartbeat: every 1m30s, not expressible in cron\n\
+             # seven_minutes: every 7m, not expressible in cron\n"
+        );
+    }
+}
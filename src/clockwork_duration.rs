@@ -0,0 +1,229 @@
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Units accepted by `Duration`, largest first, with their length in nanoseconds
+const UNITS: [(&str, u128); 7] = [
+    ("d", 24 * 60 * 60 * 1_000_000_000),
+    ("h", 60 * 60 * 1_000_000_000),
+    ("m", 60 * 1_000_000_000),
+    ("s", 1_000_000_000),
+    ("ms", 1_000_000),
+    ("us", 1_000),
+    ("ns", 1),
+];
+
+/// A duration that reads from config as a human-readable string such as `"1500ms"` or `"2m30s"`.
+/// A string is a sequence of whole numbers, each followed by a unit: `d`, `h`, `m`, `s`, `ms`,
+/// `us` or `ns`. A plain integer is read as a number of seconds.
+/// Converts to and from `std::time::Duration` (which `tokio::time::Duration` is).
+///
+/// ```
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct EchoConfig {
+///     run_duration: clockwork::Duration,
+/// }
+///
+/// let conf: EchoConfig = toml::from_str("run_duration = '2m30s'").unwrap();
+/// assert_eq!(conf.run_duration.as_std(), std::time::Duration::from_secs(150));
+/// ```
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Duration(pub std::time::Duration);
+
+impl Duration {
+    /// The wrapped `std::time::Duration`
+    pub fn as_std(&self) -> std::time::Duration {
+        self.0
+    }
+}
+
+impl From<std::time::Duration> for Duration {
+    fn from(duration: std::time::Duration) -> Self {
+        Self(duration)
+    }
+}
+
+impl From<Duration> for std::time::Duration {
+    fn from(duration: Duration) -> Self {
+        duration.0
+    }
+}
+
+/// Error returned when a string is not a valid `Duration`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseDurationError {
+    input: String,
+}
+
+impl Display for ParseDurationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "invalid duration `{}`, expected e.g. `1500ms` or `2m30s` (units: d, h, m, s, ms, us, ns)",
+            self.input
+        )
+    }
+}
+
+impl Error for ParseDurationError {}
+
+impl FromStr for Duration {
+    type Err = ParseDurationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseDurationError {
+            input: s.to_string(),
+        };
+
+        let mut rest = s.trim();
+        if rest.is_empty() {
+            return Err(err());
+        }
+
+        let mut nanos: u128 = 0;
+        while !rest.is_empty() {
+            let digits = rest.find(|c: char| !c.is_ascii_digit()).ok_or_else(err)?;
+            let value: u128 = rest[..digits].parse().map_err(|_| err())?;
+            rest = &rest[digits..];
+
+            let unit_len = rest
+                .find(|c: char| !c.is_ascii_alphabetic())
+                .unwrap_or(rest.len());
+            let (_, unit_nanos) = UNITS
+                .iter()
+                .find(|(unit, _)| *unit == &rest[..unit_len])
+                .ok_or_else(err)?;
+            nanos = value
+                .checked_mul(*unit_nanos)
+                .and_then(|n| n.checked_add(nanos))
+                .ok_or_else(err)?;
+            rest = rest[unit_len..].trim_start();
+        }
+
+        let secs = u64::try_from(nanos / 1_000_000_000).map_err(|_| err())?;
+        Ok(Self(std::time::Duration::new(
+            secs,
+            (nanos % 1_000_000_000) as u32,
+        )))
+    }
+}
+
+/// Formats in the same form as it is parsed, e.g. `2m30s`
+impl Display for Duration {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut nanos = self.0.as_nanos();
+        if nanos == 0 {
+            return write!(f, "0s");
+        }
+
+        for (unit, unit_nanos) in UNITS.iter() {
+            if nanos >= *unit_nanos {
+                write!(f, "{}{}", nanos / unit_nanos, unit)?;
+                nanos %= unit_nanos;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for Duration {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+struct DurationVisitor;
+
+impl<'de> Visitor<'de> for DurationVisitor {
+    type Value = Duration;
+
+    fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "a duration such as `1500ms` or `2m30s`, or a number of seconds"
+        )
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        v.parse().map_err(E::custom)
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        Ok(Duration(std::time::Duration::from_secs(v)))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+        u64::try_from(v)
+            .map_err(|_| E::custom(format!("invalid duration `{}`, cannot be negative", v)))
+            .and_then(|v| self.visit_u64(v))
+    }
+}
+
+impl<'de> Deserialize<'de> for Duration {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(DurationVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Duration;
+    use serde::{Deserialize, Serialize};
+    use std::time::Duration as StdDuration;
+
+    #[test]
+    fn test_parse() {
+        let parse = |s: &str| s.parse::<Duration>().map(|d| d.as_std());
+
+        assert_eq!(parse("1500ms"), Ok(StdDuration::from_millis(1500)));
+        assert_eq!(parse("2m30s"), Ok(StdDuration::from_secs(150)));
+        assert_eq!(parse("1h 5m"), Ok(StdDuration::from_secs(3900)));
+        assert_eq!(parse("1d"), Ok(StdDuration::from_secs(24 * 60 * 60)));
+        assert_eq!(parse("250us3ns"), Ok(StdDuration::from_nanos(250_003)));
+        for invalid in ["", "10", "ms", "1.5s", "10 parsecs", "-1s"] {
+            assert!(parse(invalid).is_err(), "{}", invalid);
+        }
+        assert_eq!(
+            parse("10x").unwrap_err().to_string(),
+            "invalid duration `10x`, expected e.g. `1500ms` or `2m30s` (units: d, h, m, s, ms, us, ns)"
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Duration(StdDuration::from_secs(150)).to_string(), "2m30s");
+        assert_eq!(
+            Duration(StdDuration::from_millis(1500)).to_string(),
+            "1s500ms"
+        );
+        assert_eq!(Duration(StdDuration::ZERO).to_string(), "0s");
+    }
+
+    #[test]
+    fn test_serde() {
+        #[derive(Deserialize, Serialize)]
+        struct Conf {
+            text: Duration,
+            secs: Duration,
+        }
+
+        let conf: Conf = toml::from_str("text = '1m1ms'\nsecs = 10").unwrap();
+        assert_eq!(conf.text.as_std(), StdDuration::from_millis(60_001));
+        assert_eq!(conf.secs.as_std(), StdDuration::from_secs(10));
+        assert_eq!(
+            toml::to_string(&conf).unwrap(),
+            "text = \"1m1ms\"\nsecs = \"10s\"\n"
+        );
+
+        assert!(toml::from_str::<Conf>("text = 'soon'\nsecs = 10")
+            .err()
+            .unwrap()
+            .to_string()
+            .contains("invalid duration `soon`"));
+    }
+}
@@ -0,0 +1,94 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+
+/// Error returned by the fallible (`try_*`) ways of building and starting a `ClockworkApp`
+#[derive(Debug)]
+pub enum ClockworkError {
+    /// The config file at `path` could not be read
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// The config could not be read from a stream, e.g. standard input (see
+    /// `ClockworkApp::try_from_reader`)
+    ReadInput { source: std::io::Error },
+    /// The stream ended before anything but whitespace was read (see
+    /// `ClockworkApp::try_from_reader`)
+    EmptyInput,
+    /// The config could not be parsed, `path` is the file it was read from (if any)
+    Parse {
+        path: Option<PathBuf>,
+        source: toml::de::Error,
+    },
+    /// The readiness checks in `pending` did not pass in time (see `WaitForConfig`)
+    NotReady { pending: Vec<String> },
+    /// None of the config sources could be read, `tried` says why for each of them (see
+    /// `ClockworkApp::from_sources`)
+    NoConfig { tried: Vec<String> },
+    /// The config could not be fetched from `url` (`http-config` feature only)
+    #[cfg(feature = "http-config")]
+    Fetch { url: String, source: reqwest::Error },
+    /// The server answered the request for the config at `url` with a non-success `status`
+    /// (`http-config` feature only)
+    #[cfg(feature = "http-config")]
+    Status { url: String, status: u16 },
+}
+
+impl Display for ClockworkError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClockworkError::Read { path, source } => {
+                write!(f, "failed to read {}: {}", path.display(), source)
+            }
+            ClockworkError::ReadInput { source } => {
+                write!(f, "failed to read config input: {}", source)
+            }
+            ClockworkError::EmptyInput => {
+                write!(f, "config is empty, expected a TOML config before EOF")
+            }
+            ClockworkError::Parse {
+                path: Some(path),
+                source,
+            } => write!(f, "failed to parse {}: {}", path.display(), source),
+            ClockworkError::Parse { path: None, source } => {
+                write!(f, "failed to parse config: {}", source)
+            }
+            ClockworkError::NotReady { pending } => {
+                write!(f, "timed out waiting for: {}", pending.join(", "))
+            }
+            ClockworkError::NoConfig { tried } => {
+                write!(f, "no config found, tried: {}", tried.join(", "))
+            }
+            #[cfg(feature = "http-config")]
+            ClockworkError::Fetch { url, source } if source.is_timeout() => {
+                write!(f, "timed out fetching {}", url)
+            }
+            #[cfg(feature = "http-config")]
+            ClockworkError::Fetch { url, source } => {
+                write!(f, "failed to fetch {}: {}", url, source)
+            }
+            #[cfg(feature = "http-config")]
+            ClockworkError::Status { url, status } => {
+                write!(f, "failed to fetch {}: server answered {}", url, status)
+            }
+        }
+    }
+}
+
+impl Error for ClockworkError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ClockworkError::Read { source, .. } => Some(source),
+            ClockworkError::ReadInput { source } => Some(source),
+            ClockworkError::EmptyInput => None,
+            ClockworkError::Parse { source, .. } => Some(source),
+            ClockworkError::NotReady { .. } => None,
+            ClockworkError::NoConfig { .. } => None,
+            #[cfg(feature = "http-config")]
+            ClockworkError::Fetch { source, .. } => Some(source),
+            #[cfg(feature = "http-config")]
+            ClockworkError::Status { .. } => None,
+        }
+    }
+}
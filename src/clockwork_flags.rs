@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// Named on/off feature flags, toggleable while the app runs, as held by a `ClockworkHandle` (see
+/// `ClockworkHandle::flags`). A flag that was never set is off.
+/// Clones share the flags, a flag set through any of them is seen by all.
+#[derive(Clone, Default, Debug)]
+pub struct FlagStore {
+    flags: Arc<RwLock<HashMap<String, bool>>>,
+}
+
+impl FlagStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the flag `name` is on
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.flags
+            .read()
+            .unwrap()
+            .get(name)
+            .copied()
+            .unwrap_or(false)
+    }
+
+    /// Turns the flag `name` on or off
+    pub fn set(&self, name: &str, value: bool) {
+        self.flags.write().unwrap().insert(name.to_string(), value);
+    }
+
+    /// Every flag that has been set, with its value
+    pub fn all(&self) -> HashMap<String, bool> {
+        self.flags.read().unwrap().clone()
+    }
+}
+
+impl From<HashMap<String, bool>> for FlagStore {
+    fn from(flags: HashMap<String, bool>) -> Self {
+        Self {
+            flags: Arc::new(RwLock::new(flags)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clockwork_flags::FlagStore;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_flag_store() {
+        let flags = FlagStore::from(HashMap::from([("reports".to_string(), true)]));
+        let shared = flags.clone();
+        assert!(flags.is_enabled("reports"));
+        assert!(!flags.is_enabled("unknown"));
+
+        shared.set("reports", false);
+        shared.set("ingest", true);
+        assert!(!flags.is_enabled("reports"));
+        assert!(flags.is_enabled("ingest"));
+        assert_eq!(flags.all().len(), 2);
+    }
+}
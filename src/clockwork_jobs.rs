@@ -0,0 +1,150 @@
+use crate::{ClockworkHandle, Configurable, Duration, Runnable};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// A periodic job declared in config
+/// name - identifies the job, used by the dispatcher to decide what to run
+/// period - how often the job runs, e.g. `'5m'` or a number of seconds (may also be written
+///          `period_secs`)
+/// command - free-form payload handed to the dispatcher (optional)
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct JobConfig {
+    pub name: String,
+    #[serde(alias = "period_secs")]
+    pub period: Duration,
+    #[serde(default)]
+    pub command: String,
+}
+
+/// App configuration for a `ScheduledJobsApp`, declared as `[[app.jobs]]` tables
+#[derive(Deserialize, Serialize, Clone)]
+pub struct ScheduledJobsConfig {
+    #[serde(default)]
+    pub jobs: Vec<JobConfig>,
+}
+
+/// Decides what to do when a configured job fires
+pub trait JobDispatch: Default + Send + Sync + 'static {
+    fn dispatch(&self, job: &JobConfig);
+}
+
+/// Schedules each job as a repeating task, calling `dispatch` with the job on every tick
+pub fn schedule_jobs<D>(handle: &ClockworkHandle, jobs: &[JobConfig], dispatch: D)
+where
+    D: Fn(&JobConfig) + Send + Sync + 'static,
+{
+    let dispatch = Arc::new(dispatch);
+    for job in jobs {
+        let job_conf = job.clone();
+        let dispatch = Arc::clone(&dispatch);
+        handle.schedule_repeating_task(move || dispatch(&job_conf), job.period.into());
+    }
+}
+
+/// An app whose repeating jobs are declared purely in config, turning Clockwork into a
+/// config-driven cron replacement.
+/// ```
+/// use clockwork::{ClockworkApp, JobConfig, JobDispatch, ScheduledJobsApp};
+///
+/// #[derive(Default)]
+/// struct Dispatcher;
+///
+/// impl JobDispatch for Dispatcher {
+///     fn dispatch(&self, job: &JobConfig) {
+///         match job.name.as_str() {
+///             "cleanup" => println!("cleaning up {}", job.command),
+///             _ => println!("unknown job {}", job.name),
+///         }
+///     }
+/// }
+///
+/// let conf_str = r#"
+///     [[app.jobs]]
+///     name = 'cleanup'
+///     period = '1m'
+///     command = '/tmp'
+/// "#;
+///
+/// let app: ClockworkApp<ScheduledJobsApp<Dispatcher>> =
+///     ClockworkApp::from_config_str(conf_str.to_string());
+/// ```
+pub struct ScheduledJobsApp<D: JobDispatch> {
+    jobs: Vec<JobConfig>,
+    dispatcher: Arc<D>,
+}
+
+impl<D: JobDispatch> ScheduledJobsApp<D> {
+    /// The jobs this app schedules
+    pub fn jobs(&self) -> &[JobConfig] {
+        &self.jobs
+    }
+}
+
+impl<D: JobDispatch> Configurable for ScheduledJobsApp<D> {
+    type Config = ScheduledJobsConfig;
+
+    fn from(config: Self::Config) -> Self {
+        Self {
+            jobs: config.jobs,
+            dispatcher: Arc::new(D::default()),
+        }
+    }
+}
+
+impl<D: JobDispatch> Runnable for ScheduledJobsApp<D> {
+    fn setup(&self, handle: ClockworkHandle) {
+        let dispatcher = Arc::clone(&self.dispatcher);
+        schedule_jobs(&handle, &self.jobs, move |job| dispatcher.dispatch(job));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clockwork_jobs::{JobConfig, JobDispatch, ScheduledJobsApp};
+    use crate::ClockworkApp;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    static DISPATCHED: AtomicU32 = AtomicU32::new(0);
+
+    #[derive(Default)]
+    struct CountingDispatcher;
+
+    impl JobDispatch for CountingDispatcher {
+        fn dispatch(&self, job: &JobConfig) {
+            assert!(job.name == "first" || job.name == "second");
+            DISPATCHED.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_scheduled_jobs_app() {
+        let conf_str = r#"
+            [[app.jobs]]
+            name = 'first'
+            period = '1m'
+
+            [[app.jobs]]
+            name = 'second'
+            period_secs = 60
+            command = 'echo'
+        "#
+        .to_string();
+
+        let app: ClockworkApp<ScheduledJobsApp<CountingDispatcher>> =
+            ClockworkApp::from_config_str(conf_str);
+        assert_eq!(app.app().jobs().len(), 2);
+        assert_eq!(app.app().jobs()[1].command, "echo");
+
+        let handle = app.handle();
+        std::thread::spawn(move || {
+            sleep(Duration::from_millis(50));
+            handle.stop();
+        });
+        app.start().unwrap();
+
+        // Each job fires once immediately, then not again within its period
+        assert_eq!(DISPATCHED.load(Ordering::SeqCst), 2);
+    }
+}
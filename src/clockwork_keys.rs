@@ -0,0 +1,130 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use toml::Value;
+
+/// A step into a TOML value: a key of a table or an index into an array
+#[derive(Clone, Copy)]
+enum Step<'a> {
+    Key(&'a str),
+    Index(usize),
+}
+
+/// Keys of `raw` that `S` does not know about, as dotted paths under `prefix`.
+/// A key is unknown if it does not come back when `raw` is read as `S` and serialized again, and
+/// leaving it out reads the same (which tells aliases, e.g. `run_for_secs`, from typos).
+/// Nothing is reported if `raw` cannot be read as `S` in the first place.
+pub(crate) fn unknown_keys<S>(raw: &Value, prefix: &str) -> Vec<String>
+where
+    S: DeserializeOwned + Serialize,
+{
+    let read = |raw: &Value| {
+        raw.clone()
+            .try_into::<S>()
+            .ok()
+            .and_then(|conf| Value::try_from(conf).ok())
+    };
+    let parsed = match read(raw) {
+        Some(parsed) => parsed,
+        None => return Vec::new(),
+    };
+
+    let mut candidates = Vec::new();
+    collect(raw, &parsed, &mut Vec::new(), &mut candidates);
+    candidates
+        .into_iter()
+        .filter(|path| {
+            let mut without = raw.clone();
+            remove(&mut without, path);
+            read(&without).as_ref() == Some(&parsed)
+        })
+        .map(|path| {
+            path.iter()
+                .fold(prefix.to_string(), |name, step| match step {
+                    Step::Key(key) => format!("{}.{}", name, key),
+                    Step::Index(index) => format!("{}[{}]", name, index),
+                })
+        })
+        .collect()
+}
+
+/// Pushes the path of every key in `raw` that is missing from `parsed` onto `candidates`
+fn collect<'a>(
+    raw: &'a Value,
+    parsed: &Value,
+    path: &mut Vec<Step<'a>>,
+    candidates: &mut Vec<Vec<Step<'a>>>,
+) {
+    match (raw, parsed) {
+        (Value::Table(raw), Value::Table(parsed)) => {
+            for (key, value) in raw {
+                path.push(Step::Key(key));
+                match parsed.get(key) {
+                    Some(parsed) => collect(value, parsed, path, candidates),
+                    None => candidates.push(path.clone()),
+                }
+                path.pop();
+            }
+        }
+        (Value::Array(raw), Value::Array(parsed)) => {
+            for (index, (value, parsed)) in raw.iter().zip(parsed).enumerate() {
+                path.push(Step::Index(index));
+                collect(value, parsed, path, candidates);
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+fn remove(value: &mut Value, path: &[Step]) {
+    let (last, parents) = match path.split_last() {
+        Some(split) => split,
+        None => return,
+    };
+    let parent = parents.iter().try_fold(value, |value, step| match step {
+        Step::Key(key) => value.get_mut(*key),
+        Step::Index(index) => value.get_mut(*index),
+    });
+    if let (Some(Value::Table(table)), Step::Key(key)) = (parent, last) {
+        table.remove(*key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clockwork_keys::unknown_keys;
+    use crate::ClockworkConfig;
+
+    #[test]
+    fn test_unknown_keys() {
+        let raw: toml::Value = toml::from_str(
+            r#"
+            run_for_secs = 10
+            idle_timout = '5m'
+
+            [runtime]
+            max_thread = 256
+            enable_io = false
+
+            [wait_for]
+            timeout = '5s'
+
+            [[wait_for.checks]]
+            check = 'FILE'
+            path = '/tmp/ready'
+            retries = 3
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            unknown_keys::<ClockworkConfig>(&raw, "clockwork"),
+            vec![
+                "clockwork.idle_timout",
+                "clockwork.runtime.max_thread",
+                "clockwork.wait_for.checks[0].retries",
+            ]
+        );
+        assert!(unknown_keys::<ClockworkConfig>(&toml::from_str("").unwrap(), "").is_empty());
+    }
+}
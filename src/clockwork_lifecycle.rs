@@ -0,0 +1,286 @@
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// Lifecycle of a `Clockwork` runtime, it only ever moves forward
+/// Running - tasks are scheduled and run as normal
+/// Draining - no new tasks are scheduled and repeating tasks exit, tasks already in flight (and
+///            anything they spawn) are allowed to complete
+/// Stopped - the runtime is stopped, nothing new is spawned
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Lifecycle {
+    Running,
+    Draining,
+    Stopped,
+}
+
+/// Why a `Clockwork` runtime stopped, see `ClockworkHandle::stop_reason`
+/// Requested - `stop` was called, by the app itself or from another thread
+/// RunFor - the configured `run_for` duration (or a `StopAfter`'s) elapsed
+/// RunCount - tasks ran as many times as a `StopAfterIterations` allows
+/// IdleTimeout - no scheduled task fired within the configured `idle_timeout`
+/// Done - no task was left to run (see `stop_when_done`)
+/// Drained - the runtime was drained by `shutdown_graceful` or `drain_and_stop`
+/// Returned - `Runnable::run` returned without the runtime having been stopped
+/// Panicked - `Runnable::setup` (or `try_setup`) or `run` panicked
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    Requested,
+    RunFor,
+    RunCount,
+    IdleTimeout,
+    Done,
+    Drained,
+    Returned,
+    Panicked,
+}
+
+impl Display for StopReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            StopReason::Requested => "stop requested",
+            StopReason::RunFor => "run_for elapsed",
+            StopReason::RunCount => "task run count reached",
+            StopReason::IdleTimeout => "idle timeout",
+            StopReason::Done => "no task left to run",
+            StopReason::Drained => "drained",
+            StopReason::Returned => "run returned",
+            StopReason::Panicked => "panicked",
+        })
+    }
+}
+
+/// Summary of how an app ended, as returned by `ClockworkApp::start_with_report`
+/// reason - why the runtime stopped
+/// uptime - how long the runtime was up for
+/// task_runs - number of times a scheduled task ran (a consumer task handling a message counts
+///             too), whatever the outcome
+/// task_timeouts - number of runs cancelled for not completing within their timeout (see
+///                 `ClockworkHandle::schedule_repeating_task_with_timeout`)
+/// drained - whether every task in flight completed while draining, `None` if it was not drained
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShutdownReport {
+    pub reason: StopReason,
+    pub uptime: Duration,
+    pub task_runs: u64,
+    pub task_timeouts: u64,
+    pub drained: Option<bool>,
+}
+
+/// A single summary line, e.g. `stopped (run_for elapsed) after 1h5m, 42 task runs`
+impl Display for ShutdownReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let uptime = Duration::from_millis(self.uptime.as_millis() as u64);
+        write!(
+            f,
+            "stopped ({}) after {}, {} task runs",
+            self.reason,
+            crate::Duration::from(uptime),
+            self.task_runs
+        )?;
+        if self.task_timeouts > 0 {
+            write!(f, " ({} timed out)", self.task_timeouts)?;
+        }
+        match self.drained {
+            Some(true) => write!(f, ", drained cleanly"),
+            Some(false) => write!(f, ", tasks still in flight when drain timed out"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl From<u8> for Lifecycle {
+    fn from(state: u8) -> Self {
+        match state {
+            0 => Lifecycle::Running,
+            1 => Lifecycle::Draining,
+            _ => Lifecycle::Stopped,
+        }
+    }
+}
+
+/// Lifecycle state shared between clones of a handle, waiters are notified on every transition
+#[derive(Default)]
+pub(crate) struct LifecycleState {
+    state: AtomicU8,
+    changed: Notify,
+    /// Why it stopped and whether it drained, set by whichever call made the transition to
+    /// `Stopped`
+    stopped_by: Mutex<Option<(StopReason, Option<bool>)>>,
+    /// Called once, on the transition to `Stopped`
+    on_stop: Mutex<Vec<StopCallback>>,
+    /// Child scopes (see `ClockworkHandle::child`), moved forward along with this one
+    children: Mutex<Vec<Weak<LifecycleState>>>,
+}
+
+type StopCallback = Box<dyn FnOnce() + Send>;
+
+impl LifecycleState {
+    pub(crate) fn get(&self) -> Lifecycle {
+        Lifecycle::from(self.state.load(Ordering::Acquire))
+    }
+
+    /// Creates a child scope, which moves forward along with this one but can also be moved
+    /// forward (e.g. stopped) on its own
+    pub(crate) fn child(&self) -> Arc<LifecycleState> {
+        let child = Arc::new(LifecycleState::default());
+        {
+            let mut children = self.children.lock().unwrap();
+            children.retain(|child| child.strong_count() > 0);
+            children.push(Arc::downgrade(&child));
+        }
+
+        // Caught up after registering, so that a transition made in between is not missed
+        match self.stopped_by() {
+            Some((reason, drained)) => {
+                child.stop(reason, drained);
+            }
+            None if self.get() == Lifecycle::Draining => {
+                child.advance(Lifecycle::Draining);
+            }
+            None => {}
+        }
+        child
+    }
+
+    fn children(&self) -> Vec<Arc<LifecycleState>> {
+        let children = self.children.lock().unwrap();
+        children.iter().filter_map(Weak::upgrade).collect()
+    }
+
+    /// Moves the lifecycle (and its children) forward to `to`, returns true if this call made the
+    /// transition
+    pub(crate) fn advance(&self, to: Lifecycle) -> bool {
+        let prev = self.state.fetch_max(to as u8, Ordering::AcqRel);
+        if prev >= to as u8 {
+            return false;
+        }
+
+        self.changed.notify_waiters();
+        for child in self.children() {
+            child.advance(to);
+        }
+        true
+    }
+
+    /// Moves the lifecycle to `Stopped`, returns true if this call made the transition, in which
+    /// case `reason` and `drained` (whether the tasks in flight completed while draining) are
+    /// recorded. Its children are stopped for the same reason, then the `register_on_stop`
+    /// callbacks are called.
+    pub(crate) fn stop(&self, reason: StopReason, drained: Option<bool>) -> bool {
+        {
+            // Held across the transition, so that the reason is there as soon as `Stopped` is seen
+            let mut stopped_by = self.stopped_by.lock().unwrap();
+            let prev = self
+                .state
+                .fetch_max(Lifecycle::Stopped as u8, Ordering::AcqRel);
+            if prev == Lifecycle::Stopped as u8 {
+                return false;
+            }
+
+            *stopped_by = Some((reason, drained));
+            self.changed.notify_waiters();
+        }
+
+        for child in self.children() {
+            child.stop(reason, drained);
+        }
+
+        // Taken out before running, so that a callback may itself use the handle
+        let callbacks = std::mem::take(&mut *self.on_stop.lock().unwrap());
+        for f in callbacks {
+            f();
+        }
+        true
+    }
+
+    /// Registers `f` to be called by `stop`, calls it straight away if already stopped
+    pub(crate) fn register_on_stop(&self, f: StopCallback) {
+        let mut on_stop = self.on_stop.lock().unwrap();
+        // Checked under the lock, `stop` takes the callbacks out only after raising the flag
+        if self.get() != Lifecycle::Stopped {
+            on_stop.push(f);
+            return;
+        }
+
+        drop(on_stop);
+        f();
+    }
+
+    /// Why the lifecycle moved to `Stopped` and whether it drained, `None` until it has
+    pub(crate) fn stopped_by(&self) -> Option<(StopReason, Option<bool>)> {
+        *self.stopped_by.lock().unwrap()
+    }
+
+    /// Resolves once the lifecycle satisfies `pred`
+    pub(crate) async fn wait_until<P: Fn(Lifecycle) -> bool>(&self, pred: P) {
+        loop {
+            // Register for the notification before checking, otherwise a transition made in
+            // between would be missed
+            let notified = self.changed.notified();
+            if pred(self.get()) {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// Counts the spawned tasks that are still in flight.
+/// A child tracker's tasks count towards its parent's too.
+#[derive(Default)]
+pub(crate) struct TaskTracker {
+    in_flight: AtomicUsize,
+    idle: Notify,
+    parent: Option<Arc<TaskTracker>>,
+}
+
+/// Marks a task as in flight until dropped
+pub(crate) struct TaskGuard(Arc<TaskTracker>);
+
+impl TaskTracker {
+    pub(crate) fn child(self: &Arc<Self>) -> Arc<Self> {
+        Arc::new(Self {
+            parent: Some(Arc::clone(self)),
+            ..Default::default()
+        })
+    }
+
+    pub(crate) fn track(self: &Arc<Self>) -> TaskGuard {
+        let mut tracker = Some(self);
+        while let Some(current) = tracker {
+            current.in_flight.fetch_add(1, Ordering::AcqRel);
+            tracker = current.parent.as_ref();
+        }
+        TaskGuard(Arc::clone(self))
+    }
+
+    pub(crate) fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Acquire)
+    }
+
+    /// Resolves once no tracked task is in flight
+    pub(crate) async fn wait_idle(&self) {
+        loop {
+            let notified = self.idle.notified();
+            if self.in_flight() == 0 {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        let mut tracker = Some(&self.0);
+        while let Some(current) = tracker {
+            if current.in_flight.fetch_sub(1, Ordering::AcqRel) == 1 {
+                current.idle.notify_waiters();
+            }
+            tracker = current.parent.as_ref();
+        }
+    }
+}
@@ -1,8 +1,25 @@
-use serde::Deserialize;
+#[cfg(feature = "otel")]
+use crate::clockwork_otel::{TraceExportConfig, TraceExporter};
+use crate::Duration;
+#[cfg(feature = "otel")]
+use opentelemetry::sdk::trace::Tracer;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
 use std::path::PathBuf;
-use tracing::Dispatch;
-use tracing_appender::non_blocking::{NonBlocking, WorkerGuard};
-use tracing_subscriber::filter::LevelFilter;
+use std::sync::Mutex;
+use std::time::Instant;
+use tracing::callsite::Identifier;
+use tracing::dispatcher::SetGlobalDefaultError;
+use tracing::{Dispatch, Event, Level, Metadata, Subscriber};
+use tracing_appender::non_blocking::{NonBlocking, NonBlockingBuilder, WorkerGuard};
+use tracing_subscriber::filter::{FilterExt, LevelFilter};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::layer::{Context, Filter, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::reload;
+use tracing_subscriber::{Layer, Registry};
 
 const fn default_as_true() -> bool {
     true
@@ -12,47 +29,58 @@ const fn default_as_false() -> bool {
     false
 }
 
+/// Same as `tracing_appender`'s own default
+const fn default_buffered_lines_limit() -> usize {
+    128_000
+}
+
+/// Log file used when the executable's name cannot be found
+const FALLBACK_LOG_FILE: &str = "clockwork.log";
+
 /// Default log file if filename not specified
 /// For example, if your executable is called `stuff`, it will default to `stuff.log`
+/// Falls back to `clockwork.log` if the executable path is unavailable (e.g. in some sandboxes)
 fn default_as_exe() -> String {
-    let executable_path = std::env::current_exe().expect("Unable to find executable path");
-    let stem = executable_path.file_stem().unwrap();
-    let log_file = format!("{}.log", stem.to_string_lossy());
-    log_file
+    std::env::current_exe()
+        .ok()
+        .and_then(|path| {
+            path.file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+        })
+        .map(|stem| format!("{}.log", stem))
+        .unwrap_or_else(|| FALLBACK_LOG_FILE.to_string())
 }
 
 /// How log lines should be formatted.
 /// Defaults to FULL (the equivalent to `tracing_subscriber::fmt::format::Full`)
-/// Please see: https://docs.rs/tracing-subscriber/0.2.15/tracing_subscriber/fmt/index.html
-#[derive(Deserialize)]
+/// Please see: https://docs.rs/tracing-subscriber/0.3/tracing_subscriber/fmt/index.html
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Deserialize, Serialize, Clone, Default)]
 pub enum LoggerFormat {
     COMPACT,
     PRETTY,
     JSON,
+    #[default]
     FULL,
 }
 
-impl Default for LoggerFormat {
-    fn default() -> Self {
-        LoggerFormat::FULL
-    }
-}
-
 /// Maximum log level that should be logged
 /// Maps exactly to the `tracing_subscriber::filter::LevelFilter` enum
-#[derive(Deserialize, Copy, Clone)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Deserialize, Serialize, Copy, Clone, Default)]
 pub enum LoggerLevel {
     OFF,
     ERROR,
     WARN,
     INFO,
     DEBUG,
+    #[default]
     TRACE,
 }
 
-impl Into<LevelFilter> for LoggerLevel {
-    fn into(self) -> LevelFilter {
-        match self {
+impl From<LoggerLevel> for LevelFilter {
+    fn from(level: LoggerLevel) -> Self {
+        match level {
             LoggerLevel::OFF => LevelFilter::OFF,
             LoggerLevel::TRACE => LevelFilter::TRACE,
             LoggerLevel::DEBUG => LevelFilter::DEBUG,
@@ -63,18 +91,14 @@ impl Into<LevelFilter> for LoggerLevel {
     }
 }
 
-impl Default for LoggerLevel {
-    fn default() -> Self {
-        LoggerLevel::TRACE
-    }
-}
-
 /// Target to write log lines to
 /// Default is to write to stdout
 /// Otherwise, if target is specified to write to a file, the file name defaults to `default_as_exe`
-#[derive(Deserialize)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Deserialize, Serialize, Clone, Default)]
 #[serde(tag = "write_target")]
 pub enum WriteTarget {
+    #[default]
     STDOUT,
     FILE {
         #[serde(default = "default_as_exe")]
@@ -82,14 +106,14 @@ pub enum WriteTarget {
     },
 }
 
-impl Default for WriteTarget {
-    fn default() -> Self {
-        WriteTarget::STDOUT
-    }
-}
-
 impl WriteTarget {
-    pub(crate) fn writer(self) -> (NonBlocking, WorkerGuard) {
+    /// A non blocking writer into the target, buffering up to `buffered_lines_limit` lines.
+    /// Once the buffer is full, new lines are dropped if `lossy`, otherwise logging blocks.
+    pub(crate) fn writer(
+        self,
+        buffered_lines_limit: usize,
+        lossy: bool,
+    ) -> (NonBlocking, WorkerGuard) {
         let writer: Box<dyn std::io::Write + Send + Sync> = match self {
             WriteTarget::STDOUT => Box::new(std::io::stdout()),
             WriteTarget::FILE { file_name } => {
@@ -101,31 +125,81 @@ impl WriteTarget {
             }
         };
 
-        tracing_appender::non_blocking(writer)
+        NonBlockingBuilder::default()
+            .buffered_lines_limit(buffered_lines_limit)
+            .lossy(lossy)
+            .finish(writer)
     }
 }
 
+/// An additional target to write log lines to, configured like the logger's own `write_target`
+/// log_level - maximum log verbosity level for this target (defaults to the logger's `log_level`)
+#[derive(Deserialize, Serialize, Clone)]
+pub struct LogTarget {
+    #[serde(default)]
+    pub(crate) log_level: Option<LoggerLevel>,
+    #[serde(flatten)]
+    pub(crate) write_target: WriteTarget,
+}
+
 /// Logger configurations
+/// install_subscriber - installs Clockwork's subscriber as the global default, set to false to have
+///                      events flow into a subscriber installed by the host application instead
 /// show_time - enables/disables timestamping in log output
 /// show_thread_names - enables/disables thread names in log output
 /// show_thread_ids - enables/disables thread id in log output
+/// show_file - enables/disables the source file of the event in log output
+/// show_line - enables/disables the source line number of the event in log output
 /// log_format - log formatting (COMPACT/PRETTY/JSON/FULL)
 /// log_level - maximum log verbosity level (OFF/ERROR/WARN/INFO/DEBUG/TRACE)
 /// write_target - target to write log into (see `WriteTarget` struct)
-#[derive(Deserialize)]
+/// buffered_lines_limit - number of lines each target buffers before they are written out
+///                        (defaults to 128000)
+/// lossy - when a target's buffer is full, drops new lines if true (the default), otherwise blocks
+///         the logging thread until there is room
+/// sample_rate - logs only 1 in N of the events from each place in the code, e.g. for a task that
+///               logs on every run (every event is logged if omitted)
+/// sample_interval - logs at most one event per interval from each place in the code, e.g. `'1s'`
+///                   (not limited if omitted). WARN and ERROR events are never sampled
+/// targets - additional targets to write log into, each with its own level (see `LogTarget`)
+/// fields - static key/value pairs added to every log line, e.g. the service name or environment
+///          (as top-level keys with JSON, as trailing `key=value` pairs otherwise)
+/// trace_export - exports spans to an OpenTelemetry collector (`otel` feature only)
+#[derive(Deserialize, Serialize, Clone)]
 pub struct LoggerConfig {
+    #[serde(default = "default_as_true")]
+    pub(crate) install_subscriber: bool,
     #[serde(default = "default_as_true")]
     pub(crate) show_time: bool,
     #[serde(default = "default_as_false")]
     pub(crate) show_thread_names: bool,
     #[serde(default = "default_as_true")]
     pub(crate) show_thread_ids: bool,
+    #[serde(default = "default_as_false")]
+    pub(crate) show_file: bool,
+    #[serde(default = "default_as_false")]
+    pub(crate) show_line: bool,
     #[serde(default)]
     pub(crate) log_format: LoggerFormat,
     #[serde(default)]
     pub(crate) log_level: LoggerLevel,
     #[serde(default, flatten)]
     pub(crate) write_target: WriteTarget,
+    #[serde(default = "default_buffered_lines_limit")]
+    pub(crate) buffered_lines_limit: usize,
+    #[serde(default = "default_as_true")]
+    pub(crate) lossy: bool,
+    #[serde(default)]
+    pub(crate) sample_rate: Option<u64>,
+    #[serde(default)]
+    pub(crate) sample_interval: Option<Duration>,
+    #[serde(default)]
+    pub(crate) targets: Vec<LogTarget>,
+    #[serde(default)]
+    pub(crate) fields: BTreeMap<String, String>,
+    #[cfg(feature = "otel")]
+    #[serde(default)]
+    pub(crate) trace_export: Option<TraceExportConfig>,
 }
 
 /// Even if all the fields have default value, it won't be automatically deserialized
@@ -134,72 +208,585 @@ pub struct LoggerConfig {
 impl Default for LoggerConfig {
     fn default() -> Self {
         LoggerConfig {
+            install_subscriber: default_as_true(),
             show_time: default_as_true(),
             show_thread_names: default_as_false(),
             show_thread_ids: default_as_true(),
+            show_file: default_as_false(),
+            show_line: default_as_false(),
             log_format: Default::default(),
             log_level: Default::default(),
             write_target: Default::default(),
+            buffered_lines_limit: default_buffered_lines_limit(),
+            lossy: default_as_true(),
+            sample_rate: None,
+            sample_interval: None,
+            targets: Vec::new(),
+            fields: BTreeMap::new(),
+            #[cfg(feature = "otel")]
+            trace_export: None,
         }
     }
 }
 
-pub(crate) struct ClockworkLogger {
-    dispatch: Dispatch,
-    _writer: NonBlocking,
-    _guard: WorkerGuard,
+/// Layers stacked on top of the fmt subscriber
+#[derive(Default)]
+struct ExtraLayers {
+    #[cfg(feature = "otel")]
+    tracer: Option<Tracer>,
 }
 
-impl From<LoggerConfig> for ClockworkLogger {
-    fn from(conf: LoggerConfig) -> Self {
-        let (writer, guard) = conf.write_target.writer();
+impl ExtraLayers {
+    #[cfg(feature = "otel")]
+    fn dispatch<S>(self, subscriber: S) -> Dispatch
+    where
+        S: Subscriber + for<'a> LookupSpan<'a> + Send + Sync,
+    {
+        let otel = self
+            .tracer
+            .map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer));
+        Dispatch::new(subscriber.with(otel))
+    }
 
-        let builder = tracing_subscriber::fmt()
-            .with_thread_names(conf.show_thread_names)
-            .with_thread_ids(conf.show_thread_ids)
-            .with_max_level(conf.log_level)
-            .with_writer(writer.clone());
+    #[cfg(not(feature = "otel"))]
+    fn dispatch<S>(self, subscriber: S) -> Dispatch
+    where
+        S: Subscriber + Send + Sync,
+    {
+        Dispatch::new(subscriber)
+    }
+}
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// Wraps an event format, adding the logger's static `fields` to every line it formats
+struct WithFields<E> {
+    inner: E,
+    /// The fields, rendered in the form they are added to a line
+    rendered: String,
+    json: bool,
+}
+
+impl<E> WithFields<E> {
+    fn new(inner: E, conf: &LoggerConfig) -> Self {
+        let json = matches!(conf.log_format, LoggerFormat::JSON);
+        let mut rendered = String::new();
+        for (key, value) in &conf.fields {
+            let _ = match json {
+                true => write!(
+                    rendered,
+                    ",{}:{}",
+                    serde_json::Value::from(key.as_str()),
+                    serde_json::Value::from(value.as_str())
+                ),
+                false => write!(rendered, " {}={}", key, value),
+            };
+        }
+        Self {
+            inner,
+            rendered,
+            json,
+        }
+    }
+}
+
+impl<S, N, E> FormatEvent<S, N> for WithFields<E>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+    E: FormatEvent<S, N>,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> std::fmt::Result {
+        if self.rendered.is_empty() {
+            return self.inner.format_event(ctx, writer, event);
+        }
+
+        // Lines with fields are formatted without ANSI colours, as they go through a plain buffer
+        let mut line = String::new();
+        self.inner
+            .format_event(ctx, Writer::new(&mut line), event)?;
+        // Into the JSON object, or at the end of the event's own (first) line
+        let at = match self.json {
+            true => line.rfind('}'),
+            false => line.find('\n'),
+        };
+        line.insert_str(at.unwrap_or(line.len()), &self.rendered);
+        writer.write_str(&line)
+    }
+}
+
+type LevelReload = reload::Handle<LevelFilter, Registry>;
+
+/// Changes the level of the targets that follow the logger's `log_level` at runtime, see
+/// `ClockworkHandle::set_log_level`. Targets with a `log_level` of their own keep it.
+#[derive(Clone, Default)]
+pub(crate) struct LogLevelControl {
+    reloads: Vec<LevelReload>,
+}
+
+impl LogLevelControl {
+    /// `false` if there is no target to change, e.g. for a logger installed with `with_dispatch`
+    pub(crate) fn set(&self, level: LoggerLevel) -> bool {
+        !self.reloads.is_empty()
+            && self
+                .reloads
+                .iter()
+                .all(|reload| reload.reload(LevelFilter::from(level)).is_ok())
+    }
+}
+
+/// Events seen from one callsite, and when one was last let through
+#[derive(Default)]
+struct CallsiteSamples {
+    seen: u64,
+    last: Option<Instant>,
+}
+
+/// Lets through 1 in `rate` events from each callsite, at most one per `interval`, see the
+/// logger's `sample_rate` and `sample_interval`. WARN and ERROR events always go through.
+struct Sampler {
+    rate: Option<u64>,
+    interval: Option<std::time::Duration>,
+    callsites: Mutex<HashMap<Identifier, CallsiteSamples>>,
+}
+
+impl Sampler {
+    fn new(conf: &LoggerConfig) -> Self {
+        Self {
+            rate: conf.sample_rate,
+            interval: conf.sample_interval.as_ref().map(Duration::as_std),
+            callsites: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<S> Filter<S> for Sampler {
+    fn enabled(&self, _meta: &Metadata<'_>, _cx: &Context<'_, S>) -> bool {
+        true
+    }
+
+    /// Only called for the events the level filter let through, so only those count
+    fn event_enabled(&self, event: &Event<'_>, _cx: &Context<'_, S>) -> bool {
+        let meta = event.metadata();
+        if (self.rate.is_none() && self.interval.is_none()) || *meta.level() <= Level::WARN {
+            return true;
+        }
+
+        let now = Instant::now();
+        let mut callsites = self.callsites.lock().unwrap();
+        let samples = callsites.entry(meta.callsite()).or_default();
+        samples.seen += 1;
+        let by_rate = self
+            .rate
+            .is_none_or(|rate| (samples.seen - 1).is_multiple_of(rate.max(1)));
+        let by_interval = match (self.interval, samples.last) {
+            (Some(interval), Some(last)) => now.duration_since(last) >= interval,
+            _ => true,
+        };
+        if by_rate && by_interval {
+            samples.last = Some(now);
+        }
+        by_rate && by_interval
+    }
+}
+
+impl LoggerConfig {
+    /// A fmt layer writing into `writer`, filtered by `filter`
+    fn fmt_layer<F>(&self, writer: NonBlocking, filter: F) -> BoxedLayer
+    where
+        F: tracing_subscriber::layer::Filter<Registry> + Send + Sync + 'static,
+    {
+        let layer = tracing_subscriber::fmt::layer()
+            .with_thread_names(self.show_thread_names)
+            .with_thread_ids(self.show_thread_ids)
+            .with_file(self.show_file)
+            .with_line_number(self.show_line)
+            .with_writer(writer);
 
         // FIXME: Surely this can be made more compact!
-        let dispatch = match conf.log_format {
+        let layer: BoxedLayer = match self.log_format {
             LoggerFormat::COMPACT => {
-                let builder = builder.compact();
-                match conf.show_time {
-                    true => builder.into(),
-                    false => builder.without_time().into(),
+                let layer = layer.compact();
+                match self.show_time {
+                    true => layer
+                        .map_event_format(|format| WithFields::new(format, self))
+                        .boxed(),
+                    false => layer
+                        .without_time()
+                        .map_event_format(|format| WithFields::new(format, self))
+                        .boxed(),
                 }
             }
             LoggerFormat::PRETTY => {
-                let builder = builder.pretty();
-                match conf.show_time {
-                    true => builder.into(),
-                    false => builder.without_time().into(),
+                let layer = layer.pretty();
+                match self.show_time {
+                    true => layer
+                        .map_event_format(|format| WithFields::new(format, self))
+                        .boxed(),
+                    false => layer
+                        .without_time()
+                        .map_event_format(|format| WithFields::new(format, self))
+                        .boxed(),
                 }
             }
             LoggerFormat::JSON => {
-                let builder = builder.json();
-                match conf.show_time {
-                    true => builder.into(),
-                    false => builder.without_time().into(),
+                let layer = layer.json();
+                match self.show_time {
+                    true => layer
+                        .map_event_format(|format| WithFields::new(format, self))
+                        .boxed(),
+                    false => layer
+                        .without_time()
+                        .map_event_format(|format| WithFields::new(format, self))
+                        .boxed(),
                 }
             }
-            LoggerFormat::FULL => match conf.show_time {
-                true => builder.into(),
-                false => builder.without_time().into(),
+            LoggerFormat::FULL => match self.show_time {
+                true => layer
+                    .map_event_format(|format| WithFields::new(format, self))
+                    .boxed(),
+                false => layer
+                    .without_time()
+                    .map_event_format(|format| WithFields::new(format, self))
+                    .boxed(),
             },
         };
 
+        layer.with_filter(filter.and(Sampler::new(self))).boxed()
+    }
+}
+
+pub(crate) struct ClockworkLogger {
+    pub(crate) dispatch: Dispatch,
+    pub(crate) log_level: LogLevelControl,
+    _writers: Vec<NonBlocking>,
+    _guards: Vec<WorkerGuard>,
+    #[cfg(feature = "otel")]
+    _trace_exporter: Option<TraceExporter>,
+}
+
+impl From<LoggerConfig> for ClockworkLogger {
+    fn from(conf: LoggerConfig) -> Self {
+        let targets = std::iter::once((None, conf.write_target.clone())).chain(
+            conf.targets
+                .iter()
+                .map(|target| (target.log_level, target.write_target.clone())),
+        );
+
+        let mut writers = Vec::new();
+        let mut guards = Vec::new();
+        let mut fmt_layers = Vec::new();
+        let mut log_level = LogLevelControl::default();
+        for (level, write_target) in targets {
+            let (writer, guard) = write_target.writer(conf.buffered_lines_limit, conf.lossy);
+            fmt_layers.push(match level {
+                Some(level) => conf.fmt_layer(writer.clone(), LevelFilter::from(level)),
+                None => {
+                    let (filter, reload) = reload::Layer::new(LevelFilter::from(conf.log_level));
+                    log_level.reloads.push(reload);
+                    conf.fmt_layer(writer.clone(), filter)
+                }
+            });
+            writers.push(writer);
+            guards.push(guard);
+        }
+
+        #[cfg(feature = "otel")]
+        let trace_exporter = conf.trace_export.map(TraceExporter::from);
+        let layers = ExtraLayers {
+            #[cfg(feature = "otel")]
+            tracer: trace_exporter.as_ref().map(TraceExporter::tracer),
+        };
+
         Self {
-            dispatch,
-            _writer: writer,
-            _guard: guard,
+            dispatch: layers.dispatch(tracing_subscriber::registry().with(fmt_layers)),
+            log_level,
+            _writers: writers,
+            _guards: guards,
+            #[cfg(feature = "otel")]
+            _trace_exporter: trace_exporter,
         }
     }
 }
 
 impl ClockworkLogger {
-    pub(crate) fn enable_logging(&self) {
+    /// A logger that installs an externally built `dispatch`, rather than one built from config
+    pub(crate) fn from_dispatch(dispatch: Dispatch) -> Self {
+        Self {
+            dispatch,
+            log_level: LogLevelControl::default(),
+            _writers: Vec::new(),
+            _guards: Vec::new(),
+            #[cfg(feature = "otel")]
+            _trace_exporter: None,
+        }
+    }
+
+    /// Installs the logger as the global default subscriber.
+    /// Fails if a global default has already been set, e.g. by another app in the same process.
+    pub(crate) fn enable_logging(&self) -> Result<(), SetGlobalDefaultError> {
         tracing::dispatcher::set_global_default(self.dispatch.clone())
-            .expect("Unable to set logger");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clockwork_logger::{
+        ClockworkLogger, LoggerConfig, LoggerFormat, LoggerLevel, WithFields, WriteTarget,
+    };
+    use std::sync::{Arc, Mutex};
+    use tracing_subscriber::fmt::format::{format, DefaultFields};
+    use tracing_subscriber::fmt::FormatEvent;
+    use tracing_subscriber::Registry;
+
+    #[test]
+    fn test_log_targets_conf() {
+        let conf: LoggerConfig = toml::from_str(
+            r#"
+            write_target = 'STDOUT'
+            log_level = 'INFO'
+
+            [[targets]]
+            write_target = 'FILE'
+            file_name = 'debug.log'
+            log_level = 'DEBUG'
+
+            [[targets]]
+            write_target = 'STDOUT'
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(conf.targets.len(), 2);
+        assert!(matches!(
+            conf.targets[0].log_level,
+            Some(LoggerLevel::DEBUG)
+        ));
+        assert!(matches!(
+            &conf.targets[0].write_target,
+            WriteTarget::FILE { file_name } if file_name == "debug.log"
+        ));
+        assert!(conf.targets[1].log_level.is_none());
+        assert!(matches!(conf.targets[1].write_target, WriteTarget::STDOUT));
+
+        // the scalar settings have to come before `targets` for the config to serialize
+        let conf: LoggerConfig = toml::from_str(&toml::to_string(&conf).unwrap()).unwrap();
+        assert_eq!(conf.targets.len(), 2);
+    }
+
+    #[test]
+    fn test_buffer_conf() {
+        let conf: LoggerConfig = toml::from_str("write_target = 'STDOUT'").unwrap();
+        assert_eq!(conf.buffered_lines_limit, 128_000);
+        assert!(conf.lossy);
+
+        let conf: LoggerConfig = toml::from_str(
+            r#"
+            write_target = 'STDOUT'
+            buffered_lines_limit = 16
+            lossy = false
+        "#,
+        )
+        .unwrap();
+        assert_eq!(conf.buffered_lines_limit, 16);
+        assert!(!conf.lossy);
+        let _logger = ClockworkLogger::from(conf);
+    }
+
+    #[test]
+    fn test_static_fields() {
+        struct Captured(Arc<Mutex<Vec<u8>>>);
+
+        impl std::io::Write for Captured {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        // Logs one event through `format` wrapped with the fields of `conf`, returns the output
+        fn log_line<E>(format: E, conf: &LoggerConfig) -> String
+        where
+            E: FormatEvent<Registry, DefaultFields> + Send + Sync + 'static,
+        {
+            let output = Arc::new(Mutex::new(Vec::new()));
+            let subscriber = {
+                let output = output.clone();
+                tracing_subscriber::fmt()
+                    .with_writer(move || Captured(output.clone()))
+                    .event_format(WithFields::new(format, conf))
+                    .finish()
+            };
+            tracing::subscriber::with_default(subscriber, || tracing::info!(job = 7, "hello"));
+            let output = output.lock().unwrap().clone();
+            String::from_utf8(output).unwrap()
+        }
+
+        let mut conf: LoggerConfig = toml::from_str(
+            r#"
+            write_target = 'STDOUT'
+            log_format = 'JSON'
+
+            [fields]
+            service = 'billing'
+            env = 'prod "eu"'
+        "#,
+        )
+        .unwrap();
+        assert_eq!(conf.fields.len(), 2);
+
+        let json = log_line(format().json().without_time(), &conf);
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(json["service"], "billing");
+        assert_eq!(json["env"], "prod \"eu\"");
+        assert_eq!(json["fields"]["message"], "hello");
+
+        conf.log_format = LoggerFormat::FULL;
+        let full = log_line(format().without_time().with_ansi(false), &conf);
+        assert!(
+            full.ends_with("hello job=7 env=prod \"eu\" service=billing\n"),
+            "{}",
+            full
+        );
+
+        conf.fields.clear();
+        let plain = log_line(format().without_time().with_ansi(false), &conf);
+        assert!(plain.ends_with("hello job=7\n"), "{}", plain);
+    }
+
+    #[test]
+    fn test_invalid_variant_lists_accepted_values() {
+        let err = toml::from_str::<LoggerConfig>("log_level = 'VERBOSE'\nwrite_target = 'STDOUT'")
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains(
+            "unknown variant `VERBOSE`, expected one of `OFF`, `ERROR`, `WARN`, `INFO`, `DEBUG`, `TRACE`"
+        ));
+
+        let err = toml::from_str::<LoggerConfig>("log_format = 'XML'\nwrite_target = 'STDOUT'")
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains(
+            "unknown variant `XML`, expected one of `COMPACT`, `PRETTY`, `JSON`, `FULL`"
+        ));
+    }
+
+    #[test]
+    fn test_log_level_control() {
+        let dir = std::env::temp_dir().join(format!("clockwork_reload_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let conf: LoggerConfig = toml::from_str(&format!(
+            r#"
+            log_level = 'INFO'
+            write_target = 'FILE'
+            file_name = '{0}/main.log'
+
+            [[targets]]
+            log_level = 'WARN'
+            write_target = 'FILE'
+            file_name = '{0}/warn.log'
+        "#,
+            dir.display()
+        ))
+        .unwrap();
+
+        let logger = ClockworkLogger::from(conf);
+        tracing::dispatcher::with_default(&logger.dispatch, || {
+            tracing::debug!("before");
+            assert!(logger.log_level.set(LoggerLevel::DEBUG));
+            tracing::debug!("after");
+        });
+        drop(logger);
+
+        let main = std::fs::read_to_string(dir.join("main.log")).unwrap();
+        let warn = std::fs::read_to_string(dir.join("warn.log")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(!main.contains("before"));
+        assert!(main.contains("after"));
+        assert!(warn.is_empty());
+
+        let logger = ClockworkLogger::from_dispatch(tracing::Dispatch::none());
+        assert!(!logger.log_level.set(LoggerLevel::DEBUG));
+    }
+
+    #[test]
+    fn test_log_sampling() {
+        let dir = std::env::temp_dir().join(format!("clockwork_sampling_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let conf: LoggerConfig = toml::from_str(&format!(
+            r#"
+            log_level = 'INFO'
+            sample_rate = 3
+            write_target = 'FILE'
+            file_name = '{0}/rate.log'
+
+            [[targets]]
+            write_target = 'FILE'
+            file_name = '{0}/debug.log'
+            log_level = 'DEBUG'
+        "#,
+            dir.display()
+        ))
+        .unwrap();
+
+        let logger = ClockworkLogger::from(conf);
+        tracing::dispatcher::with_default(&logger.dispatch, || {
+            for run in 0..7 {
+                // filtered out by level, does not count towards the sampling
+                tracing::debug!("hot debug {}", run);
+                tracing::info!("hot task ran {}", run);
+                tracing::warn!("hot task warned {}", run);
+            }
+        });
+        drop(logger);
+
+        let rate = std::fs::read_to_string(dir.join("rate.log")).unwrap();
+        let debug = std::fs::read_to_string(dir.join("debug.log")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(rate.matches("hot task ran").count(), 3);
+        for run in [0, 3, 6] {
+            assert!(rate.contains(&format!("hot task ran {}", run)));
+        }
+        assert_eq!(rate.matches("hot task warned").count(), 7);
+        assert!(!rate.contains("hot debug"));
+        // each target samples on its own
+        assert_eq!(debug.matches("hot debug").count(), 3);
+        assert_eq!(debug.matches("hot task ran").count(), 3);
+
+        let conf: LoggerConfig = toml::from_str(&format!(
+            "sample_interval = '1h'\nwrite_target = 'FILE'\nfile_name = '{}/interval.log'",
+            dir.display()
+        ))
+        .unwrap();
+        std::fs::create_dir_all(&dir).unwrap();
+        let logger = ClockworkLogger::from(conf);
+        tracing::dispatcher::with_default(&logger.dispatch, || {
+            for _ in 0..5 {
+                tracing::info!("hot task ran");
+            }
+        });
+        drop(logger);
+
+        let interval = std::fs::read_to_string(dir.join("interval.log")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(interval.matches("hot task ran").count(), 1);
+    }
+
+    #[test]
+    fn test_enable_logging_twice() {
+        let logger = ClockworkLogger::from(LoggerConfig::default());
+
+        // another test may have installed a subscriber already, only the second call is certain
+        let _ = logger.enable_logging();
+        assert!(logger.enable_logging().is_err());
     }
 }
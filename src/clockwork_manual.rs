@@ -0,0 +1,275 @@
+use crate::TaskHandle;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+enum ManualTask {
+    Once(Box<dyn FnOnce() + Send>, TaskHandle),
+    Repeating(Arc<dyn Fn() + Send + Sync>, Duration),
+}
+
+/// A task waiting in the queue, ordered by when it is due, then by when it was scheduled
+struct Entry {
+    due: Instant,
+    seq: u64,
+    task: ManualTask,
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.due, self.seq).cmp(&(other.due, other.seq))
+    }
+}
+
+struct Schedule {
+    /// Time of the last `tick`, what delays are counted from
+    now: Instant,
+    next_seq: u64,
+    queue: BinaryHeap<Reverse<Entry>>,
+    stopped: bool,
+}
+
+impl Schedule {
+    fn push(&mut self, due: Instant, task: ManualTask) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.queue.push(Reverse(Entry { due, seq, task }));
+    }
+}
+
+/// Scheduler without a runtime behind it, for targets where a tokio runtime is too heavy (e.g.
+/// embedded), `manual-exec` feature only.
+/// Tasks are scheduled the same way as through a `ClockworkHandle`, but are only recorded: the
+/// caller fires the ones that are due by calling `tick` from their own loop, and tasks run on the
+/// caller's thread, inside `tick`. Delays count from the time of the last `tick` (from creation
+/// until the first one).
+/// Clones share the schedule, so that a task can schedule more through a clone it holds.
+/// The crate itself still depends on tokio, this scheduler only makes no use of it.
+/// ```
+/// use clockwork::ManualClockwork;
+/// use std::time::{Duration, Instant};
+///
+/// let start = Instant::now();
+/// let cw = ManualClockwork::starting_at(start);
+/// cw.schedule_repeating_task(|| println!("tick"), Duration::from_secs(1));
+/// cw.schedule_oneof_task(|| println!("once"), Duration::from_millis(1500));
+///
+/// assert_eq!(cw.tick(start), 1);
+/// assert_eq!(cw.tick(start + Duration::from_secs(2)), 3);
+/// assert_eq!(cw.next_due(), Some(start + Duration::from_secs(3)));
+/// ```
+#[derive(Clone)]
+pub struct ManualClockwork {
+    schedule: Arc<Mutex<Schedule>>,
+}
+
+impl Default for ManualClockwork {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ManualClockwork {
+    pub fn new() -> Self {
+        Self::starting_at(Instant::now())
+    }
+
+    /// Creates a scheduler whose delays count from `now` until the first `tick`
+    pub fn starting_at(now: Instant) -> Self {
+        Self {
+            schedule: Arc::new(Mutex::new(Schedule {
+                now,
+                next_seq: 0,
+                queue: BinaryHeap::new(),
+                stopped: false,
+            })),
+        }
+    }
+
+    /// Schedules a task that runs once, on the first `tick` at least `duration` after the last
+    /// one. If stopped, the task is not scheduled.
+    /// Returns a `TaskHandle` to defuse the task with, it comes back already cancelled if the task
+    /// was not scheduled.
+    pub fn schedule_oneof_task<F>(&self, f: F, duration: Duration) -> TaskHandle
+    where
+        F: 'static + FnOnce() + Send,
+    {
+        let at = self.schedule.lock().unwrap().now + duration;
+        self.schedule_oneof_task_at(f, at)
+    }
+
+    /// Same as `schedule_oneof_task`, running on the first `tick` at or after `at`
+    pub fn schedule_oneof_task_at<F>(&self, f: F, at: Instant) -> TaskHandle
+    where
+        F: 'static + FnOnce() + Send,
+    {
+        let task = TaskHandle::default();
+        let mut schedule = self.schedule.lock().unwrap();
+        if schedule.stopped {
+            task.cancel();
+        } else {
+            schedule.push(at, ManualTask::Once(Box::new(f), task.clone()));
+        }
+        task
+    }
+
+    /// Schedules a task that repeats every period until stopped, first running on the next `tick`.
+    /// If stopped, the task is not scheduled.
+    pub fn schedule_repeating_task<F>(&self, f: F, period: Duration)
+    where
+        F: 'static + Fn() + Send + Sync,
+    {
+        let start = self.schedule.lock().unwrap().now;
+        self.schedule_repeating_task_at(f, start, period);
+    }
+
+    /// Schedules a task that repeats every period, running at `start + n * period`.
+    /// A `tick` that is late by several periods runs the task once for each of them, the same as
+    /// `MissedTickBehavior::Burst`.
+    /// If stopped, the task is not scheduled.
+    /// Panics if `period` is zero.
+    pub fn schedule_repeating_task_at<F>(&self, f: F, start: Instant, period: Duration)
+    where
+        F: 'static + Fn() + Send + Sync,
+    {
+        assert!(!period.is_zero(), "`period` must be non-zero.");
+        let mut schedule = self.schedule.lock().unwrap();
+        if !schedule.stopped {
+            schedule.push(start, ManualTask::Repeating(Arc::new(f), period));
+        }
+    }
+
+    /// Runs every task due at `now`, in the order they are due, and returns how many runs there
+    /// were. Tasks scheduled by a task run in the same `tick` if they are due by `now`.
+    /// Once stopped, nothing runs.
+    pub fn tick(&self, now: Instant) -> usize {
+        self.schedule.lock().unwrap().now = now;
+
+        let mut runs = 0;
+        loop {
+            // Not held while the task runs, so that it can schedule through a clone
+            let entry = {
+                let mut schedule = self.schedule.lock().unwrap();
+                match schedule.queue.peek() {
+                    Some(Reverse(entry)) if !schedule.stopped && entry.due <= now => {
+                        schedule.queue.pop().unwrap().0
+                    }
+                    _ => return runs,
+                }
+            };
+
+            match entry.task {
+                ManualTask::Once(f, task) => {
+                    if !task.is_cancelled() {
+                        f();
+                        runs += 1;
+                    }
+                }
+                ManualTask::Repeating(f, period) => {
+                    f();
+                    runs += 1;
+                    let mut schedule = self.schedule.lock().unwrap();
+                    if !schedule.stopped {
+                        schedule.push(entry.due + period, ManualTask::Repeating(f, period));
+                    }
+                }
+            }
+        }
+    }
+
+    /// When the next task is due, e.g. for the caller to sleep until then. `None` if there is
+    /// nothing left to run or if stopped.
+    pub fn next_due(&self) -> Option<Instant> {
+        let schedule = self.schedule.lock().unwrap();
+        if schedule.stopped {
+            return None;
+        }
+        schedule.queue.peek().map(|Reverse(entry)| entry.due)
+    }
+
+    /// Stops the scheduler: the tasks still scheduled are dropped and nothing is scheduled or run
+    /// any more
+    pub fn stop(&self) {
+        let mut schedule = self.schedule.lock().unwrap();
+        schedule.stopped = true;
+        schedule.queue.clear();
+    }
+
+    /// Whether `stop` has been called
+    pub fn stopped(&self) -> bool {
+        self.schedule.lock().unwrap().stopped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clockwork_manual::ManualClockwork;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_manual_tick() {
+        let start = Instant::now();
+        let cw = ManualClockwork::starting_at(start);
+        let events = Arc::new(Mutex::new(Vec::new()));
+
+        let repeating = events.clone();
+        cw.schedule_repeating_task(
+            move || repeating.lock().unwrap().push("repeat"),
+            Duration::from_secs(1),
+        );
+        let once = events.clone();
+        cw.schedule_oneof_task(
+            move || once.lock().unwrap().push("once"),
+            Duration::from_millis(500),
+        );
+        let defused = cw.schedule_oneof_task(|| panic!("defused"), Duration::from_millis(100));
+        defused.cancel();
+
+        assert_eq!(cw.tick(start), 1);
+        assert_eq!(cw.tick(start + Duration::from_millis(999)), 1);
+        // late by two periods, runs for each of them
+        assert_eq!(cw.tick(start + Duration::from_secs(3)), 3);
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec!["repeat", "once", "repeat", "repeat", "repeat"]
+        );
+        assert_eq!(cw.next_due(), Some(start + Duration::from_secs(4)));
+
+        // a task can schedule through a clone, its delay counts from the last tick
+        let nested = cw.clone();
+        let inner = events.clone();
+        cw.schedule_oneof_task(
+            move || {
+                nested.schedule_oneof_task(
+                    move || inner.lock().unwrap().push("nested"),
+                    Duration::ZERO,
+                );
+            },
+            Duration::ZERO,
+        );
+        assert_eq!(cw.tick(start + Duration::from_secs(3)), 2);
+        assert_eq!(events.lock().unwrap().last(), Some(&"nested"));
+
+        cw.stop();
+        assert!(cw.stopped());
+        assert_eq!(cw.next_due(), None);
+        assert_eq!(cw.tick(start + Duration::from_secs(10)), 0);
+        assert!(cw.schedule_oneof_task(|| {}, Duration::ZERO).is_cancelled());
+    }
+}
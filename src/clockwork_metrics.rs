@@ -0,0 +1,230 @@
+use crate::ClockworkHandle;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+fn default_host() -> String {
+    "0.0.0.0".to_string()
+}
+
+/// Metrics endpoint configurations
+/// host - address to listen on, defaults to all interfaces
+/// port - port to serve the metrics on, scrape `http://<host>:<port>/metrics`
+#[derive(Deserialize, Serialize, Clone)]
+pub struct MetricsConfig {
+    #[serde(default = "default_host")]
+    pub host: String,
+    pub port: u16,
+}
+
+/// Saturation of the tokio runtime behind a `ClockworkHandle` (see
+/// `ClockworkHandle::runtime_metrics`), useful to tell why scheduled tasks are running late
+/// workers - number of worker threads
+/// alive_tasks - number of tasks spawned on the runtime that have not completed yet
+/// global_queue_depth - number of tasks waiting in the runtime's global queue
+/// blocking_threads - number of threads in the blocking pool (`tokio_unstable` only)
+/// idle_blocking_threads - number of idle threads in the blocking pool (`tokio_unstable` only)
+/// blocking_queue_depth - number of tasks waiting for a blocking thread (`tokio_unstable` only)
+#[derive(Clone, Debug)]
+pub struct RuntimeMetrics {
+    pub workers: usize,
+    pub alive_tasks: usize,
+    pub global_queue_depth: usize,
+    #[cfg(tokio_unstable)]
+    pub blocking_threads: usize,
+    #[cfg(tokio_unstable)]
+    pub idle_blocking_threads: usize,
+    #[cfg(tokio_unstable)]
+    pub blocking_queue_depth: usize,
+}
+
+impl From<tokio::runtime::RuntimeMetrics> for RuntimeMetrics {
+    fn from(metrics: tokio::runtime::RuntimeMetrics) -> Self {
+        Self {
+            workers: metrics.num_workers(),
+            alive_tasks: metrics.num_alive_tasks(),
+            global_queue_depth: metrics.global_queue_depth(),
+            #[cfg(tokio_unstable)]
+            blocking_threads: metrics.num_blocking_threads(),
+            #[cfg(tokio_unstable)]
+            idle_blocking_threads: metrics.num_idle_blocking_threads(),
+            #[cfg(tokio_unstable)]
+            blocking_queue_depth: metrics.blocking_queue_depth(),
+        }
+    }
+}
+
+/// Escapes a Prometheus label value
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Renders the runtime's metrics in the Prometheus text format
+pub(crate) fn render(handle: &ClockworkHandle) -> String {
+    let mut out = String::new();
+    let stats = handle.task_stats();
+
+    // Writing into a String cannot fail
+    let _ = writeln!(
+        out,
+        "# HELP clockwork_uptime_seconds Time since the runtime was created.\n\
+         # TYPE clockwork_uptime_seconds gauge\n\
+         clockwork_uptime_seconds {}",
+        handle.uptime().as_secs_f64()
+    );
+    let _ = writeln!(
+        out,
+        "# HELP clockwork_tasks_in_flight Spawned tasks that have not completed yet.\n\
+         # TYPE clockwork_tasks_in_flight gauge\n\
+         clockwork_tasks_in_flight {}",
+        handle.tasks_in_flight()
+    );
+
+    let _ = writeln!(
+        out,
+        "# HELP clockwork_task_runs_total Runs of named tasks, by outcome.\n\
+         # TYPE clockwork_task_runs_total counter"
+    );
+    for (name, stats) in &stats {
+        let name = escape(name);
+        for (outcome, count) in [("ok", stats.ok), ("err", stats.err), ("panic", stats.panic)] {
+            let _ = writeln!(
+                out,
+                "clockwork_task_runs_total{{task=\"{}\",outcome=\"{}\"}} {}",
+                name, outcome, count
+            );
+        }
+    }
+
+    let _ = writeln!(
+        out,
+        "# HELP clockwork_task_run_seconds_total Time spent running named tasks.\n\
+         # TYPE clockwork_task_run_seconds_total counter"
+    );
+    for (name, stats) in &stats {
+        let _ = writeln!(
+            out,
+            "clockwork_task_run_seconds_total{{task=\"{}\"}} {}",
+            escape(name),
+            stats.elapsed.as_secs_f64()
+        );
+    }
+
+    out
+}
+
+/// Answers a single scrape, whatever the request, then closes the connection
+async fn respond(mut stream: TcpStream, handle: &ClockworkHandle) -> std::io::Result<()> {
+    let mut request = [0u8; 1024];
+    let _ = stream.read(&mut request).await?;
+
+    let body = render(handle);
+    let response = format!(
+        "HTTP/1.1 200 OK\r\n\
+         Content-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Binds the metrics endpoint, panics if the address cannot be bound
+pub(crate) fn bind(conf: &MetricsConfig) -> std::net::TcpListener {
+    let listener = std::net::TcpListener::bind((conf.host.as_str(), conf.port))
+        .expect("Failed to bind metrics endpoint!");
+    listener
+        .set_nonblocking(true)
+        .expect("Failed to bind metrics endpoint!");
+    listener
+}
+
+/// Serves the metrics endpoint until runtime is stopped
+pub(crate) async fn serve(listener: std::net::TcpListener, handle: ClockworkHandle) {
+    let listener = TcpListener::from_std(listener).expect("Failed to bind metrics endpoint!");
+    let mut stop = handle.stop_signal();
+
+    loop {
+        tokio::select! {
+            _ = &mut stop => break,
+            accepted = listener.accept() => {
+                if let Ok((stream, _)) = accepted {
+                    let _ = respond(stream, &handle).await;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clockwork_metrics::render;
+    use crate::{Clockwork, ClockworkConfig, ClockworkHandle};
+    use std::io::{Read, Write};
+    use tokio::time::Duration;
+
+    #[test]
+    fn test_render() {
+        let cw = Clockwork::default().with_run_for(Duration::from_millis(20));
+        cw.schedule_named_oneof_task("say \"hi\"", || {}, Duration::from_millis(0));
+        cw.schedule_named_oneof_task("fail", || Err("failed"), Duration::from_millis(0));
+        cw.run(&|_| {});
+
+        let metrics = render(&cw.handle());
+        assert!(metrics.contains("# TYPE clockwork_uptime_seconds gauge\n"));
+        assert!(metrics.contains("clockwork_tasks_in_flight 0\n"));
+        assert!(metrics.contains("clockwork_task_runs_total{task=\"fail\",outcome=\"err\"} 1\n"));
+        assert!(metrics.contains("clockwork_task_runs_total{task=\"fail\",outcome=\"ok\"} 0\n"));
+        assert!(metrics
+            .contains("clockwork_task_runs_total{task=\"say \\\"hi\\\"\",outcome=\"ok\"} 1\n"));
+    }
+
+    #[test]
+    fn test_runtime_metrics() {
+        let rt = std::sync::Arc::new(
+            tokio::runtime::Builder::new_multi_thread()
+                .worker_threads(2)
+                .build()
+                .unwrap(),
+        );
+        let handle = ClockworkHandle::new(rt);
+        handle.spawn_task(std::future::pending::<()>()).unwrap();
+
+        let metrics = handle.runtime_metrics();
+        assert_eq!(metrics.workers, 2);
+        assert_eq!(metrics.alive_tasks, 1);
+    }
+
+    #[test]
+    fn test_serve() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let conf: ClockworkConfig =
+            toml::from_str(&format!("[metrics]\nhost = '127.0.0.1'\nport = {}", port)).unwrap();
+        let cw = Clockwork::from(conf);
+        let thread = cw.run_detached(|_| {});
+
+        let mut response = String::new();
+        let mut stream = loop {
+            match std::net::TcpStream::connect(("127.0.0.1", port)) {
+                Ok(stream) => break stream,
+                Err(_) => std::thread::sleep(Duration::from_millis(5)),
+            }
+        };
+        stream.write_all(b"GET /metrics HTTP/1.1\r\n\r\n").unwrap();
+        stream.read_to_string(&mut response).unwrap();
+        thread.stop_and_join().unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("clockwork_uptime_seconds"));
+    }
+}
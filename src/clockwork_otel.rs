@@ -0,0 +1,58 @@
+use opentelemetry::sdk::trace::Tracer;
+use opentelemetry_otlp::WithExportConfig;
+use serde::{Deserialize, Serialize};
+use tokio::runtime::{Builder, Runtime};
+
+/// OpenTelemetry span export configurations
+/// endpoint - OTLP (gRPC) collector endpoint to export spans to, e.g. `http://localhost:4317`
+#[derive(Deserialize, Serialize, Clone)]
+pub struct TraceExportConfig {
+    pub(crate) endpoint: String,
+}
+
+/// Owns the OTLP export pipeline.
+/// Spans are exported from a dedicated runtime so that exporting (and flushing on drop) does not
+/// depend on the `Clockwork` runtime being driven.
+pub(crate) struct TraceExporter {
+    tracer: Tracer,
+    _rt: Runtime,
+}
+
+impl From<TraceExportConfig> for TraceExporter {
+    fn from(conf: TraceExportConfig) -> Self {
+        let rt = Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("clockwork-otel")
+            .enable_all()
+            .build()
+            .expect("Failed to Build Trace Export Runtime");
+
+        let tracer = {
+            let _guard = rt.enter();
+            opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_exporter(
+                    opentelemetry_otlp::new_exporter()
+                        .tonic()
+                        .with_endpoint(conf.endpoint),
+                )
+                .install_batch(opentelemetry::runtime::Tokio)
+                .expect("Failed to install OTLP pipeline")
+        };
+
+        Self { tracer, _rt: rt }
+    }
+}
+
+impl TraceExporter {
+    pub(crate) fn tracer(&self) -> Tracer {
+        self.tracer.clone()
+    }
+}
+
+impl Drop for TraceExporter {
+    /// Flushes any spans that have not been exported yet
+    fn drop(&mut self) {
+        opentelemetry::global::shutdown_tracer_provider();
+    }
+}
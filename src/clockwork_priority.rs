@@ -0,0 +1,92 @@
+use crate::clockwork::StopSignal;
+use std::cmp::Ordering as CmpOrdering;
+use std::collections::BinaryHeap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use tokio::sync::Notify;
+
+type BoxedTask = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Priority of a task spawned with `spawn_task_with_priority`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TaskPriority {
+    Low,
+    Normal,
+    High,
+}
+
+struct QueuedTask {
+    priority: TaskPriority,
+    seq: u64,
+    task: BoxedTask,
+}
+
+/// Highest priority first, then the earliest submitted first
+impl Ord for QueuedTask {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl PartialOrd for QueuedTask {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for QueuedTask {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == CmpOrdering::Equal
+    }
+}
+
+impl Eq for QueuedTask {}
+
+/// Queue of pending prioritized tasks, drained by a single dispatcher task
+#[derive(Default)]
+pub(crate) struct PriorityQueue {
+    tasks: Mutex<BinaryHeap<QueuedTask>>,
+    next_seq: AtomicU64,
+    notify: Notify,
+    dispatching: AtomicBool,
+}
+
+impl PriorityQueue {
+    /// Queues a task, returns true if the caller should start the dispatcher
+    pub(crate) fn push(&self, priority: TaskPriority, task: BoxedTask) -> bool {
+        let seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+        self.tasks.lock().unwrap().push(QueuedTask {
+            priority,
+            seq,
+            task,
+        });
+        self.notify.notify_one();
+
+        !self.dispatching.swap(true, Ordering::AcqRel)
+    }
+
+    fn pop(&self) -> Option<BoxedTask> {
+        self.tasks.lock().unwrap().pop().map(|queued| queued.task)
+    }
+
+    /// Runs queued tasks one at a time, highest priority first, until the runtime is stopped
+    pub(crate) async fn dispatch(&self, mut stop: StopSignal) {
+        loop {
+            while let Some(task) = self.pop() {
+                tokio::select! {
+                    _ = &mut stop => return,
+                    _ = task => {}
+                }
+            }
+
+            tokio::select! {
+                _ = &mut stop => return,
+                _ = self.notify.notified() => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use crate::{ClockworkError, Duration};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::time::Instant;
+
+/// How long a single TCP/HTTP check may take before it counts as failed
+const CHECK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Time between two rounds of checks
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// A dependency that has to be ready before the app is set up
+/// TCP - `address` (`host:port`) accepts connections
+/// FILE - `path` exists
+/// HTTP - `url` (plain `http://` only) answers a GET with a 2xx status
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(tag = "check")]
+pub enum ReadinessCheck {
+    TCP { address: String },
+    FILE { path: PathBuf },
+    HTTP { url: String },
+}
+
+impl Display for ReadinessCheck {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReadinessCheck::TCP { address } => write!(f, "tcp {}", address),
+            ReadinessCheck::FILE { path } => write!(f, "file {}", path.display()),
+            ReadinessCheck::HTTP { url } => write!(f, "http {}", url),
+        }
+    }
+}
+
+impl ReadinessCheck {
+    /// Whether the dependency is ready right now
+    pub fn is_ready(&self) -> bool {
+        match self {
+            ReadinessCheck::TCP { address } => connect(address).is_some(),
+            ReadinessCheck::FILE { path } => path.exists(),
+            ReadinessCheck::HTTP { url } => http_ok(url).unwrap_or(false),
+        }
+    }
+}
+
+fn connect(address: &str) -> Option<TcpStream> {
+    address
+        .to_socket_addrs()
+        .ok()?
+        .find_map(|addr| TcpStream::connect_timeout(&addr, CHECK_TIMEOUT).ok())
+}
+
+/// Sends a bare HTTP/1.1 GET to `url`, `None` if it cannot be sent or the answer is not HTTP
+fn http_ok(url: &str) -> Option<bool> {
+    let rest = url.strip_prefix("http://")?;
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let address = match authority.contains(':') {
+        true => authority.to_string(),
+        false => format!("{}:80", authority),
+    };
+
+    let mut stream = connect(&address)?;
+    stream.set_read_timeout(Some(CHECK_TIMEOUT)).ok()?;
+    stream.set_write_timeout(Some(CHECK_TIMEOUT)).ok()?;
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        path, authority
+    );
+    stream.write_all(request.as_bytes()).ok()?;
+
+    // Only the status line matters, e.g. `HTTP/1.1 200 OK`
+    let mut response = [0u8; 32];
+    let read = stream.read(&mut response).ok()?;
+    let status_line = std::str::from_utf8(&response[..read]).ok()?;
+    let status = status_line.split_whitespace().nth(1)?;
+    Some(status.starts_with('2') && status.len() == 3)
+}
+
+/// Readiness checks to pass before the app is set up
+/// timeout - how long to wait for every check to pass before giving up (waits for as long as it
+///           takes if omitted)
+/// checks - the checks, all of which have to pass (see `ReadinessCheck`)
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct WaitForConfig {
+    #[serde(default)]
+    pub timeout: Option<Duration>,
+    #[serde(default)]
+    pub checks: Vec<ReadinessCheck>,
+}
+
+impl WaitForConfig {
+    /// Polls the checks until they have all passed, blocking the calling thread.
+    /// Checks that have passed once are not polled again.
+    /// Fails with `ClockworkError::NotReady` once `timeout` has elapsed.
+    pub fn wait(&self) -> Result<(), ClockworkError> {
+        let deadline = self
+            .timeout
+            .map(|timeout| Instant::now() + timeout.as_std());
+        let mut pending: Vec<_> = self.checks.iter().collect();
+
+        loop {
+            pending.retain(|check| !check.is_ready());
+            if pending.is_empty() {
+                return Ok(());
+            }
+
+            let mut wait = POLL_INTERVAL;
+            if let Some(deadline) = deadline {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    return Err(ClockworkError::NotReady {
+                        pending: pending.iter().map(|check| check.to_string()).collect(),
+                    });
+                }
+                wait = wait.min(remaining);
+            }
+
+            #[cfg(feature = "logging")]
+            tracing::info!(pending = pending.len(), "Waiting for dependencies");
+            std::thread::sleep(wait);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clockwork_readiness::{ReadinessCheck, WaitForConfig};
+    use crate::ClockworkError;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    /// Serves a single request with `status`, returns the server's `http://` url
+    fn serve_once(status: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/health", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            // the whole request has to be read, closing with unread data resets the connection
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                match stream.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(read) => request.extend_from_slice(&buf[..read]),
+                }
+            }
+            let _ = write!(stream, "HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status);
+        });
+        url
+    }
+
+    #[test]
+    fn test_checks() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        assert!(ReadinessCheck::TCP { address }.is_ready());
+        drop(listener);
+
+        let path = std::env::temp_dir().join(format!("clockwork_ready_{}", std::process::id()));
+        let file = ReadinessCheck::FILE { path: path.clone() };
+        assert!(!file.is_ready());
+        std::fs::write(&path, "").unwrap();
+        assert!(file.is_ready());
+        std::fs::remove_file(&path).unwrap();
+
+        let url = serve_once("200 OK");
+        assert!(ReadinessCheck::HTTP { url }.is_ready());
+        let url = serve_once("503 Service Unavailable");
+        assert!(!ReadinessCheck::HTTP { url }.is_ready());
+    }
+
+    #[test]
+    fn test_wait() {
+        let path = std::env::temp_dir().join(format!("clockwork_wait_{}", std::process::id()));
+        let conf: WaitForConfig = toml::from_str(&format!(
+            r#"
+            timeout = '5s'
+
+            [[checks]]
+            check = 'FILE'
+            path = '{}'
+        "#,
+            path.display()
+        ))
+        .unwrap();
+
+        {
+            let path = path.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(50));
+                std::fs::write(path, "").unwrap();
+            });
+        }
+        assert!(conf.wait().is_ok());
+        std::fs::remove_file(&path).unwrap();
+
+        let conf = WaitForConfig {
+            timeout: Some(Duration::from_millis(20).into()),
+            checks: vec![ReadinessCheck::FILE { path: path.clone() }],
+        };
+        match conf.wait() {
+            Err(ClockworkError::NotReady { pending }) => {
+                assert_eq!(pending, vec![format!("file {}", path.display())])
+            }
+            _ => panic!("expected NotReady"),
+        }
+    }
+}
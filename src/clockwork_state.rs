@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Contents of the state file
+/// last_run - wall-clock time each named repeating task last started a run, in milliseconds since
+///            the UNIX epoch
+#[derive(Deserialize, Serialize, Default)]
+struct State {
+    #[serde(default)]
+    last_run: BTreeMap<String, u64>,
+}
+
+/// Last-run times of named repeating tasks, persisted to a file so that they survive restarts
+pub(crate) struct StateFile {
+    path: PathBuf,
+    state: Mutex<State>,
+}
+
+impl StateFile {
+    /// Loads the state file at `path`, a missing file is treated as empty.
+    /// Panics if the file exists but cannot be read or parsed.
+    pub(crate) fn load(path: PathBuf) -> Self {
+        let state = match std::fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents).expect("Failed to parse state file!"),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => State::default(),
+            Err(e) => panic!("State file cannot be read: {}!", e),
+        };
+
+        Self {
+            path,
+            state: Mutex::new(state),
+        }
+    }
+
+    /// When the task `name` last started a run, as recorded in the file
+    pub(crate) fn last_run(&self, name: &str) -> Option<SystemTime> {
+        let millis = *self.state.lock().unwrap().last_run.get(name)?;
+        Some(UNIX_EPOCH + Duration::from_millis(millis))
+    }
+
+    /// Records that the task `name` started a run at `at`, and writes the file.
+    /// The file is replaced atomically, a crash mid-write leaves the previous state in place.
+    /// A failed write is logged (if the `logging` feature is enabled), the task keeps running.
+    pub(crate) fn record(&self, name: &str, at: SystemTime) {
+        let millis = at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO)
+            .as_millis() as u64;
+
+        let mut state = self.state.lock().unwrap();
+        state.last_run.insert(name.to_string(), millis);
+
+        let contents = toml::to_string(&*state).expect("Failed to serialize state!");
+        let tmp_path = self.path.with_extension("tmp");
+        let written = std::fs::write(&tmp_path, contents)
+            .and_then(|_| std::fs::rename(&tmp_path, &self.path));
+        if let Err(_e) = written {
+            #[cfg(feature = "logging")]
+            tracing::warn!(task = name, path = %self.path.display(), error = %_e, "Failed to write state file");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clockwork_state::StateFile;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn test_state_file() {
+        let path =
+            std::env::temp_dir().join(format!("clockwork_state_{}.toml", std::process::id()));
+        let at = UNIX_EPOCH + Duration::from_millis(1_600_000_000_000);
+
+        let state = StateFile::load(path.clone());
+        assert_eq!(state.last_run("job"), None);
+        state.record("job", at);
+
+        let reloaded = StateFile::load(path.clone());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(reloaded.last_run("job"), Some(at));
+        assert_eq!(reloaded.last_run("other"), None);
+    }
+}
@@ -0,0 +1,216 @@
+use crate::{ClockworkHandle, Runnable, StopSignal};
+use std::error::Error;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+/// Runs `inner`'s `run` alongside `stopper`, which stops the runtime when it resolves.
+/// `run` is awaited to completion either way, so that it can clean up once asked to stop.
+fn run_with_stopper<'a, R, S>(
+    inner: &'a R,
+    handle: ClockworkHandle,
+    stop: StopSignal,
+    stopper: S,
+) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>>
+where
+    R: Runnable,
+    S: Future<Output = ()> + Send + 'a,
+{
+    let run = inner.run(handle, stop);
+    Box::pin(async move {
+        tokio::pin!(run);
+        tokio::select! {
+            _ = &mut run => return,
+            _ = stopper => {}
+        }
+        run.await
+    })
+}
+
+/// Wraps a `Runnable`, stopping the runtime once `duration` has elapsed since `run` began, the
+/// same as `run_for` but for any `Runnable` rather than through config.
+/// Every other function is delegated to the wrapped `Runnable`, whose `run` sees the stop through
+/// its `StopSignal` and is awaited to completion.
+///
+/// ```
+/// use clockwork::{run_runnable, Clockwork, ClockworkHandle, StopAfter};
+/// use std::time::Duration;
+///
+/// let app = |handle: ClockworkHandle| {
+///     handle.schedule_repeating_task(|| println!("tick"), Duration::from_millis(5));
+/// };
+/// run_runnable(Clockwork::default(), StopAfter::new(app, Duration::from_millis(20)));
+/// ```
+pub struct StopAfter<R> {
+    inner: R,
+    duration: Duration,
+}
+
+impl<R: Runnable> StopAfter<R> {
+    pub fn new(inner: R, duration: Duration) -> Self {
+        Self { inner, duration }
+    }
+}
+
+impl<R: Runnable> Runnable for StopAfter<R> {
+    fn setup(&self, handle: ClockworkHandle) {
+        self.inner.setup(handle)
+    }
+
+    fn try_setup(&self, handle: ClockworkHandle) -> Result<(), Box<dyn Error>> {
+        self.inner.try_setup(handle)
+    }
+
+    fn on_ready(&self, handle: ClockworkHandle) {
+        self.inner.on_ready(handle)
+    }
+
+    fn shutdown(&self) {
+        self.inner.shutdown()
+    }
+
+    fn run<'a>(
+        &'a self,
+        handle: ClockworkHandle,
+        stop: StopSignal,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>> {
+        let stopper = handle.clone();
+        let duration = self.duration;
+        run_with_stopper(&self.inner, handle, stop, async move {
+            stopper.stop_after(duration).await
+        })
+    }
+}
+
+/// Wraps a `Runnable`, stopping the runtime once scheduled tasks have run `iterations` times in
+/// total (see `ClockworkHandle::task_runs`), e.g. for a batch that should tick a fixed number of
+/// times. Otherwise the same as `StopAfter`.
+///
+/// ```
+/// use clockwork::{run_runnable, Clockwork, ClockworkHandle, StopAfterIterations};
+/// use std::time::Duration;
+///
+/// let app = |handle: ClockworkHandle| {
+///     handle.schedule_repeating_task(|| println!("tick"), Duration::from_millis(5));
+/// };
+/// run_runnable(Clockwork::default(), StopAfterIterations::new(app, 3));
+/// ```
+pub struct StopAfterIterations<R> {
+    inner: R,
+    iterations: u64,
+}
+
+impl<R: Runnable> StopAfterIterations<R> {
+    pub fn new(inner: R, iterations: u64) -> Self {
+        Self { inner, iterations }
+    }
+}
+
+impl<R: Runnable> Runnable for StopAfterIterations<R> {
+    fn setup(&self, handle: ClockworkHandle) {
+        self.inner.setup(handle)
+    }
+
+    fn try_setup(&self, handle: ClockworkHandle) -> Result<(), Box<dyn Error>> {
+        self.inner.try_setup(handle)
+    }
+
+    fn on_ready(&self, handle: ClockworkHandle) {
+        self.inner.on_ready(handle)
+    }
+
+    fn shutdown(&self) {
+        self.inner.shutdown()
+    }
+
+    fn run<'a>(
+        &'a self,
+        handle: ClockworkHandle,
+        stop: StopSignal,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>> {
+        let stopper = handle.clone();
+        let iterations = self.iterations;
+        run_with_stopper(&self.inner, handle, stop, async move {
+            stopper.stop_after_runs(iterations).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clockwork_stop_after::{StopAfter, StopAfterIterations};
+    use crate::{run_runnable, Clockwork, ClockworkHandle, Runnable, StopReason, StopSignal};
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    struct Ticker {
+        ticks: Arc<AtomicU32>,
+        events: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    impl Runnable for Ticker {
+        fn setup(&self, handle: ClockworkHandle) {
+            let ticks = self.ticks.clone();
+            handle.schedule_repeating_task(
+                move || {
+                    ticks.fetch_add(1, Ordering::SeqCst);
+                },
+                Duration::from_millis(5),
+            );
+        }
+
+        fn shutdown(&self) {
+            self.events.lock().unwrap().push("shutdown");
+        }
+
+        fn run<'a>(
+            &'a self,
+            _handle: ClockworkHandle,
+            stop: StopSignal,
+        ) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>> {
+            Box::pin(async move {
+                stop.await;
+                self.events.lock().unwrap().push("run");
+            })
+        }
+    }
+
+    fn ticker() -> (Ticker, Arc<AtomicU32>, Arc<Mutex<Vec<&'static str>>>) {
+        let ticks = Arc::new(AtomicU32::new(0));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let ticker = Ticker {
+            ticks: ticks.clone(),
+            events: events.clone(),
+        };
+        (ticker, ticks, events)
+    }
+
+    #[test]
+    fn test_stop_after() {
+        let (ticker, ticks, events) = ticker();
+        let cw = Clockwork::default();
+        let handle = cw.handle();
+
+        run_runnable(cw, StopAfter::new(ticker, Duration::from_millis(30)));
+        assert_eq!(handle.stop_reason(), Some(StopReason::RunFor));
+        assert!(ticks.load(Ordering::SeqCst) >= 2);
+        // the wrapped `run` sees the stop and completes before shutdown
+        assert_eq!(*events.lock().unwrap(), vec!["run", "shutdown"]);
+    }
+
+    #[test]
+    fn test_stop_after_iterations() {
+        let (ticker, ticks, events) = ticker();
+        let cw = Clockwork::default();
+        let handle = cw.handle();
+
+        run_runnable(cw, StopAfterIterations::new(ticker, 3));
+        assert_eq!(handle.stop_reason(), Some(StopReason::RunCount));
+        assert_eq!(handle.task_runs(), 3);
+        assert_eq!(ticks.load(Ordering::SeqCst), 3);
+        assert_eq!(*events.lock().unwrap(), vec!["run", "shutdown"]);
+    }
+}
@@ -0,0 +1,687 @@
+use crate::clockwork_config::DuplicateTaskPolicy;
+use std::any::Any;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Display;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tokio::sync::Notify;
+use tokio::time::{Duration, Instant};
+
+/// How a single run of a named task ended
+#[derive(Clone, Debug, PartialEq)]
+pub enum TaskOutcome {
+    Ok,
+    Err(String),
+    Panic(String),
+}
+
+/// A single run of a named task, as kept in the task's history
+/// started_at - wall-clock time at which the run started
+/// elapsed - how long the run took
+/// outcome - how the run ended
+#[derive(Clone, Debug)]
+pub struct TaskRun {
+    pub started_at: SystemTime,
+    pub elapsed: Duration,
+    pub outcome: TaskOutcome,
+}
+
+/// How a named task is scheduled
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum TaskKind {
+    Repeating,
+    OneShot,
+}
+
+/// Why `ClockworkHandle::spawn_task` did not spawn a future
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum SpawnError {
+    /// The runtime (or the handle's scope) is stopped
+    Stopped,
+    /// `max_concurrent_tasks` futures are already running, under the REJECT policy
+    TaskLimit,
+}
+
+impl Display for SpawnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpawnError::Stopped => write!(f, "runtime is stopped"),
+            SpawnError::TaskLimit => write!(f, "concurrent task limit reached"),
+        }
+    }
+}
+
+impl std::error::Error for SpawnError {}
+
+/// A named task that is still scheduled to run, as listed by `ClockworkHandle::scheduled_tasks`
+/// name - name the task was scheduled with
+/// kind - whether the task repeats or runs once
+/// period - time between runs (repeating tasks only)
+/// next_fire - estimate of when the task runs next
+/// paused - whether runs are currently being skipped (see `ClockworkHandle::pause_task`)
+/// group - group the task was scheduled in, if any (see `ClockworkHandle::in_group`)
+#[derive(Clone, Debug)]
+pub struct TaskInfo {
+    pub name: String,
+    pub kind: TaskKind,
+    pub period: Option<Duration>,
+    pub next_fire: Instant,
+    pub paused: bool,
+    pub group: Option<String>,
+}
+
+#[derive(Default, Debug)]
+struct Cancellation {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+/// Cancels a scheduled task, as returned by `ClockworkHandle::schedule_batch` and
+/// `ClockworkHandle::schedule_oneof_task`.
+/// Clones share the task, cancelling any of them cancels it.
+#[derive(Clone, Default, Debug)]
+pub struct TaskHandle {
+    inner: Arc<Cancellation>,
+}
+
+impl TaskHandle {
+    /// Stops the task from running again, a run already in progress completes.
+    /// A one-shot task cancelled before it fires never runs.
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::Release);
+        self.inner.notify.notify_waiters();
+    }
+
+    /// Whether the task has been cancelled, or was never scheduled in the first place
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::Acquire)
+    }
+
+    /// Resolves once the task is cancelled
+    pub(crate) async fn cancelled(&self) {
+        // Registered before checking, otherwise a `cancel` made in between would be missed
+        let notified = self.inner.notify.notified();
+        if self.is_cancelled() {
+            return;
+        }
+        notified.await;
+    }
+}
+
+/// Return types a named task may have.
+/// Tasks returning `()` always succeed, tasks returning a `Result` fail with the error's message.
+pub trait TaskResult {
+    fn into_outcome(self) -> TaskOutcome;
+}
+
+impl TaskResult for () {
+    fn into_outcome(self) -> TaskOutcome {
+        TaskOutcome::Ok
+    }
+}
+
+impl<E: Display> TaskResult for Result<(), E> {
+    fn into_outcome(self) -> TaskOutcome {
+        match self {
+            Ok(()) => TaskOutcome::Ok,
+            Err(e) => TaskOutcome::Err(e.to_string()),
+        }
+    }
+}
+
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    match payload.downcast_ref::<String>() {
+        Some(message) => message.clone(),
+        None => match payload.downcast_ref::<&'static str>() {
+            Some(message) => message.to_string(),
+            None => "Box<dyn Any>".to_string(),
+        },
+    }
+}
+
+type TaskBody = Arc<dyn Fn() -> TaskOutcome + Send + Sync>;
+
+fn task_body<F, R>(f: F) -> TaskBody
+where
+    F: 'static + Fn() -> R + Send + Sync,
+    R: TaskResult,
+{
+    Arc::new(move || f().into_outcome())
+}
+
+/// Cumulative counters of a named task's runs
+#[cfg(feature = "metrics")]
+#[derive(Clone, Default)]
+pub(crate) struct TaskStats {
+    pub(crate) ok: u64,
+    pub(crate) err: u64,
+    pub(crate) panic: u64,
+    pub(crate) elapsed: Duration,
+}
+
+struct TaskEntry {
+    /// Tells the scheduled loops of a replaced task apart from the current one's
+    generation: u64,
+    body: TaskBody,
+    kind: TaskKind,
+    period: Option<Duration>,
+    /// The period to go back to once a boost (see `boost`) ends
+    boosted_from: Option<Duration>,
+    /// `None` once a one-shot task has run
+    next_fire: Option<Instant>,
+    paused: bool,
+    group: Option<String>,
+    history: VecDeque<TaskRun>,
+    #[cfg(feature = "metrics")]
+    stats: TaskStats,
+}
+
+/// Whether a named task has completed a run yet, with the waiters for its first one
+#[derive(Default)]
+struct FirstRun {
+    done: bool,
+    notify: Arc<Notify>,
+}
+
+/// Book-keeping for named tasks, keyed by name
+pub(crate) struct TaskRegistry {
+    history_size: usize,
+    generations: AtomicU64,
+    tasks: Mutex<HashMap<String, TaskEntry>>,
+    /// Kept apart from `tasks`, as a task can be waited on before it is registered
+    first_runs: Mutex<HashMap<String, FirstRun>>,
+}
+
+impl TaskRegistry {
+    pub(crate) fn new(history_size: usize) -> Self {
+        Self {
+            history_size,
+            generations: AtomicU64::new(0),
+            tasks: Default::default(),
+            first_runs: Default::default(),
+        }
+    }
+
+    /// Registers the task `name` in `group`, with `f` as its body, first running at `first_fire`.
+    /// If a task of that name is still scheduled, it is replaced or the registration is rejected
+    /// according to `policy`; a replaced task keeps its history.
+    /// Returns the generation to pass to `run`, or `None` if the registration was rejected.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn register<F, R>(
+        &self,
+        name: &str,
+        group: Option<&str>,
+        f: F,
+        kind: TaskKind,
+        period: Option<Duration>,
+        first_fire: Instant,
+        policy: DuplicateTaskPolicy,
+    ) -> Option<u64>
+    where
+        F: 'static + Fn() -> R + Send + Sync,
+        R: TaskResult,
+    {
+        let mut tasks = self.tasks.lock().unwrap();
+        if let Some(previous) = tasks.get(name) {
+            if previous.next_fire.is_some() {
+                #[cfg(feature = "logging")]
+                tracing::warn!(task = name, ?policy, "Named task is already scheduled");
+                if policy == DuplicateTaskPolicy::REJECT {
+                    return None;
+                }
+            }
+        }
+
+        let mut history = VecDeque::new();
+        #[cfg(feature = "metrics")]
+        let mut stats = TaskStats::default();
+        if let Some(previous) = tasks.remove(name) {
+            history = previous.history;
+            #[cfg(feature = "metrics")]
+            {
+                stats = previous.stats;
+            }
+        }
+
+        let generation = self.generations.fetch_add(1, Ordering::Relaxed);
+        tasks.insert(
+            name.to_string(),
+            TaskEntry {
+                generation,
+                body: task_body(f),
+                kind,
+                period,
+                boosted_from: None,
+                next_fire: Some(first_fire),
+                paused: false,
+                group: group.map(str::to_string),
+                history,
+                #[cfg(feature = "metrics")]
+                stats,
+            },
+        );
+        Some(generation)
+    }
+
+    /// Swaps the body of the task `name` for `f`, returns `false` if there is no such task
+    pub(crate) fn replace<F, R>(&self, name: &str, f: F) -> bool
+    where
+        F: 'static + Fn() -> R + Send + Sync,
+        R: TaskResult,
+    {
+        match self.tasks.lock().unwrap().get_mut(name) {
+            Some(entry) => {
+                entry.body = task_body(f);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Switches the repeating task `name` to `fast_period` under a new generation, bringing its next
+    /// run forward to at most `fast_period` after `now`. The original period is kept for `unboost`,
+    /// boosting an already boosted task keeps the period from before the first boost.
+    /// Returns the new generation and its first fire time, or `None` if there is no such repeating
+    /// task still scheduled.
+    pub(crate) fn boost(
+        &self,
+        name: &str,
+        fast_period: Duration,
+        now: Instant,
+    ) -> Option<(u64, Instant)> {
+        let mut tasks = self.tasks.lock().unwrap();
+        let entry = tasks.get_mut(name)?;
+        let (period, next_fire) = match (entry.kind, entry.period, entry.next_fire) {
+            (TaskKind::Repeating, Some(period), Some(next_fire)) => (period, next_fire),
+            _ => return None,
+        };
+
+        let first_fire = next_fire.min(now + fast_period);
+        entry.boosted_from = Some(entry.boosted_from.unwrap_or(period));
+        entry.period = Some(fast_period);
+        entry.next_fire = Some(first_fire);
+        entry.generation = self.generations.fetch_add(1, Ordering::Relaxed);
+        Some((entry.generation, first_fire))
+    }
+
+    /// Ends the boost that gave the task `name` its `generation`, restoring the original period
+    /// under a new generation. The next run comes a full original period after the last boosted
+    /// one. Returns the new generation, the original period and the first fire time, or `None` if
+    /// the task has since been replaced or boosted again.
+    pub(crate) fn unboost(&self, name: &str, generation: u64) -> Option<(u64, Duration, Instant)> {
+        let mut tasks = self.tasks.lock().unwrap();
+        let entry = tasks.get_mut(name)?;
+        if entry.generation != generation {
+            return None;
+        }
+
+        let (fast_period, next_fire) = (entry.period?, entry.next_fire?);
+        let period = entry.boosted_from.take()?;
+        let first_fire = next_fire
+            .checked_sub(fast_period)
+            .map_or(next_fire, |last_fire| last_fire + period);
+        entry.period = Some(period);
+        entry.next_fire = Some(first_fire);
+        entry.generation = self.generations.fetch_add(1, Ordering::Relaxed);
+        Some((entry.generation, period, first_fire))
+    }
+
+    /// Changes the period of the repeating task `name` under a new generation, ending any boost.
+    /// The next run comes a full new period after the last one. Returns the new generation and
+    /// the first fire time, or `None` if there is no such repeating task still scheduled.
+    pub(crate) fn set_period(
+        &self,
+        name: &str,
+        period: Duration,
+        now: Instant,
+    ) -> Option<(u64, Instant)> {
+        let mut tasks = self.tasks.lock().unwrap();
+        let entry = tasks.get_mut(name)?;
+        let (current, next_fire) = match (entry.kind, entry.period, entry.next_fire) {
+            (TaskKind::Repeating, Some(current), Some(next_fire)) => (current, next_fire),
+            _ => return None,
+        };
+
+        // Not before now, in case the last run was longer than the new period ago
+        let first_fire = next_fire
+            .checked_sub(current)
+            .map_or(next_fire, |last_fire| last_fire + period)
+            .max(now);
+        entry.boosted_from = None;
+        entry.period = Some(period);
+        entry.next_fire = Some(first_fire);
+        entry.generation = self.generations.fetch_add(1, Ordering::Relaxed);
+        Some((entry.generation, first_fire))
+    }
+
+    /// Pauses/resumes the task `name`, returns `false` if there is no such task
+    pub(crate) fn set_paused(&self, name: &str, paused: bool) -> bool {
+        match self.tasks.lock().unwrap().get_mut(name) {
+            Some(entry) => {
+                entry.paused = paused;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Pauses/resumes the tasks of `group` that are still scheduled, returns how many there are
+    pub(crate) fn set_group_paused(&self, group: &str, paused: bool) -> usize {
+        self.for_group(group, |entry| entry.paused = paused)
+    }
+
+    /// Unschedules the tasks of `group` that are still scheduled, returns how many there were.
+    /// Their loops exit on their next tick, as the generation they run under is gone; their
+    /// history is kept.
+    pub(crate) fn cancel_group(&self, group: &str) -> usize {
+        self.for_group(group, |entry| {
+            entry.next_fire = None;
+            entry.generation = self.generations.fetch_add(1, Ordering::Relaxed);
+        })
+    }
+
+    /// Calls `f` on every task of `group` that is still scheduled, returns how many there are
+    fn for_group<A: FnMut(&mut TaskEntry)>(&self, group: &str, mut f: A) -> usize {
+        let mut tasks = self.tasks.lock().unwrap();
+        let mut count = 0;
+        for entry in tasks.values_mut() {
+            if entry.next_fire.is_some() && entry.group.as_deref() == Some(group) {
+                f(entry);
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Runs the current body of the task `name` once, catching any panic, and records the run.
+    /// Does nothing but move the task on to its next fire time while it is paused.
+    /// Returns `false`, without running anything, if `generation` has since been replaced; the
+    /// loop scheduling it should then exit.
+    pub(crate) fn run(&self, name: &str, generation: u64) -> bool {
+        self.run_with(name, generation, || {})
+    }
+
+    /// Same as `run`, but calls `on_start` right before the body runs (not while paused)
+    pub(crate) fn run_with<S: FnOnce()>(&self, name: &str, generation: u64, on_start: S) -> bool {
+        let body = {
+            let mut tasks = self.tasks.lock().unwrap();
+            let entry = match tasks.get_mut(name) {
+                Some(entry) if entry.generation == generation => entry,
+                _ => return false,
+            };
+
+            entry.next_fire = match (entry.kind, entry.period, entry.next_fire) {
+                (TaskKind::Repeating, Some(period), Some(next_fire)) => Some(next_fire + period),
+                _ => None,
+            };
+            if entry.paused {
+                return true;
+            }
+            Arc::clone(&entry.body)
+        };
+
+        on_start();
+        let started_at = SystemTime::now();
+        let start = Instant::now();
+        let outcome = match catch_unwind(AssertUnwindSafe(|| body())) {
+            Ok(outcome) => outcome,
+            Err(payload) => TaskOutcome::Panic(panic_message(payload.as_ref())),
+        };
+
+        self.record(
+            name,
+            TaskRun {
+                started_at,
+                elapsed: start.elapsed(),
+                outcome,
+            },
+        );
+        self.first_run_done(name);
+        true
+    }
+
+    fn first_run_done(&self, name: &str) {
+        let mut first_runs = self.first_runs.lock().unwrap();
+        let first_run = first_runs.entry(name.to_string()).or_default();
+        if !first_run.done {
+            first_run.done = true;
+            first_run.notify.notify_waiters();
+        }
+    }
+
+    /// Waits until the task `name` has completed a run, returns at once if it already has
+    pub(crate) async fn wait_for_first_run(&self, name: &str) {
+        let notify = {
+            let mut first_runs = self.first_runs.lock().unwrap();
+            let first_run = first_runs.entry(name.to_string()).or_default();
+            if first_run.done {
+                return;
+            }
+            Arc::clone(&first_run.notify)
+        };
+
+        // `notify_waiters` wakes every `Notified` created before it, so check again once created
+        let notified = notify.notified();
+        if self.first_runs.lock().unwrap()[name].done {
+            return;
+        }
+        notified.await;
+    }
+
+    fn record(&self, name: &str, run: TaskRun) {
+        let mut tasks = self.tasks.lock().unwrap();
+        let entry = match tasks.get_mut(name) {
+            Some(entry) => entry,
+            None => return,
+        };
+
+        #[cfg(feature = "metrics")]
+        {
+            let stats = &mut entry.stats;
+            stats.elapsed += run.elapsed;
+            match run.outcome {
+                TaskOutcome::Ok => stats.ok += 1,
+                TaskOutcome::Err(_) => stats.err += 1,
+                TaskOutcome::Panic(_) => stats.panic += 1,
+            }
+        }
+
+        if self.history_size == 0 {
+            return;
+        }
+
+        let history = &mut entry.history;
+        if history.len() == self.history_size {
+            history.pop_front();
+        }
+        history.push_back(run);
+    }
+
+    /// When the task `name` is next due, `None` if there is no such task or it is not due again
+    pub(crate) fn next_fire(&self, name: &str) -> Option<Instant> {
+        self.tasks.lock().unwrap().get(name)?.next_fire
+    }
+
+    /// Named tasks that are still due to run, ordered by name
+    pub(crate) fn scheduled(&self) -> Vec<TaskInfo> {
+        let mut scheduled: Vec<_> = self
+            .tasks
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(name, entry)| {
+                entry.next_fire.map(|next_fire| TaskInfo {
+                    name: name.clone(),
+                    kind: entry.kind,
+                    period: entry.period,
+                    next_fire,
+                    paused: entry.paused,
+                    group: entry.group.clone(),
+                })
+            })
+            .collect();
+        scheduled.sort_by(|a, b| a.name.cmp(&b.name));
+        scheduled
+    }
+
+    /// Cumulative counters of every named task, ordered by name
+    #[cfg(feature = "metrics")]
+    pub(crate) fn stats(&self) -> Vec<(String, TaskStats)> {
+        let mut stats: Vec<_> = self
+            .tasks
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, entry)| (name.clone(), entry.stats.clone()))
+            .collect();
+        stats.sort_by(|a, b| a.0.cmp(&b.0));
+        stats
+    }
+
+    /// Recent runs of the task `name`, oldest first
+    pub(crate) fn history(&self, name: &str) -> Vec<TaskRun> {
+        self.tasks
+            .lock()
+            .unwrap()
+            .get(name)
+            .map(|entry| entry.history.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clockwork_config::DuplicateTaskPolicy::{REJECT, REPLACE};
+    use crate::clockwork_tasks::{TaskKind, TaskOutcome, TaskRegistry};
+    use tokio::time::{Duration, Instant};
+
+    #[test]
+    fn test_history_is_bounded() {
+        let registry = TaskRegistry::new(2);
+
+        let generation = registry
+            .register(
+                "task",
+                None,
+                || {},
+                TaskKind::OneShot,
+                None,
+                Instant::now(),
+                REPLACE,
+            )
+            .unwrap();
+        registry.run("task", generation);
+        assert!(registry.replace("task", || Err::<(), _>("failed")));
+        registry.run("task", generation);
+        assert!(registry.replace("task", || -> () { panic!("boom") }));
+        registry.run("task", generation);
+        assert!(!registry.replace("unknown", || {}));
+
+        let outcomes: Vec<_> = registry
+            .history("task")
+            .into_iter()
+            .map(|run| run.outcome)
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                TaskOutcome::Err("failed".to_string()),
+                TaskOutcome::Panic("boom".to_string())
+            ]
+        );
+        assert!(registry.history("unknown").is_empty());
+    }
+
+    #[test]
+    fn test_scheduled() {
+        let registry = TaskRegistry::new(2);
+        let now = Instant::now();
+        let period = Duration::from_secs(10);
+
+        let once = registry.register("once", None, || {}, TaskKind::OneShot, None, now, REPLACE);
+        let every = registry.register(
+            "every",
+            None,
+            || {},
+            TaskKind::Repeating,
+            Some(period),
+            now,
+            REPLACE,
+        );
+        assert!(registry.set_paused("every", true));
+        assert!(!registry.set_paused("unknown", true));
+
+        assert!(registry.run("once", once.unwrap()));
+        assert!(registry.run("every", every.unwrap()));
+
+        let scheduled = registry.scheduled();
+        assert_eq!(scheduled.len(), 1);
+        assert_eq!(scheduled[0].name, "every");
+        assert_eq!(scheduled[0].kind, TaskKind::Repeating);
+        assert_eq!(scheduled[0].next_fire, now + period);
+        assert!(scheduled[0].paused);
+        assert_eq!(registry.next_fire("every"), Some(now + period));
+        assert_eq!(registry.next_fire("once"), None);
+        assert_eq!(registry.next_fire("unknown"), None);
+        assert!(registry.history("every").is_empty());
+    }
+
+    #[test]
+    fn test_duplicate_names() {
+        let registry = TaskRegistry::new(2);
+        let now = Instant::now();
+        let period = Duration::from_secs(10);
+
+        let first = registry
+            .register(
+                "every",
+                None,
+                || {},
+                TaskKind::Repeating,
+                Some(period),
+                now,
+                REPLACE,
+            )
+            .unwrap();
+        assert!(registry.run("every", first));
+        assert!(registry
+            .register(
+                "every",
+                None,
+                || {},
+                TaskKind::Repeating,
+                Some(period),
+                now,
+                REJECT
+            )
+            .is_none());
+        assert!(registry.run("every", first));
+
+        // the replaced task's loop is told to exit, its history carries over
+        let second = registry
+            .register(
+                "every",
+                None,
+                || {},
+                TaskKind::Repeating,
+                Some(period),
+                now,
+                REPLACE,
+            )
+            .unwrap();
+        assert!(!registry.run("every", first));
+        assert!(registry.run("every", second));
+        assert_eq!(registry.history("every").len(), 2);
+
+        // a one-shot task that has run is no longer scheduled, its name is free again
+        let once = registry
+            .register("once", None, || {}, TaskKind::OneShot, None, now, REJECT)
+            .unwrap();
+        assert!(registry.run("once", once));
+        assert!(registry
+            .register("once", None, || {}, TaskKind::OneShot, None, now, REJECT)
+            .is_some());
+    }
+}
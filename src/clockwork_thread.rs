@@ -3,9 +3,18 @@ use super::Clockwork;
 use super::ClockworkConfig;
 use super::ClockworkHandle;
 use super::Runnable;
-use crate::Configurable;
+use crate::Lifecycle;
+use crate::{AsyncRunnable, Configurable};
 use std::any::Any;
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::sync::Arc;
 use std::thread::JoinHandle;
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tokio::sync::oneshot;
+use tokio::task::JoinError;
 
 // TODO: Should refactor this out, might be useful
 pub struct ClockworkRunnable<T: Runnable> {
@@ -22,9 +31,20 @@ impl<T: Runnable> ClockworkRunnable<T> {
         self.cw.handle()
     }
 
+    /// Panics if `try_setup` fails (after any retries, see `Clockwork::with_setup_retries`), after
+    /// calling `shutdown`.
+    /// If `setup` or `run` panics, `shutdown` is still called (best effort) and the runtime is
+    /// stopped with `StopReason::Panicked` before the panic carries on.
     pub fn start(&self) {
-        self.t.setup(self.handle());
-        self.cw.run(&self.t);
+        let setup = self
+            .cw
+            .isolate_panics(&self.t, || self.cw.try_setup(&self.t));
+        if let Err(e) = setup {
+            self.t.shutdown();
+            panic!("Failed to set up Runnable: {}!", e);
+        }
+
+        self.cw.isolate_panics(&self.t, || self.cw.run(&self.t));
         self.t.shutdown();
     }
 }
@@ -39,19 +59,47 @@ impl<T: Runnable + Configurable> ClockworkRunnable<T> {
     }
 }
 
+/// Status of a spawned `ClockworkThread`
+/// Running - the runtime has not been asked to stop
+/// Stopping - the runtime has been asked to stop, but the thread has not exited yet
+/// Stopped - the thread has exited without panicking
+/// Panicked - the `Runnable` panicked (the thread may still be unwinding)
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ClockworkStatus {
+    Running,
+    Stopping,
+    Stopped,
+    Panicked,
+}
+
 /// Wraps around a `JoinHandle` and a `ClockworkHandle`.
 /// Treat this like you would a `JoinHandle`.  
 /// This is returned by `spawn`.
 pub struct ClockworkJoinHandle {
     join_handle: JoinHandle<()>,
     cw_handle: ClockworkHandle,
+    /// Disconnects once the thread exits (the sender is dropped with the thread, even on panic)
+    exited: Receiver<()>,
+    /// Same as `exited`, for `join_async`
+    exited_async: oneshot::Receiver<()>,
+    /// Raised by the thread as soon as the `Runnable` panics
+    panicked: Arc<AtomicBool>,
 }
 
 impl ClockworkJoinHandle {
-    pub(crate) fn new(join_handle: JoinHandle<()>, cw_handle: ClockworkHandle) -> Self {
+    pub(crate) fn new(
+        join_handle: JoinHandle<()>,
+        cw_handle: ClockworkHandle,
+        exited: Receiver<()>,
+        exited_async: oneshot::Receiver<()>,
+        panicked: Arc<AtomicBool>,
+    ) -> Self {
         Self {
             join_handle,
             cw_handle,
+            exited,
+            exited_async,
+            panicked,
         }
     }
 
@@ -72,6 +120,52 @@ impl ClockworkJoinHandle {
         self.join_handle.join()
     }
 
+    /// Same as `join`, but awaits the thread finishing instead of blocking on it, so that it can be
+    /// used from async code without blocking the executor.
+    /// ```
+    /// use clockwork::{Clockwork, spawn_from_runnable, ClockworkHandle};
+    /// let rt = tokio::runtime::Runtime::new().unwrap();
+    /// let thread = spawn_from_runnable(Clockwork::default(), |_handle: ClockworkHandle| {});
+    /// thread.stop();
+    /// rt.block_on(thread.join_async()).unwrap();
+    /// ```
+    pub async fn join_async(self) -> Result<(), Box<dyn Any + Send + 'static>> {
+        // Nothing is ever sent, the sender is dropped as the thread exits (even on panic)
+        let _ = self.exited_async.await;
+        // Only waits for the thread to wind down from there on
+        let result = self.join_handle.join();
+        // The thread is gone, this may now hold the last reference to its runtime
+        let mut cw_handle = self.cw_handle;
+        cw_handle.release_runtime();
+        result
+    }
+
+    /// Waits up to `timeout` for the thread to finish.
+    /// Returns the result of `join` if the thread finished in time, otherwise gives the handle back
+    /// so the caller can retry or stop the thread.
+    /// ```
+    /// use clockwork::{Clockwork, spawn_from_runnable, ClockworkHandle};
+    /// use std::time::Duration;
+    /// let cw = Clockwork::default();
+    /// let thread = spawn_from_runnable(cw, |_handle: ClockworkHandle| {});
+    /// let thread = thread
+    ///     .join_timeout(Duration::from_millis(10))
+    ///     .err()
+    ///     .expect("thread should still be running");
+    /// thread.stop();
+    /// assert!(thread.join_timeout(Duration::from_secs(5)).is_ok());
+    /// ```
+    #[allow(clippy::result_large_err)]
+    pub fn join_timeout(
+        self,
+        timeout: Duration,
+    ) -> Result<Result<(), Box<dyn Any + Send + 'static>>, Self> {
+        match self.exited.recv_timeout(timeout) {
+            Err(RecvTimeoutError::Timeout) => Err(self),
+            Ok(()) | Err(RecvTimeoutError::Disconnected) => Ok(self.join()),
+        }
+    }
+
     /// Stops the `Clockwork` runtime and waits for the thread to finish, blocking.
     /// This does not guarantee that the thread will eventually join, user have to ensure that the
     /// Runnable will eventually terminate after being stopped.
@@ -100,6 +194,35 @@ impl ClockworkJoinHandle {
         self.cw_handle.stopped()
     }
 
+    /// The current status of the thread, unlike `joinable` this tells apart a thread that is still
+    /// winding down from one that has exited, and a clean exit from a panic.
+    /// ```
+    /// use clockwork::{spawn_from_runnable, Clockwork, ClockworkHandle, ClockworkStatus};
+    /// let thread = spawn_from_runnable(Clockwork::default(), |_handle: ClockworkHandle| {});
+    /// assert_eq!(thread.status(), ClockworkStatus::Running);
+    /// thread.stop();
+    /// while thread.status() == ClockworkStatus::Stopping {}
+    /// assert_eq!(thread.status(), ClockworkStatus::Stopped);
+    ///
+    /// let thread = spawn_from_runnable(Clockwork::default(), |_handle: ClockworkHandle| {
+    ///     panic!("setup failed")
+    /// });
+    /// while thread.status() == ClockworkStatus::Running {}
+    /// assert_eq!(thread.status(), ClockworkStatus::Panicked);
+    /// assert!(thread.join().is_err());
+    /// ```
+    pub fn status(&self) -> ClockworkStatus {
+        if self.panicked.load(Ordering::Acquire) {
+            ClockworkStatus::Panicked
+        } else if self.join_handle.is_finished() {
+            ClockworkStatus::Stopped
+        } else if self.cw_handle.lifecycle() != Lifecycle::Running {
+            ClockworkStatus::Stopping
+        } else {
+            ClockworkStatus::Running
+        }
+    }
+
     /// Tells the `Clockwork` run time to stop.
     /// Whether it will actually stop depends on the `Runnable`
     pub fn stop(&self) {
@@ -107,14 +230,69 @@ impl ClockworkJoinHandle {
     }
 }
 
-/// Implements Runnable for any closure that takes in a ClockworkHandle
+/// Wraps around a tokio `JoinHandle` and a `ClockworkHandle`.
+/// The async counterpart of `ClockworkJoinHandle`, returned by `spawn_on_runtime`.
+pub struct ClockworkAsyncJoinHandle {
+    join_handle: tokio::task::JoinHandle<()>,
+    cw_handle: ClockworkHandle,
+}
+
+impl ClockworkAsyncJoinHandle {
+    /// Waits for the `Runnable` to finish.
+    /// Use this if you can guarantee the `Runnable` will stop.
+    pub async fn join(self) -> Result<(), JoinError> {
+        self.join_handle.await
+    }
+
+    /// Stops the `Clockwork` handle and waits for the `Runnable` to finish.
+    /// This does not guarantee that the `Runnable` will eventually finish, user have to ensure
+    /// that it will eventually terminate after being stopped.
+    pub async fn stop_and_join(self) -> Result<(), JoinError> {
+        self.stop();
+        self.join().await
+    }
+
+    /// Determines if the handle is ready to be joined.
+    pub fn joinable(&self) -> bool {
+        self.cw_handle.stopped()
+    }
+
+    /// Tells the `Clockwork` handle to stop.
+    /// Whether it will actually stop depends on the `Runnable`
+    pub fn stop(&self) {
+        self.cw_handle.stop();
+    }
+
+    /// Returns a clone of the `ClockworkHandle` the `Runnable` was set up with
+    pub fn handle(&self) -> ClockworkHandle {
+        self.cw_handle.clone()
+    }
+}
+
+/// Implements Runnable (through `AsyncRunnable`) for any closure that takes in a ClockworkHandle
 /// Allows user to directly use an anonymous function as a parameter to `spawn`
-impl<T: Fn(ClockworkHandle) + Send + Sync + 'static> Runnable for T {
+impl<T: Fn(ClockworkHandle) + Send + Sync + 'static> AsyncRunnable for T {
     fn setup(&self, handle: ClockworkHandle) {
         self(handle);
     }
 }
 
+/// Runs the `Runnable` on the current thread using the given `Clockwork` runtime, without spawning.
+/// Calls setup, run and shutdown inline, blocking until the `Runnable` stops.
+///
+/// ```
+/// use clockwork::{run_runnable, Clockwork, ClockworkHandle};
+/// use tokio::time::Duration;
+/// let cw = Clockwork::default();
+/// run_runnable(cw, |handle: ClockworkHandle| {
+///     let stopper = handle.clone();
+///     handle.schedule_oneof_task(move || stopper.stop(), Duration::from_millis(10));
+/// });
+/// ```
+pub fn run_runnable<T: Runnable>(cw: Clockwork, t: T) {
+    ClockworkRunnable::new(cw, t).start();
+}
+
 /// Spawns a `ClockworkThread` using the given `Clockwork` runtime and `Runnable` instance.
 ///
 /// ```
@@ -133,6 +311,43 @@ where
     spawn(cw_runnable)
 }
 
+/// Same as `spawn_from_runnable`, but `on_panic` is invoked from the spawned thread as soon as the
+/// `Runnable` panics, so supervisors can react immediately rather than only at join time.
+/// The `Clockwork` runtime is marked as stopped and the panic is still reported on join.
+///
+/// ```
+/// use clockwork::{spawn_from_runnable_with_panic_handler, Clockwork, ClockworkHandle};
+/// use std::sync::atomic::{AtomicBool, Ordering};
+/// use std::sync::Arc;
+///
+/// let panicked = Arc::new(AtomicBool::new(false));
+/// let on_panic = {
+///     let panicked = panicked.clone();
+///     move |_payload: &(dyn std::any::Any + Send)| panicked.store(true, Ordering::SeqCst)
+/// };
+///
+/// let cw = Clockwork::default();
+/// let thread = spawn_from_runnable_with_panic_handler(
+///     cw,
+///     |_handle: ClockworkHandle| panic!("setup failed"),
+///     on_panic,
+/// );
+/// assert!(thread.join().is_err());
+/// assert!(panicked.load(Ordering::SeqCst));
+/// ```
+pub fn spawn_from_runnable_with_panic_handler<T, P>(
+    cw: Clockwork,
+    t: T,
+    on_panic: P,
+) -> ClockworkJoinHandle
+where
+    T: Runnable + Send + Sync + 'static,
+    P: FnOnce(&(dyn Any + Send)) + Send + 'static,
+{
+    let cw_runnable = ClockworkRunnable::new(cw, t);
+    spawn_with_panic_handler(cw_runnable, on_panic)
+}
+
 pub fn spawn_from_config<T>(cw_conf: ClockworkConfig, t: T::Config) -> ClockworkJoinHandle
 where
     T: Runnable + Configurable + Send + Sync + 'static,
@@ -141,13 +356,117 @@ where
     spawn(cw_runnable)
 }
 
+/// Spawns a `ClockworkThread` running the given `ClockworkRunnable`.
+/// If the `Runnable` panics, the `Clockwork` runtime is marked as stopped (so `joinable` becomes
+/// true) and the panic is reported when the thread is joined.
 pub fn spawn<T>(cw_runnable: ClockworkRunnable<T>) -> ClockworkJoinHandle
 where
     T: Runnable + Send + Sync + 'static,
 {
-    let runnable_handle = cw_runnable.handle();
-    ClockworkJoinHandle {
-        join_handle: std::thread::spawn(move || cw_runnable.start()),
-        cw_handle: runnable_handle,
+    spawn_with_panic_handler(cw_runnable, |_| {})
+}
+
+/// Same as `spawn`, but `on_panic` is invoked from the spawned thread as soon as the `Runnable`
+/// panics, with the panic payload.
+pub fn spawn_with_panic_handler<T, P>(
+    cw_runnable: ClockworkRunnable<T>,
+    on_panic: P,
+) -> ClockworkJoinHandle
+where
+    T: Runnable + Send + Sync + 'static,
+    P: FnOnce(&(dyn Any + Send)) + Send + 'static,
+{
+    let cw_handle = cw_runnable.handle();
+    spawn_thread(cw_handle, move || cw_runnable.start(), on_panic)
+}
+
+/// Runs `body` on a new thread, on behalf of the `Clockwork` runtime behind `cw_handle`.
+/// If `body` panics, the runtime is marked as stopped and `on_panic` is invoked from the thread.
+pub(crate) fn spawn_thread<B, P>(
+    cw_handle: ClockworkHandle,
+    body: B,
+    on_panic: P,
+) -> ClockworkJoinHandle
+where
+    B: FnOnce() + Send + 'static,
+    P: FnOnce(&(dyn Any + Send)) + Send + 'static,
+{
+    let (exited_tx, exited_rx) = channel();
+    let (exited_async_tx, exited_async_rx) = oneshot::channel();
+    let panicked = Arc::new(AtomicBool::new(false));
+    let join_handle = {
+        let cw_handle = cw_handle.clone();
+        let panicked = Arc::clone(&panicked);
+        std::thread::spawn(move || {
+            let _exited = exited_tx;
+            let _exited_async = exited_async_tx;
+            if let Err(payload) = catch_unwind(AssertUnwindSafe(body)) {
+                panicked.store(true, Ordering::Release);
+                cw_handle.stop();
+                on_panic(payload.as_ref());
+                resume_unwind(payload);
+            }
+        })
+    };
+
+    ClockworkJoinHandle::new(join_handle, cw_handle, exited_rx, exited_async_rx, panicked)
+}
+
+/// Runs the `Runnable` on an existing tokio runtime instead of creating a new runtime and thread.
+/// Setup, run and shutdown all happen inside a task spawned onto `rt`.
+/// If `setup` or `run` panics, `shutdown` is still called (best effort) and the panic is reported
+/// when the task is joined.
+///
+/// ```
+/// use clockwork::{spawn_on_runtime, ClockworkHandle};
+/// let rt = tokio::runtime::Runtime::new().unwrap();
+/// let task = spawn_on_runtime(rt.handle().clone(), |_handle: ClockworkHandle| {
+///     // set up the tasks (scheduled tasks/etc)
+/// });
+/// rt.block_on(task.stop_and_join()).unwrap();
+/// ```
+pub fn spawn_on_runtime<T>(rt: Handle, t: T) -> ClockworkAsyncJoinHandle
+where
+    T: Runnable + Send + Sync + 'static,
+{
+    let cw_handle = ClockworkHandle::from_tokio_handle(rt.clone());
+    let handle = cw_handle.clone();
+    let join_handle = rt.clone().spawn(async move {
+        match catch_unwind(AssertUnwindSafe(|| t.try_setup(handle.clone()))) {
+            Err(payload) => {
+                handle.runnable_panicked(&t, payload.as_ref());
+                resume_unwind(payload);
+            }
+            Ok(Err(e)) => {
+                t.shutdown();
+                panic!("Failed to set up Runnable: {}!", e);
+            }
+            Ok(Ok(())) => {}
+        }
+
+        // Run as a task of its own, so that a panic in `run` surfaces here rather than unwinding
+        // past `shutdown`
+        let t = Arc::new(t);
+        let run = {
+            let t = Arc::clone(&t);
+            let handle = handle.clone();
+            rt.spawn(async move {
+                t.on_ready(handle.clone());
+                t.run(handle.clone(), handle.stop_signal()).await
+            })
+        };
+        if let Err(e) = run.await {
+            if e.is_panic() {
+                let payload = e.into_panic();
+                handle.runnable_panicked(t.as_ref(), payload.as_ref());
+                resume_unwind(payload);
+            }
+        }
+        t.shutdown();
+    });
+
+    ClockworkAsyncJoinHandle {
+        join_handle,
+        cw_handle,
     }
 }
@@ -0,0 +1,130 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+/// Beaten by a task on the runtime to record that it is making progress
+#[derive(Clone)]
+pub(crate) struct Heartbeat {
+    last: Weak<Mutex<Instant>>,
+}
+
+impl Heartbeat {
+    /// Returns `false` once the watchdog is gone, for the beating task to exit
+    pub(crate) fn beat(&self) -> bool {
+        match self.last.upgrade() {
+            Some(last) => {
+                *last.lock().unwrap() = Instant::now();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Watches the runtime from a thread of its own, see `Clockwork::with_stall_watchdog`.
+/// The runtime is stalled once its `Heartbeat` is older than the threshold, e.g. because a task
+/// blocks the only thread of a current-thread runtime. Stops watching once dropped.
+pub(crate) struct StallWatchdog {
+    last: Arc<Mutex<Instant>>,
+    #[cfg_attr(not(test), allow(dead_code))]
+    stalls: Arc<AtomicUsize>,
+    /// Dropped along with the watchdog, which disconnects the thread's receiver
+    _stop: Sender<()>,
+}
+
+impl StallWatchdog {
+    pub(crate) fn start(threshold: Duration, abort: bool) -> Self {
+        let last = Arc::new(Mutex::new(Instant::now()));
+        let stalls = Arc::new(AtomicUsize::new(0));
+        let (stop, stopped) = channel::<()>();
+        let (watched, counted) = (Arc::downgrade(&last), stalls.clone());
+        std::thread::spawn(move || {
+            let mut stalled = false;
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(threshold / 4) {
+                let age = match watched.upgrade() {
+                    Some(last) => last.lock().unwrap().elapsed(),
+                    None => return,
+                };
+                if age <= threshold {
+                    stalled = false;
+                    continue;
+                }
+                // Only reported once per stall
+                if !stalled {
+                    stalled = true;
+                    counted.fetch_add(1, Ordering::Relaxed);
+                    report_stall(age, abort);
+                }
+            }
+        });
+
+        Self {
+            last,
+            stalls,
+            _stop: stop,
+        }
+    }
+
+    /// The heartbeat to beat from the runtime
+    pub(crate) fn heartbeat(&self) -> Heartbeat {
+        Heartbeat {
+            last: Arc::downgrade(&self.last),
+        }
+    }
+
+    /// Number of stalls reported so far
+    #[cfg(test)]
+    pub(crate) fn stalls(&self) -> usize {
+        self.stalls.load(Ordering::Relaxed)
+    }
+}
+
+fn report_stall(stalled_for: Duration, abort: bool) {
+    #[cfg(feature = "logging")]
+    tracing::error!(
+        ?stalled_for,
+        "Runtime stalled, a task is likely blocking its thread (e.g. `std::thread::sleep` or a \
+         blocking lock), no other task nor `stop` can make progress"
+    );
+    #[cfg(not(feature = "logging"))]
+    eprintln!(
+        "Warning: runtime stalled for {:?}, a task is likely blocking its thread",
+        stalled_for
+    );
+
+    if abort {
+        // The logger may not get to write the line above out before the process is gone
+        eprintln!("Error: runtime stalled for {:?}, aborting", stalled_for);
+        std::process::abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clockwork_watchdog::StallWatchdog;
+    use std::time::Duration;
+
+    #[test]
+    fn test_stall_watchdog() {
+        let watchdog = StallWatchdog::start(Duration::from_millis(100), false);
+        let heartbeat = watchdog.heartbeat();
+        for _ in 0..10 {
+            assert!(heartbeat.beat());
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert_eq!(watchdog.stalls(), 0);
+
+        // reported once for the whole stall, then again once it stalls after recovering
+        std::thread::sleep(Duration::from_millis(300));
+        assert_eq!(watchdog.stalls(), 1);
+        heartbeat.beat();
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(watchdog.stalls(), 1);
+        std::thread::sleep(Duration::from_millis(200));
+        assert_eq!(watchdog.stalls(), 2);
+
+        drop(watchdog);
+        assert!(!heartbeat.beat());
+    }
+}
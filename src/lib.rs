@@ -1,48 +1,222 @@
 mod clockwork;
 mod clockwork_app;
+mod clockwork_boxed;
+mod clockwork_circuit;
+mod clockwork_clock;
+mod clockwork_composite;
 mod clockwork_config;
+mod clockwork_crontab;
+mod clockwork_duration;
+mod clockwork_error;
+mod clockwork_flags;
+mod clockwork_jobs;
+mod clockwork_keys;
+mod clockwork_lifecycle;
+mod clockwork_priority;
+mod clockwork_readiness;
+mod clockwork_state;
+mod clockwork_stop_after;
+mod clockwork_tasks;
 mod clockwork_thread;
+mod clockwork_watchdog;
 
 #[cfg(feature = "logging")]
 mod clockwork_logger;
 
+#[cfg(feature = "otel")]
+mod clockwork_otel;
+
+#[cfg(feature = "tz")]
+mod clockwork_calendar;
+
+#[cfg(feature = "metrics")]
+mod clockwork_metrics;
+
+#[cfg(feature = "manual-exec")]
+mod clockwork_manual;
+
 pub type Clockwork = clockwork::Clockwork;
 pub type ClockworkHandle = clockwork::ClockworkHandle;
+pub type StopSignal = clockwork::StopSignal;
 pub type ClockworkConfig = clockwork_config::ClockworkConfig;
 pub type ClockworkApp<T> = clockwork_app::ClockworkApp<T>;
+pub type ClockworkAppConfig<T> = clockwork_app::ClockworkAppConfig<T>;
+
+pub use clockwork_app::ConfigSource;
+pub use clockwork_boxed::BoxedRunnable;
+pub use clockwork_circuit::{CircuitBreaker, CircuitError, CircuitState};
+pub use clockwork_clock::{Clock, ManualClock, TokioClock};
+pub use clockwork_composite::CompositeRunnable;
+pub use clockwork_config::{DuplicateTaskPolicy, TaskLimitPolicy};
+pub use clockwork_duration::{Duration, ParseDurationError};
+pub use clockwork_error::ClockworkError;
+pub use clockwork_flags::FlagStore;
+pub use clockwork_jobs::{
+    schedule_jobs, JobConfig, JobDispatch, ScheduledJobsApp, ScheduledJobsConfig,
+};
+pub use clockwork_lifecycle::{Lifecycle, ShutdownReport, StopReason};
+pub use clockwork_priority::TaskPriority;
+pub use clockwork_readiness::{ReadinessCheck, WaitForConfig};
+pub use clockwork_stop_after::{StopAfter, StopAfterIterations};
+pub use clockwork_tasks::{
+    SpawnError, TaskHandle, TaskInfo, TaskKind, TaskOutcome, TaskResult, TaskRun,
+};
+pub use clockwork_thread::{
+    run_runnable, spawn_from_runnable, spawn_from_runnable_with_panic_handler, spawn_on_runtime,
+};
+pub use clockwork_thread::{ClockworkAsyncJoinHandle, ClockworkJoinHandle, ClockworkStatus};
 
-pub use clockwork_thread::spawn_from_runnable;
+#[cfg(feature = "logging")]
+pub use clockwork_logger::{LoggerConfig, LoggerLevel};
+#[cfg(feature = "manual-exec")]
+pub use clockwork_manual::ManualClockwork;
+#[cfg(feature = "metrics")]
+pub use clockwork_metrics::{MetricsConfig, RuntimeMetrics};
+
+#[cfg(feature = "derive")]
+pub use clockwork_derive::Configurable;
 use serde::de::DeserializeOwned;
+use std::error::Error;
 use std::pin::Pin;
-use tokio::time::{sleep, Duration};
 
 /// A data structure that is compatible with the `Clockwork` framework.
 /// This trait is required for `ClockworkApp` and `ClockworkThread`.
 /// The data structure needs to define how the app should run.
-/// It needs to provide the following three functions: `setup`, `shutdown` and `run`.
-/// Only `setup` is mandatory, the others have default implementations.
+/// It can provide the following functions: `setup` (or `try_setup`), `on_ready`, `shutdown` and
+/// `run`. All of them have default implementations.
 pub trait Runnable {
     /// Defines how to set up the application. A user can spawn tasks here.
-    fn setup(&self, handle: ClockworkHandle);
+    /// The default behaviour is to do nothing.
+    fn setup(&self, _handle: ClockworkHandle) {}
+
+    /// Fallible version of `setup`, for apps whose initialization can fail (e.g. binding a socket
+    /// or opening a file). A failed setup aborts the run: `shutdown` is called straight away and
+    /// `ClockworkApp::start` returns the error.
+    /// The default behaviour is to call `setup`.
+    fn try_setup(&self, handle: ClockworkHandle) -> Result<(), Box<dyn Error>> {
+        self.setup(handle);
+        Ok(())
+    }
+
+    /// Called once the runtime is up and `setup` has completed, right before `run` begins.
+    /// A well-defined readiness point, e.g. for emitting a "ready" log or notifying a process
+    /// manager. The default behaviour is to do nothing.
+    fn on_ready(&self, _handle: ClockworkHandle) {}
 
     /// Defines how to tear down the application. The default behaviour is to do nothing.
     /// Useful for logging, etc.
     fn shutdown(&self) {}
 
     /// Defines how the application should be run. i.e. stopping conditions, etc.
-    /// The default behaviour is to loop and yield infinitely until the handle is stopped.
-    /// FIXME: there maybe a better solution instead of using this while loop
-    /// FIXME: also, would be good if we can hide away the state of the handle
-    ///        user should not need to know if it is still running or have been stopped
+    /// The default behaviour is to wait until the handle is stopped.
+    /// `stop` resolves once the runtime is asked to stop, which lets a long-running `run` tell
+    /// apart being asked to stop from finishing naturally, and cancel in-flight work at a point of
+    /// its choosing. The recommended pattern is to `select!` against it:
+    /// ```
+    /// use clockwork::{ClockworkHandle, Runnable, StopSignal};
+    /// use std::pin::Pin;
+    /// use std::future::Future;
+    /// use tokio::time::{sleep, Duration};
+    ///
+    /// struct Worker;
+    ///
+    /// impl Runnable for Worker {
+    ///     fn setup(&self, _handle: ClockworkHandle) {}
+    ///
+    ///     fn run<'a>(
+    ///         &'a self,
+    ///         _handle: ClockworkHandle,
+    ///         mut stop: StopSignal,
+    ///     ) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>> {
+    ///         Box::pin(async move {
+    ///             loop {
+    ///                 tokio::select! {
+    ///                     _ = &mut stop => break, // asked to stop, clean up in-flight work
+    ///                     _ = sleep(Duration::from_secs(1)) => {} // a unit of work
+    ///                 }
+    ///             }
+    ///         })
+    ///     }
+    /// }
+    /// ```
+    fn run<'a>(
+        &'a self,
+        _handle: ClockworkHandle,
+        stop: StopSignal,
+    ) -> Pin<Box<dyn core::future::Future<Output = ()> + Send + 'a>> {
+        Box::pin(stop)
+    }
+}
+
+/// `Runnable` with a plain `async fn run`, for apps that would rather not box and pin the future
+/// by hand. Every `AsyncRunnable` is a `Runnable`, the other functions are the same as there.
+/// ```
+/// use clockwork::{AsyncRunnable, ClockworkHandle, StopSignal};
+/// use tokio::time::{sleep, Duration};
+///
+/// struct Worker;
+///
+/// impl AsyncRunnable for Worker {
+///     async fn run(&self, _handle: ClockworkHandle, mut stop: StopSignal) {
+///         loop {
+///             tokio::select! {
+///                 _ = &mut stop => break, // asked to stop, clean up in-flight work
+///                 _ = sleep(Duration::from_secs(1)) => {} // a unit of work
+///             }
+///         }
+///     }
+/// }
+/// ```
+pub trait AsyncRunnable {
+    /// See `Runnable::setup`
+    fn setup(&self, _handle: ClockworkHandle) {}
+
+    /// See `Runnable::try_setup`
+    fn try_setup(&self, handle: ClockworkHandle) -> Result<(), Box<dyn Error>> {
+        self.setup(handle);
+        Ok(())
+    }
+
+    /// See `Runnable::on_ready`
+    fn on_ready(&self, _handle: ClockworkHandle) {}
+
+    /// See `Runnable::shutdown`
+    fn shutdown(&self) {}
+
+    /// See `Runnable::run`, can be implemented as an `async fn`.
+    /// The default behaviour is to wait until the handle is stopped.
+    fn run(
+        &self,
+        _handle: ClockworkHandle,
+        stop: StopSignal,
+    ) -> impl core::future::Future<Output = ()> + Send {
+        stop
+    }
+}
+
+impl<T: AsyncRunnable + Sync> Runnable for T {
+    fn setup(&self, handle: ClockworkHandle) {
+        AsyncRunnable::setup(self, handle)
+    }
+
+    fn try_setup(&self, handle: ClockworkHandle) -> Result<(), Box<dyn Error>> {
+        AsyncRunnable::try_setup(self, handle)
+    }
+
+    fn on_ready(&self, handle: ClockworkHandle) {
+        AsyncRunnable::on_ready(self, handle)
+    }
+
+    fn shutdown(&self) {
+        AsyncRunnable::shutdown(self)
+    }
+
     fn run<'a>(
         &'a self,
         handle: ClockworkHandle,
+        stop: StopSignal,
     ) -> Pin<Box<dyn core::future::Future<Output = ()> + Send + 'a>> {
-        Box::pin(async move {
-            while !handle.stopped() {
-                sleep(Duration::from_secs(0)).await
-            }
-        })
+        Box::pin(AsyncRunnable::run(self, handle, stop))
     }
 }
 